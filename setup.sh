@@ -5,17 +5,24 @@ ROOT_DIR="$(cd "$(dirname "${BASH_SOURCE[0]}")" && pwd)"
 
 cd "$ROOT_DIR"
 
-echo "[1/5] formatting Rust code"
+echo "[1/6] formatting Rust code"
 cargo fmt --all
 
-echo "[2/5] building workspace"
+echo "[2/6] building workspace"
 cargo build --workspace
 
-echo "[3/5] building Windows debug test executables"
+echo "[3/6] checking the 32-bit (i686) build"
+if rustup target list --installed 2>/dev/null | grep -qx i686-unknown-linux-gnu; then
+  cargo check --workspace --target i686-unknown-linux-gnu
+else
+  echo "skipped: rustup target i686-unknown-linux-gnu is not installed"
+fi
+
+echo "[4/6] building Windows debug test executables"
 "$ROOT_DIR/tests/build_exes.sh"
 
-echo "[4/5] running debug compatibility tests"
+echo "[5/6] running debug compatibility tests"
 "$ROOT_DIR/tests/test.sh"
 
-echo "[5/5] done"
+echo "[6/6] done"
 echo "Use: ./target/debug/winrun -d tests/winapi/<sample>.exe"
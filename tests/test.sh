@@ -9,10 +9,13 @@ if [[ ! -x "$WINRUN" ]]; then
   exit 1
 fi
 
-mapfile -t EXES < <(find "$ROOT_DIR/tests" -type f -name '*.exe' | sort)
+FIXTURES="$ROOT_DIR/tests/fixtures"
+trap 'rm -f "$FIXTURES"/*.waygate.plan' EXIT
+
+mapfile -t EXES < <(find "$ROOT_DIR/tests/winapi" -type f -name '*.exe' | sort)
 
 if [[ ${#EXES[@]} -eq 0 ]]; then
-  echo "error: no .exe files found under $ROOT_DIR/tests" >&2
+  echo "error: no .exe files found under $ROOT_DIR/tests/winapi. run: tests/build_exes.sh" >&2
   exit 1
 fi
 
@@ -23,4 +26,38 @@ for exe in "${EXES[@]}"; do
   "$WINRUN" -d "$exe"
 done
 
+# expect FIXTURE EXIT_CODE [PATTERN...]: runs `winrun -d` on a PE fixture and
+# checks its exit code and that every pattern appears in the output.
+expect() {
+  local exe="$FIXTURES/$1" code="$2" output status=0
+  shift 2
+  echo "---"
+  echo "[fixture] $exe (expect exit $code)"
+  output="$("$WINRUN" -d "$exe" 2>&1)" || status=$?
+  if [[ $status -ne $code ]]; then
+    echo "$output"
+    echo "error: $exe exited with $status, expected $code" >&2
+    exit 1
+  fi
+  for pattern in "$@"; do
+    if ! grep -qF -- "$pattern" <<<"$output"; then
+      echo "$output"
+      echo "error: $exe output lacks: $pattern" >&2
+      exit 1
+    fi
+  done
+}
+
+expect hello.exe 7
+expect ord.exe 52 "imphash: c2e1a540f581cedfb691c18b0710bbb6 (4 import(s))"
+expect arm64.exe 3 "unsupported architecture: ARM64 image"
+expect native.exe 4 "unsupported subsystem: native"
+# Packed from upx-app.exe; the stub exits 85 instead of decompressing, so the
+# unpacked imports come from winrun's own unpacker.
+for packed in upx-nrv2b.exe upx-lzma.exe; do
+  expect "$packed" 85 \
+    "unpacked: 6 import(s) from 3 DLL(s), call filter 0x26 reversed" \
+    "win32api: found 6 symbol(s) in 3 module(s)"
+done
+
 echo "all tests passed"
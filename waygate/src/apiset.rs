@@ -0,0 +1,84 @@
+/// Built-in API set schema: contract name (version suffix stripped) to the
+/// module implementing it. Keys ending in `-` cover a whole family.
+/// kernelbase-hosted contracts map to kernel32, which waygate serves both
+/// names from.
+const API_SETS: &[(&str, &str)] = &[
+    ("api-ms-win-core-console", "kernel32"),
+    ("api-ms-win-core-datetime", "kernel32"),
+    ("api-ms-win-core-debug", "kernel32"),
+    ("api-ms-win-core-errorhandling", "kernel32"),
+    ("api-ms-win-core-fibers", "kernel32"),
+    ("api-ms-win-core-file", "kernel32"),
+    ("api-ms-win-core-handle", "kernel32"),
+    ("api-ms-win-core-heap", "kernel32"),
+    ("api-ms-win-core-interlocked", "kernel32"),
+    ("api-ms-win-core-io", "kernel32"),
+    ("api-ms-win-core-libraryloader", "kernel32"),
+    ("api-ms-win-core-localization", "kernel32"),
+    ("api-ms-win-core-memory", "kernel32"),
+    ("api-ms-win-core-namedpipe", "kernel32"),
+    ("api-ms-win-core-processenvironment", "kernel32"),
+    ("api-ms-win-core-processthreads", "kernel32"),
+    ("api-ms-win-core-profile", "kernel32"),
+    ("api-ms-win-core-realtime", "kernel32"),
+    ("api-ms-win-core-string", "kernel32"),
+    ("api-ms-win-core-synch", "kernel32"),
+    ("api-ms-win-core-sysinfo", "kernel32"),
+    ("api-ms-win-core-timezone", "kernel32"),
+    ("api-ms-win-core-util", "kernel32"),
+    ("api-ms-win-core-rtlsupport", "ntdll"),
+    ("api-ms-win-core-registry", "advapi32"),
+    ("api-ms-win-eventing-", "advapi32"),
+    ("api-ms-win-security-", "advapi32"),
+    ("api-ms-win-crt-", "ucrtbase"),
+    ("api-ms-win-shcore-", "shcore"),
+    ("ext-ms-win-gdi-", "gdi32"),
+    ("ext-ms-win-ntuser-", "user32"),
+    ("ext-ms-win-rtcore-ntuser-", "user32"),
+];
+
+/// Host module for an API set contract DLL such as
+/// `api-ms-win-core-synch-l1-2-0.dll`, or `None` for ordinary DLL names.
+pub fn api_set_host(dll: &str) -> Option<&'static str> {
+    let contract = contract_name(dll)?;
+    API_SETS
+        .iter()
+        .find(|(key, _)| {
+            if key.ends_with('-') {
+                contract.starts_with(key)
+            } else {
+                contract == *key
+            }
+        })
+        .map(|(_, host)| *host)
+}
+
+/// True for `api-ms-*` / `ext-ms-*` names, known to the schema or not.
+pub fn is_api_set(dll: &str) -> bool {
+    contract_name(dll).is_some()
+}
+
+/// Lowercase contract name without `.dll` and the trailing `-lN-N-N`
+/// version, which the schema ignores like the Windows loader does.
+fn contract_name(dll: &str) -> Option<String> {
+    let dll = dll.to_ascii_lowercase();
+    let stem = dll.strip_suffix(".dll").unwrap_or(&dll);
+    if !stem.starts_with("api-") && !stem.starts_with("ext-") {
+        return None;
+    }
+    let parts: Vec<&str> = stem.split('-').collect();
+    let versioned = parts.len() > 3 && {
+        let tail = &parts[parts.len() - 3..];
+        tail[0].strip_prefix('l').is_some_and(is_number) && is_number(tail[1]) && is_number(tail[2])
+    };
+    let keep = if versioned {
+        parts.len() - 3
+    } else {
+        parts.len()
+    };
+    Some(parts[..keep].join("-"))
+}
+
+fn is_number(text: &str) -> bool {
+    !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit())
+}
@@ -0,0 +1,464 @@
+use crate::symbols::backtrace;
+use crate::thunk::{DelayLoad, SlotState};
+use crate::unwind::Frame;
+use crate::{invoke, module_handle, resolve, Arg, ArgKind, Export};
+
+/// Longest string argument decoded before giving up on a missing NUL.
+const MAX_STRING_ARG: usize = 32 * 1024;
+
+/// Turns raw register/stack slots into typed arguments using the export's
+/// parameter list. `raw` holds one zero-extended slot per parameter.
+pub(crate) fn decode_args(export: &Export, raw: &[u64]) -> Vec<Arg> {
+    export
+        .params
+        .iter()
+        .zip(raw)
+        .map(|(param, &value)| match param.kind {
+            ArgKind::Int => Arg::Int(value as u32 as i32 as i64),
+            ArgKind::Size | ArgKind::Ptr => Arg::Ptr(value),
+            // Values below 64K are integer atoms/ordinals (MAKEINTRESOURCE,
+            // GetProcAddress by ordinal), never real pointers.
+            ArgKind::Str | ArgKind::WStr if value < 0x1_0000 => Arg::Ptr(value),
+            // SAFETY: the caller passed this as a C string pointer.
+            ArgKind::Str => match unsafe { read_c_string(value as *const u8) } {
+                Some(text) => Arg::Str {
+                    address: value,
+                    text,
+                },
+                None => Arg::Ptr(value),
+            },
+            // SAFETY: the caller passed this as a wide string pointer.
+            ArgKind::WStr => match unsafe { read_wide_string(value as *const u16) } {
+                Some(text) => Arg::Str {
+                    address: value,
+                    text,
+                },
+                None => Arg::Ptr(value),
+            },
+        })
+        .collect()
+}
+
+unsafe fn read_c_string(ptr: *const u8) -> Option<String> {
+    let mut bytes = Vec::new();
+    for i in 0..MAX_STRING_ARG {
+        let b = ptr.add(i).read();
+        if b == 0 {
+            return Some(String::from_utf8_lossy(&bytes).into_owned());
+        }
+        bytes.push(b);
+    }
+    None
+}
+
+unsafe fn read_wide_string(ptr: *const u16) -> Option<String> {
+    let mut units = Vec::new();
+    for i in 0..MAX_STRING_ARG {
+        let unit = ptr.add(i).read_unaligned();
+        if unit == 0 {
+            return Some(String::from_utf16_lossy(&units));
+        }
+        units.push(unit);
+    }
+    None
+}
+
+/// Prints `head` and `tail` around ` from app.exe!WinMain+0x42 (main.c:17)`
+/// naming the caller, then one `called from` line per frame further out.
+/// Calls from outside the loaded images print `head` and `tail` alone.
+fn eprint_with_backtrace(head: &str, caller: Frame, tail: &str) {
+    // SAFETY: bridges only hand over the frame of the call being served.
+    let frames = unsafe { backtrace(caller) };
+    match frames.split_first() {
+        Some((first, outer)) => {
+            eprintln!("{head} from {first}{tail}");
+            for frame in outer {
+                eprintln!("        called from {frame}");
+            }
+        }
+        None => eprintln!("{head}{tail}"),
+    }
+}
+
+/// Decodes the slot's arguments, runs the implementation and returns the
+/// value for RAX/EAX. `raw` reads the n-th argument slot of the call and
+/// `caller` is the calling frame, as it will be once the call returns.
+fn slot_call(state: &SlotState, caller: Frame, raw: impl Fn(usize) -> u64) -> u64 {
+    let slot = &state.slot;
+    let Some(export) = resolve(&slot.module, &slot.symbol) else {
+        missing_import(state, caller);
+    };
+    if let (Some(resolved), Some(delay)) = (state.resolved_entry, &slot.delay) {
+        resolve_delay_load(state, delay, resolved);
+    }
+    let raw: Vec<u64> = (0..export.params.len()).map(raw).collect();
+    let args = decode_args(export, &raw);
+    match invoke(export.name, &args) {
+        Ok(call) => {
+            if state.trace {
+                eprint_with_backtrace(
+                    &format!("  [call] {}!{}", slot.module, slot.symbol),
+                    caller,
+                    &format!(" -> {}", call.message),
+                );
+            }
+            call.ret
+        }
+        Err(err) => {
+            if state.trace {
+                eprint_with_backtrace(
+                    &format!("  [call] {}!{}", slot.module, slot.symbol),
+                    caller,
+                    &format!(" -> {err}"),
+                );
+            }
+            0
+        }
+    }
+}
+
+/// Delay-load helper: records the module handle and points the IAT cell at
+/// the direct stub so only the first call pays for resolution.
+fn resolve_delay_load(state: &SlotState, delay: &DelayLoad, resolved: u64) {
+    let slot = &state.slot;
+    // SAFETY: the loader only hands out cells inside writable image sections.
+    unsafe {
+        if delay.module_handle_cell != 0 {
+            let cell = delay.module_handle_cell as usize as *mut usize;
+            if cell.read_unaligned() == 0 {
+                cell.write_unaligned(module_handle(&slot.module) as usize);
+            }
+        }
+        (delay.iat_cell as usize as *mut usize).write_unaligned(resolved as usize);
+    }
+    if state.trace {
+        eprintln!(
+            "  [delay-load] {}!{} resolved through waygate",
+            slot.module, slot.symbol
+        );
+    }
+}
+
+fn missing_import(state: &SlotState, caller: Frame) -> ! {
+    eprint_with_backtrace(
+        &format!(
+            "waygate: unresolved import {}!{} was called",
+            state.slot.module, state.slot.symbol
+        ),
+        caller,
+        "",
+    );
+    std::process::exit(1);
+}
+
+/// Runs a loaded image's entry point on the current thread and returns the
+/// value it returns (programs usually leave through `ExitProcess` instead).
+///
+/// # Safety
+///
+/// `address` must point at mapped, executable machine code for this host
+/// with all of its imports bound.
+pub unsafe fn call_entry_point(address: u64) -> Result<u32, String> {
+    arch::call_entry_point(address)
+}
+
+/// Calls a DLL entry point as `DllMain(base, reason, NULL)` and reports
+/// whether it returned TRUE.
+///
+/// # Safety
+///
+/// Same requirements as [`call_entry_point`], for a DLL image at `base`.
+pub(crate) unsafe fn call_dll_main(entry: u64, base: u64, reason: u32) -> Result<bool, String> {
+    arch::call_dll_main(entry, base, reason)
+}
+
+/// Calls a thread start routine as `ThreadProc(param)` and returns its
+/// exit code.
+///
+/// # Safety
+///
+/// Same requirements as [`call_entry_point`].
+pub(crate) unsafe fn call_thread_start(start: u64, param: u64) -> Result<u32, String> {
+    arch::call_thread_start(start, param)
+}
+
+pub(crate) use arch::{encode_stub, import_entry, missing_import_entry, STUB_SIZE};
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use super::{missing_import, slot_call, SlotState};
+    use crate::unwind::Frame;
+
+    /// Bytes reserved per generated stub; keeps every entry 16-byte aligned.
+    pub const STUB_SIZE: usize = 32;
+
+    // Win64 (ms_abi) entry. Callers leave 32 bytes of shadow space above the
+    // return address, so spilling RCX/RDX/R8/R9 there makes every argument
+    // contiguous with the stack-passed ones starting at [rsp + 40]. RDI, RSI
+    // and XMM6-15 are callee-saved on Windows but not in the SysV ABI the
+    // Rust side uses, so they are preserved here. The caller cleans up.
+    std::arch::global_asm!(
+        ".globl waygate_import_entry",
+        "waygate_import_entry:",
+        "push rbp",
+        "mov rbp, rsp",
+        "mov [rbp + 16], rcx",
+        "mov [rbp + 24], rdx",
+        "mov [rbp + 32], r8",
+        "mov [rbp + 40], r9",
+        "push rdi",
+        "push rsi",
+        "sub rsp, 160",
+        "movdqu [rsp], xmm6",
+        "movdqu [rsp + 16], xmm7",
+        "movdqu [rsp + 32], xmm8",
+        "movdqu [rsp + 48], xmm9",
+        "movdqu [rsp + 64], xmm10",
+        "movdqu [rsp + 80], xmm11",
+        "movdqu [rsp + 96], xmm12",
+        "movdqu [rsp + 112], xmm13",
+        "movdqu [rsp + 128], xmm14",
+        "movdqu [rsp + 144], xmm15",
+        "mov rdi, r10",
+        "lea rsi, [rbp + 16]",
+        "call {dispatch}",
+        "movdqu xmm6, [rsp]",
+        "movdqu xmm7, [rsp + 16]",
+        "movdqu xmm8, [rsp + 32]",
+        "movdqu xmm9, [rsp + 48]",
+        "movdqu xmm10, [rsp + 64]",
+        "movdqu xmm11, [rsp + 80]",
+        "movdqu xmm12, [rsp + 96]",
+        "movdqu xmm13, [rsp + 112]",
+        "movdqu xmm14, [rsp + 128]",
+        "movdqu xmm15, [rsp + 144]",
+        "add rsp, 160",
+        "pop rsi",
+        "pop rdi",
+        "pop rbp",
+        "ret",
+        "",
+        ".globl waygate_missing_import",
+        "waygate_missing_import:",
+        "mov rdi, r10",
+        "mov rsi, rsp",
+        "mov rdx, rbp",
+        "and rsp, -16",
+        "call {missing}",
+        "ud2",
+        dispatch = sym win64_dispatch,
+        missing = sym missing_import_trap,
+    );
+
+    extern "C" {
+        fn waygate_import_entry();
+        fn waygate_missing_import();
+    }
+
+    extern "sysv64" fn win64_dispatch(state: *const SlotState, args: *const u64) -> u64 {
+        // SAFETY: stubs only ever pass pointers into a live ThunkTable, and
+        // `args` points at the spilled registers followed by the stack
+        // arguments of the Win64 call, right above the return address and
+        // the caller's RBP the entry pushed.
+        let state = unsafe { &*state };
+        let caller = unsafe { Frame::new(args.sub(1).read(), args as u64, args.sub(2).read()) };
+        slot_call(state, caller, |idx| unsafe { args.add(idx).read() })
+    }
+
+    extern "sysv64" fn missing_import_trap(
+        state: *const SlotState,
+        stack: *const u64,
+        rbp: u64,
+    ) -> ! {
+        // SAFETY: as above; `stack` points at the return address.
+        let caller = unsafe { Frame::new(stack.read(), stack.add(1) as u64, rbp) };
+        missing_import(unsafe { &*state }, caller)
+    }
+
+    pub fn import_entry() -> Result<u64, String> {
+        Ok(waygate_import_entry as *const () as u64)
+    }
+
+    pub fn missing_import_entry() -> Result<u64, String> {
+        Ok(waygate_missing_import as *const () as u64)
+    }
+
+    /// `mov r10, imm64; mov r11, imm64; jmp r11`, padded with `int3`.
+    pub fn encode_stub(slot: u64, target: u64) -> [u8; STUB_SIZE] {
+        let mut stub = [0xCC; STUB_SIZE];
+        stub[0..2].copy_from_slice(&[0x49, 0xBA]);
+        stub[2..10].copy_from_slice(&slot.to_le_bytes());
+        stub[10..12].copy_from_slice(&[0x49, 0xBB]);
+        stub[12..20].copy_from_slice(&target.to_le_bytes());
+        stub[20..23].copy_from_slice(&[0x41, 0xFF, 0xE3]);
+        stub
+    }
+
+    pub unsafe fn call_entry_point(address: u64) -> Result<u32, String> {
+        let entry: extern "win64" fn() -> u32 = std::mem::transmute(address as usize);
+        Ok(entry())
+    }
+
+    pub unsafe fn call_dll_main(address: u64, base: u64, reason: u32) -> Result<bool, String> {
+        let entry: extern "win64" fn(u64, u32, u64) -> i32 = std::mem::transmute(address as usize);
+        Ok(entry(base, reason, 0) != 0)
+    }
+
+    pub unsafe fn call_thread_start(address: u64, param: u64) -> Result<u32, String> {
+        let start: extern "win64" fn(u64) -> u32 = std::mem::transmute(address as usize);
+        Ok(start(param))
+    }
+}
+
+#[cfg(target_arch = "x86")]
+mod arch {
+    use super::{missing_import, slot_call, SlotState};
+    use crate::resolve;
+    use crate::unwind::Frame;
+
+    /// Bytes reserved per generated stub.
+    pub const STUB_SIZE: usize = 16;
+
+    // 32-bit stdcall/cdecl entry. Arguments live on the stack right above
+    // the return address. The Rust side reports how many bytes the callee
+    // must pop (4 * argc for stdcall, 0 for cdecl); the epilogue then drops
+    // them after taking the return address off the stack.
+    std::arch::global_asm!(
+        ".globl waygate_import_entry",
+        "waygate_import_entry:",
+        "push ebp",
+        "mov ebp, esp",
+        "push ebx",
+        "push esi",
+        "push edi",
+        "sub esp, 4",
+        "mov edx, esp",
+        "and esp, -16",
+        "sub esp, 4",
+        "push edx",
+        "lea ecx, [ebp + 8]",
+        "push ecx",
+        "push eax",
+        "call {dispatch}",
+        "mov ecx, [ebp - 16]",
+        "lea esp, [ebp - 12]",
+        "pop edi",
+        "pop esi",
+        "pop ebx",
+        "pop ebp",
+        "pop edx",
+        "add esp, ecx",
+        "jmp edx",
+        "",
+        ".globl waygate_missing_import",
+        "waygate_missing_import:",
+        "mov ecx, esp",
+        "and esp, -16",
+        "sub esp, 4",
+        "push ebp",
+        "push ecx",
+        "push eax",
+        "call {missing}",
+        "ud2",
+        dispatch = sym x86_dispatch,
+        missing = sym missing_import_trap,
+    );
+
+    extern "C" {
+        fn waygate_import_entry();
+        fn waygate_missing_import();
+    }
+
+    extern "C" fn x86_dispatch(state: *const SlotState, args: *const u32, pop: *mut u32) -> u32 {
+        // SAFETY: stubs only ever pass pointers into a live ThunkTable;
+        // `args` points at the caller's stack arguments, right above the
+        // return address and the caller's EBP the entry pushed, and `pop`
+        // at the entry's scratch slot.
+        let state = unsafe { &*state };
+        let pop_bytes = resolve(&state.slot.module, &state.slot.symbol)
+            .map(|export| export.callee_pop_bytes())
+            .unwrap_or(0);
+        unsafe { pop.write(pop_bytes) };
+        let caller = unsafe {
+            Frame::new(
+                u64::from(args.sub(1).read()),
+                args as u64 + u64::from(pop_bytes),
+                u64::from(args.sub(2).read()),
+            )
+        };
+        slot_call(state, caller, |idx| {
+            u64::from(unsafe { args.add(idx).read() })
+        }) as u32
+    }
+
+    extern "C" fn missing_import_trap(state: *const SlotState, stack: *const u32, ebp: u32) -> ! {
+        // SAFETY: as above; `stack` points at the return address.
+        let caller =
+            unsafe { Frame::new(u64::from(stack.read()), stack.add(1) as u64, ebp.into()) };
+        missing_import(unsafe { &*state }, caller)
+    }
+
+    pub fn import_entry() -> Result<u64, String> {
+        Ok(waygate_import_entry as *const () as u64)
+    }
+
+    pub fn missing_import_entry() -> Result<u64, String> {
+        Ok(waygate_missing_import as *const () as u64)
+    }
+
+    /// `mov eax, imm32; mov ecx, imm32; jmp ecx`, padded with `int3`.
+    pub fn encode_stub(slot: u64, target: u64) -> [u8; STUB_SIZE] {
+        let mut stub = [0xCC; STUB_SIZE];
+        stub[0] = 0xB8;
+        stub[1..5].copy_from_slice(&(slot as u32).to_le_bytes());
+        stub[5] = 0xB9;
+        stub[6..10].copy_from_slice(&(target as u32).to_le_bytes());
+        stub[10..12].copy_from_slice(&[0xFF, 0xE1]);
+        stub
+    }
+
+    pub unsafe fn call_entry_point(address: u64) -> Result<u32, String> {
+        let entry: extern "C" fn() -> u32 = std::mem::transmute(address as usize);
+        Ok(entry())
+    }
+
+    pub unsafe fn call_dll_main(address: u64, base: u64, reason: u32) -> Result<bool, String> {
+        let entry: extern "stdcall" fn(u32, u32, u32) -> i32 =
+            std::mem::transmute(address as usize);
+        Ok(entry(base as u32, reason, 0) != 0)
+    }
+
+    pub unsafe fn call_thread_start(address: u64, param: u64) -> Result<u32, String> {
+        let start: extern "stdcall" fn(u32) -> u32 = std::mem::transmute(address as usize);
+        Ok(start(param as u32))
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
+mod arch {
+    pub const STUB_SIZE: usize = 16;
+
+    pub fn import_entry() -> Result<u64, String> {
+        Err("waygate: import thunks require an x86 or x86_64 host".to_string())
+    }
+
+    pub fn missing_import_entry() -> Result<u64, String> {
+        import_entry()
+    }
+
+    pub fn encode_stub(_slot: u64, _target: u64) -> [u8; STUB_SIZE] {
+        [0; STUB_SIZE]
+    }
+
+    pub unsafe fn call_entry_point(_address: u64) -> Result<u32, String> {
+        import_entry().map(|_| 0)
+    }
+
+    pub unsafe fn call_dll_main(_address: u64, _base: u64, _reason: u32) -> Result<bool, String> {
+        import_entry().map(|_| false)
+    }
+
+    pub unsafe fn call_thread_start(_address: u64, _param: u64) -> Result<u32, String> {
+        import_entry().map(|_| 0)
+    }
+}
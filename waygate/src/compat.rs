@@ -0,0 +1,114 @@
+use std::sync::Mutex;
+
+/// A Windows version as the version APIs report it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct OsVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub build: u32,
+}
+
+impl OsVersion {
+    /// Windows 8, what `GetVersionEx` reports to applications whose
+    /// manifest does not declare Windows 8.1 or later.
+    pub const WIN8: OsVersion = OsVersion {
+        major: 6,
+        minor: 2,
+        build: 9200,
+    };
+    pub const WIN81: OsVersion = OsVersion {
+        major: 6,
+        minor: 3,
+        build: 9600,
+    };
+    pub const WIN10: OsVersion = OsVersion {
+        major: 10,
+        minor: 0,
+        build: 19045,
+    };
+}
+
+/// Process DPI awareness (`PROCESS_DPI_AWARENESS`, plus per-monitor v2).
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DpiAwareness {
+    Unaware,
+    System,
+    PerMonitor,
+    PerMonitorV2,
+}
+
+impl DpiAwareness {
+    /// Value `GetProcessDpiAwareness` writes; v2 has no value of its own.
+    fn process_value(self) -> u32 {
+        match self {
+            DpiAwareness::Unaware => 0,
+            DpiAwareness::System => 1,
+            DpiAwareness::PerMonitor | DpiAwareness::PerMonitorV2 => 2,
+        }
+    }
+}
+
+/// What the application declared in its manifest, as far as waygate's
+/// exports behave differently because of it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct AppCompat {
+    /// Version reported by `GetVersion`/`GetVersionEx`. `RtlGetVersion`
+    /// ignores the manifest and always reports [`OsVersion::WIN10`].
+    pub version: OsVersion,
+    pub dpi_awareness: DpiAwareness,
+    /// Side-by-side dependency on Microsoft.Windows.Common-Controls 6.0.
+    pub common_controls_v6: bool,
+}
+
+impl AppCompat {
+    /// An application without a manifest.
+    pub const DEFAULT: AppCompat = AppCompat {
+        version: OsVersion::WIN8,
+        dpi_awareness: DpiAwareness::Unaware,
+        common_controls_v6: false,
+    };
+}
+
+static APP_COMPAT: Mutex<AppCompat> = Mutex::new(AppCompat::DEFAULT);
+
+/// Installs the running application's declared requirements. Call before
+/// transferring control to the image.
+pub fn set_app_compat(compat: AppCompat) {
+    *APP_COMPAT.lock().unwrap_or_else(|e| e.into_inner()) = compat;
+}
+
+pub fn app_compat() -> AppCompat {
+    *APP_COMPAT.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// `SetProcessDPIAware`: an unaware process becomes system aware; a
+/// manifest-declared awareness is kept.
+pub(crate) fn set_process_dpi_aware() {
+    let mut compat = APP_COMPAT.lock().unwrap_or_else(|e| e.into_inner());
+    if compat.dpi_awareness == DpiAwareness::Unaware {
+        compat.dpi_awareness = DpiAwareness::System;
+    }
+}
+
+pub(crate) fn process_dpi_awareness() -> u32 {
+    app_compat().dpi_awareness.process_value()
+}
+
+/// Fills an OSVERSIONINFO[EX][A|W] / RTL_OSVERSIONINFOW. Only the numeric
+/// fields are written; `szCSDVersion` is left as the caller initialized it.
+/// Returns false when the caller's `dwOSVersionInfoSize` is too small.
+///
+/// # Safety
+/// `info` must point to a writable OSVERSIONINFO-shaped buffer.
+pub(crate) unsafe fn write_version_info(info: *mut u32, version: OsVersion) -> bool {
+    // dwOSVersionInfoSize, dwMajorVersion, dwMinorVersion, dwBuildNumber,
+    // dwPlatformId (VER_PLATFORM_WIN32_NT).
+    if info.read_unaligned() < 20 {
+        return false;
+    }
+    let fields = [version.major, version.minor, version.build, 2];
+    for (idx, value) in fields.into_iter().enumerate() {
+        info.add(idx + 1).write_unaligned(value);
+    }
+    true
+}
@@ -0,0 +1,184 @@
+use std::sync::Mutex;
+
+use crate::bridge::call_dll_main;
+
+pub const DLL_PROCESS_DETACH: u32 = 0;
+pub const DLL_PROCESS_ATTACH: u32 = 1;
+pub const DLL_THREAD_ATTACH: u32 = 2;
+pub const DLL_THREAD_DETACH: u32 = 3;
+
+/// Longest TLS callback array walked before giving up on a missing NULL.
+const MAX_TLS_CALLBACKS: usize = 256;
+
+#[derive(Clone)]
+struct AttachedModule {
+    name: String,
+    base: u64,
+    /// `DllMain`; `None` for the executable and DLLs without one.
+    entry: Option<u64>,
+    /// The NULL-terminated TLS callback array, 0 when there is none.
+    tls_callbacks: u64,
+    executable: bool,
+    trace: bool,
+}
+
+impl AttachedModule {
+    /// Notifies the module the way the Windows loader does: TLS callbacks
+    /// first, then `DllMain`. Only DllMain's DLL_PROCESS_ATTACH result
+    /// counts; every other return value is ignored.
+    unsafe fn notify(&self, reason: u32) -> Result<bool, String> {
+        for callback in tls_callbacks(self.tls_callbacks) {
+            if self.trace {
+                eprintln!(
+                    "  [tls] {} callback {callback:#x} {}",
+                    self.name,
+                    reason_name(reason)
+                );
+            }
+            call_dll_main(callback, self.base, reason)?;
+        }
+        let Some(entry) = self.entry else {
+            return Ok(true);
+        };
+        if self.trace {
+            eprintln!("  [dllmain] {} {}", self.name, reason_name(reason));
+        }
+        call_dll_main(entry, self.base, reason)
+    }
+}
+
+/// Reads the callback array afresh, as Windows does on every notification:
+/// a callback may add entries for later ones.
+unsafe fn tls_callbacks(array: u64) -> Vec<u64> {
+    let array = array as *const usize;
+    let mut callbacks = Vec::new();
+    while !array.is_null() && callbacks.len() < MAX_TLS_CALLBACKS {
+        match array.add(callbacks.len()).read_unaligned() {
+            0 => break,
+            callback => callbacks.push(callback as u64),
+        }
+    }
+    callbacks
+}
+
+/// Modules that accepted DLL_PROCESS_ATTACH, in attach order. The
+/// executable comes last, since it is attached right before its entry point.
+static ATTACHED: Mutex<Vec<AttachedModule>> = Mutex::new(Vec::new());
+
+fn reason_name(reason: u32) -> &'static str {
+    match reason {
+        DLL_PROCESS_DETACH => "DLL_PROCESS_DETACH",
+        DLL_PROCESS_ATTACH => "DLL_PROCESS_ATTACH",
+        DLL_THREAD_ATTACH => "DLL_THREAD_ATTACH",
+        DLL_THREAD_DETACH => "DLL_THREAD_DETACH",
+        _ => "unknown reason",
+    }
+}
+
+fn attached() -> Vec<AttachedModule> {
+    ATTACHED.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Modules in detach order: DLLs in reverse attach order, then the
+/// executable, whose TLS callbacks Windows notifies last.
+fn detach_order(modules: &[AttachedModule]) -> impl Iterator<Item = &AttachedModule> {
+    let dlls = modules.iter().rev().filter(|module| !module.executable);
+    dlls.chain(modules.iter().filter(|module| module.executable))
+}
+
+/// Runs the DLL's TLS callbacks and then `DllMain(base, DLL_PROCESS_ATTACH,
+/// NULL)`. When DllMain returns TRUE, remembers the DLL so thread starts and
+/// [`detach_modules`] notify it later.
+///
+/// # Safety
+///
+/// `entry` and the callbacks in the `tls_callbacks` array (0 for none) must
+/// be code of a mapped, bound and protected DLL image at `base` that this
+/// host can execute.
+pub unsafe fn attach_dll(
+    name: &str,
+    base: u64,
+    entry: Option<u64>,
+    tls_callbacks: u64,
+    trace: bool,
+) -> Result<(), String> {
+    attach(AttachedModule {
+        name: name.to_string(),
+        base,
+        entry,
+        tls_callbacks,
+        executable: false,
+        trace,
+    })
+}
+
+/// Runs the executable's TLS callbacks with DLL_PROCESS_ATTACH. Call it
+/// after every DLL is attached and right before the entry point, which is
+/// where the callbacks run on Windows.
+///
+/// # Safety
+///
+/// Same requirements as [`attach_dll`], for the executable image at `base`.
+pub unsafe fn attach_executable(
+    name: &str,
+    base: u64,
+    tls_callbacks: u64,
+    trace: bool,
+) -> Result<(), String> {
+    attach(AttachedModule {
+        name: name.to_string(),
+        base,
+        entry: None,
+        tls_callbacks,
+        executable: true,
+        trace,
+    })
+}
+
+unsafe fn attach(module: AttachedModule) -> Result<(), String> {
+    if !module.notify(DLL_PROCESS_ATTACH)? {
+        return Err(format!(
+            "waygate: DllMain of {} failed DLL_PROCESS_ATTACH",
+            module.name
+        ));
+    }
+    ATTACHED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(module);
+    Ok(())
+}
+
+/// Sends DLL_THREAD_ATTACH to every attached module, in attach order, on
+/// a thread that is about to run its start routine.
+pub(crate) fn attach_thread() -> Result<(), String> {
+    for module in &attached() {
+        // SAFETY: attached images stay mapped until the loader that
+        // attached them is dropped.
+        unsafe { module.notify(DLL_THREAD_ATTACH) }?;
+    }
+    Ok(())
+}
+
+/// Sends DLL_THREAD_DETACH to every attached module once a thread's start
+/// routine has returned.
+pub(crate) fn detach_thread() {
+    let modules = attached();
+    for module in detach_order(&modules) {
+        // SAFETY: as in `attach_thread`.
+        let _ = unsafe { module.notify(DLL_THREAD_DETACH) };
+    }
+}
+
+/// Sends DLL_PROCESS_DETACH to every attached module: DLLs in reverse
+/// attach order, then the executable's TLS callbacks. Runs at most once per
+/// module; `ExitProcess` calls it before exiting.
+pub fn detach_modules() {
+    // Taken out of the lock first: a DllMain may itself call ExitProcess.
+    let attached = std::mem::take(&mut *ATTACHED.lock().unwrap_or_else(|e| e.into_inner()));
+    for module in detach_order(&attached) {
+        // SAFETY: only modules accepted by `attach` are recorded, and their
+        // images stay mapped until the loader that attached them is dropped.
+        let _ = unsafe { module.notify(DLL_PROCESS_DETACH) };
+    }
+}
@@ -1,44 +1,596 @@
+use std::cell::Cell;
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use compat::{process_dpi_awareness, set_process_dpi_aware, write_version_info};
+
+mod apiset;
+mod bridge;
+mod compat;
+mod dll;
+mod ordinals;
+mod symbols;
+mod thread;
+mod thunk;
+mod tls;
+mod unwind;
+
+pub use apiset::{api_set_host, is_api_set};
+pub use bridge::call_entry_point;
+pub use compat::{app_compat, set_app_compat, AppCompat, DpiAwareness, OsVersion};
+pub use dll::{
+    attach_dll, attach_executable, detach_modules, DLL_PROCESS_ATTACH, DLL_PROCESS_DETACH,
+    DLL_THREAD_ATTACH, DLL_THREAD_DETACH,
+};
+pub use ordinals::ordinal_name;
+pub use symbols::{register_image, symbolize, CodeImage, Symbolizer};
+pub use thunk::{DelayLoad, ThunkSlot, ThunkTable};
+pub use tls::{allocate_tls_index, install_thread_tls, TlsTemplate};
+
 pub type WaygateResult = Result<String, String>;
 
-pub fn dispatch(symbol: &str, args: &[String]) -> WaygateResult {
-    match symbol {
-        "CreateFileA" => stub("CreateFileA", args),
-        "ReadFile" => stub("ReadFile", args),
-        "WriteFile" => stub("WriteFile", args),
-        "CloseHandle" => stub("CloseHandle", args),
-        "MessageBoxA" => stub("MessageBoxA", args),
-        "VirtualAlloc" => stub("VirtualAlloc", args),
-        "VirtualFree" => stub("VirtualFree", args),
-        "GetLastError" => stub("GetLastError", args),
-        "SetLastError" => stub("SetLastError", args),
-        "ExitProcess" => stub("ExitProcess", args),
-        "GetCurrentProcess" => stub("GetCurrentProcess", args),
-        "Sleep" => stub("Sleep", args),
-        "GetTickCount" => stub("GetTickCount", args),
-        "GetModuleHandle" => stub("GetModuleHandle", args),
-        "GetProcAddress" => stub("GetProcAddress", args),
-        "LoadLibrary" => stub("LoadLibrary", args),
-        "FreeLibrary" => stub("FreeLibrary", args),
-        "SendInput" => stub("SendInput", args),
-        "mouse_event" => stub("mouse_event", args),
-        "keybd_event" => stub("keybd_event", args),
-        "GetCursorPos" => stub("GetCursorPos", args),
-        "SetCursorPos" => stub("SetCursorPos", args),
-        "GetAsyncKeyState" => stub("GetAsyncKeyState", args),
-        "GetKeyState" => stub("GetKeyState", args),
-        "MapVirtualKey" => stub("MapVirtualKey", args),
-        "ShowCursor" => stub("ShowCursor", args),
-        "ClipCursor" => stub("ClipCursor", args),
-        "CreateThread" => stub("CreateThread", args),
-        "WaitForSingleObject" => stub("WaitForSingleObject", args),
-        "CreateEvent" => stub("CreateEvent", args),
-        "SetEvent" => stub("SetEvent", args),
-        "ResetEvent" => stub("ResetEvent", args),
-        "QueryPerformanceCounter" => stub("QueryPerformanceCounter", args),
-        "QueryPerformanceFrequency" => stub("QueryPerformanceFrequency", args),
-        "GetSystemTime" => stub("GetSystemTime", args),
-        "GetLocalTime" => stub("GetLocalTime", args),
-        _ => Err(format!("waygate: symbol '{symbol}' is not implemented")),
+/// How a parameter's raw register/stack slot is interpreted.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ArgKind {
+    /// 32-bit integer (`int`, `DWORD`, `UINT`, `BOOL`, ...).
+    Int,
+    /// Pointer-sized integer (`SIZE_T`, `ULONG_PTR`).
+    Size,
+    /// Pointer or handle, passed through untouched.
+    Ptr,
+    /// NUL-terminated ANSI string pointer.
+    Str,
+    /// NUL-terminated UTF-16 string pointer (the `W` entry points).
+    WStr,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CallConv {
+    /// Callee pops its arguments (`WINAPI`); the only convention on Win64.
+    Stdcall,
+    /// Caller pops its arguments (`WINAPIV`, variadic functions).
+    Cdecl,
+}
+
+pub struct Param {
+    pub name: &'static str,
+    pub kind: ArgKind,
+}
+
+pub struct Export {
+    /// Host module the export belongs to, as a lowercase name without
+    /// `.dll` (`kernel32`, `user32`, ...).
+    pub dll: &'static str,
+    pub name: &'static str,
+    /// Implementation shared by the `A`/`W` variants of one API, e.g.
+    /// `LoadLibrary` for `LoadLibraryA` and `LoadLibraryW`. Equal to `name`
+    /// for symbols without variants.
+    pub family: &'static str,
+    pub params: &'static [Param],
+    pub conv: CallConv,
+}
+
+impl Export {
+    /// True for the export's own name and, on `A` variants, for the bare
+    /// family name C sources use through the header macros (`LoadLibrary`
+    /// without `UNICODE` is `LoadLibraryA`).
+    fn matches(&self, symbol: &str) -> bool {
+        self.name == symbol
+            || (self.family == symbol && self.name.strip_suffix('A') == Some(symbol))
+    }
+
+    /// Bytes a 32-bit callee removes from the stack on return.
+    pub fn callee_pop_bytes(&self) -> u32 {
+        match self.conv {
+            CallConv::Stdcall => self.params.len() as u32 * 4,
+            CallConv::Cdecl => 0,
+        }
+    }
+}
+
+/// A typed argument decoded from a machine-level call.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Arg {
+    Int(i64),
+    Ptr(u64),
+    /// A string argument: the pointer the caller passed and what it read.
+    Str {
+        address: u64,
+        text: String,
+    },
+}
+
+impl Arg {
+    /// The argument's raw slot value; strings give back their pointer.
+    pub fn as_u64(&self) -> u64 {
+        match self {
+            Arg::Int(v) => *v as u64,
+            Arg::Ptr(v) => *v,
+            Arg::Str { address, .. } => *address,
+        }
+    }
+}
+
+impl fmt::Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arg::Int(v) => write!(f, "{v}"),
+            Arg::Ptr(0) => write!(f, "NULL"),
+            Arg::Ptr(v) => write!(f, "{v:#x}"),
+            Arg::Str { text, .. } => write!(f, "{text:?}"),
+        }
+    }
+}
+
+/// Result of a typed call: the value returned to the caller in RAX/EAX and
+/// the trace line describing the call.
+pub struct Invocation {
+    pub ret: u64,
+    pub message: String,
+}
+
+macro_rules! params {
+    ($($name:ident: $kind:ident),* $(,)?) => {
+        &[$(Param { name: stringify!($name), kind: ArgKind::$kind }),*]
+    };
+}
+
+macro_rules! export {
+    ($dll:literal, $name:literal, $conv:ident, [$($params:tt)*]) => {
+        Export {
+            dll: $dll,
+            name: $name,
+            family: $name,
+            params: params!($($params)*),
+            conv: CallConv::$conv,
+        }
+    };
+}
+
+/// One character-set variant of `$family`: `variant!("kernel32",
+/// "LoadLibrary", "W", ...)` declares `LoadLibraryW`.
+macro_rules! variant {
+    ($dll:literal, $family:literal, $suffix:literal, $conv:ident, [$($params:tt)*]) => {
+        Export {
+            dll: $dll,
+            name: concat!($family, $suffix),
+            family: $family,
+            params: params!($($params)*),
+            conv: CallConv::$conv,
+        }
+    };
+}
+
+/// Every Win32 symbol waygate provides an implementation for.
+pub const EXPORTS: &[Export] = &[
+    variant!(
+        "kernel32",
+        "CreateFile",
+        "A",
+        Stdcall,
+        [
+            path: Str,
+            access: Int,
+            share: Int,
+            security: Ptr,
+            disposition: Int,
+            flags: Int,
+            template: Ptr,
+        ]
+    ),
+    variant!(
+        "kernel32",
+        "CreateFile",
+        "W",
+        Stdcall,
+        [
+            path: WStr,
+            access: Int,
+            share: Int,
+            security: Ptr,
+            disposition: Int,
+            flags: Int,
+            template: Ptr,
+        ]
+    ),
+    export!(
+        "kernel32",
+        "ReadFile",
+        Stdcall,
+        [handle: Ptr, buffer: Ptr, len: Int, read: Ptr, overlapped: Ptr]
+    ),
+    export!(
+        "kernel32",
+        "WriteFile",
+        Stdcall,
+        [
+            handle: Ptr,
+            buffer: Ptr,
+            len: Int,
+            written: Ptr,
+            overlapped: Ptr,
+        ]
+    ),
+    export!("kernel32", "CloseHandle", Stdcall, [handle: Ptr]),
+    variant!("user32", "MessageBox", "A", Stdcall, [hwnd: Ptr, text: Str, caption: Str, kind: Int]),
+    variant!(
+        "user32",
+        "MessageBox",
+        "W",
+        Stdcall,
+        [hwnd: Ptr, text: WStr, caption: WStr, kind: Int]
+    ),
+    export!(
+        "kernel32",
+        "VirtualAlloc",
+        Stdcall,
+        [address: Ptr, size: Size, allocation: Int, protect: Int]
+    ),
+    export!("kernel32", "VirtualFree", Stdcall, [address: Ptr, size: Size, free_type: Int]),
+    export!("kernel32", "GetLastError", Stdcall, []),
+    export!("kernel32", "SetLastError", Stdcall, [code: Int]),
+    export!("kernel32", "ExitProcess", Stdcall, [code: Int]),
+    export!("kernel32", "GetCurrentProcess", Stdcall, []),
+    export!("kernel32", "Sleep", Stdcall, [ms: Int]),
+    export!("kernel32", "GetTickCount", Stdcall, []),
+    variant!("kernel32", "GetModuleHandle", "A", Stdcall, [module: Str]),
+    variant!("kernel32", "GetModuleHandle", "W", Stdcall, [module: WStr]),
+    variant!("kernel32", "GetModuleHandleEx", "A", Stdcall, [flags: Int, module: Str, handle: Ptr]),
+    variant!(
+        "kernel32",
+        "GetModuleHandleEx",
+        "W",
+        Stdcall,
+        [flags: Int, module: WStr, handle: Ptr]
+    ),
+    export!("kernel32", "GetProcAddress", Stdcall, [module: Ptr, name: Str]),
+    variant!("kernel32", "LoadLibrary", "A", Stdcall, [path: Str]),
+    variant!("kernel32", "LoadLibrary", "W", Stdcall, [path: WStr]),
+    variant!("kernel32", "LoadLibraryEx", "A", Stdcall, [path: Str, file: Ptr, flags: Int]),
+    variant!("kernel32", "LoadLibraryEx", "W", Stdcall, [path: WStr, file: Ptr, flags: Int]),
+    export!("kernel32", "FreeLibrary", Stdcall, [module: Ptr]),
+    export!("user32", "SendInput", Stdcall, [cInputs: Int, pInputs: Ptr, cbSize: Int]),
+    export!(
+        "user32",
+        "mouse_event",
+        Stdcall,
+        [flags: Int, dx: Int, dy: Int, data: Int, extra: Size]
+    ),
+    export!("user32", "keybd_event", Stdcall, [vk: Int, scan: Int, flags: Int, extra: Size]),
+    export!("user32", "GetCursorPos", Stdcall, [point: Ptr]),
+    export!("user32", "SetCursorPos", Stdcall, [x: Int, y: Int]),
+    export!("user32", "GetAsyncKeyState", Stdcall, [vk: Int]),
+    export!("user32", "GetKeyState", Stdcall, [vk: Int]),
+    variant!("user32", "MapVirtualKey", "A", Stdcall, [vk: Int, map_type: Int]),
+    variant!("user32", "MapVirtualKey", "W", Stdcall, [vk: Int, map_type: Int]),
+    variant!("user32", "MapVirtualKeyEx", "A", Stdcall, [vk: Int, map_type: Int, layout: Ptr]),
+    variant!("user32", "MapVirtualKeyEx", "W", Stdcall, [vk: Int, map_type: Int, layout: Ptr]),
+    export!("user32", "ShowCursor", Stdcall, [show: Int]),
+    export!("user32", "ClipCursor", Stdcall, [rect: Ptr]),
+    export!(
+        "kernel32",
+        "CreateThread",
+        Stdcall,
+        [
+            attrs: Ptr,
+            stack: Size,
+            func: Ptr,
+            param: Ptr,
+            flags: Int,
+            thread_id: Ptr,
+        ]
+    ),
+    export!("kernel32", "WaitForSingleObject", Stdcall, [handle: Ptr, timeout: Int]),
+    variant!(
+        "kernel32",
+        "CreateEvent",
+        "A",
+        Stdcall,
+        [attrs: Ptr, manual: Int, initial: Int, name: Str]
+    ),
+    variant!(
+        "kernel32",
+        "CreateEvent",
+        "W",
+        Stdcall,
+        [attrs: Ptr, manual: Int, initial: Int, name: WStr]
+    ),
+    variant!(
+        "kernel32",
+        "CreateEventEx",
+        "A",
+        Stdcall,
+        [attrs: Ptr, name: Str, flags: Int, access: Int]
+    ),
+    variant!(
+        "kernel32",
+        "CreateEventEx",
+        "W",
+        Stdcall,
+        [attrs: Ptr, name: WStr, flags: Int, access: Int]
+    ),
+    export!("kernel32", "SetEvent", Stdcall, [handle: Ptr]),
+    export!("kernel32", "ResetEvent", Stdcall, [handle: Ptr]),
+    export!("kernel32", "QueryPerformanceCounter", Stdcall, [counter: Ptr]),
+    export!("kernel32", "QueryPerformanceFrequency", Stdcall, [counter: Ptr]),
+    export!("kernel32", "GetSystemTime", Stdcall, [time: Ptr]),
+    export!("kernel32", "GetLocalTime", Stdcall, [time: Ptr]),
+    export!("kernel32", "GetVersion", Stdcall, []),
+    variant!("kernel32", "GetVersionEx", "A", Stdcall, [info: Ptr]),
+    variant!("kernel32", "GetVersionEx", "W", Stdcall, [info: Ptr]),
+    export!("ntdll", "RtlGetVersion", Stdcall, [info: Ptr]),
+    export!("user32", "SetProcessDPIAware", Stdcall, []),
+    export!("user32", "IsProcessDPIAware", Stdcall, []),
+    export!("shcore", "GetProcessDpiAwareness", Stdcall, [process: Ptr, value: Ptr]),
+    export!("comctl32", "InitCommonControls", Stdcall, []),
+    export!("comctl32", "InitCommonControlsEx", Stdcall, [icc: Ptr]),
+    export!("comctl32", "DllGetVersion", Stdcall, [info: Ptr]),
+    export!("ws2_32", "WSAStartup", Stdcall, [version: Int, data: Ptr]),
+    export!("ws2_32", "WSACleanup", Stdcall, []),
+    export!("ws2_32", "WSAGetLastError", Stdcall, []),
+    export!("ws2_32", "socket", Stdcall, [af: Int, kind: Int, protocol: Int]),
+    export!("ws2_32", "closesocket", Stdcall, [socket: Ptr]),
+    export!("ws2_32", "connect", Stdcall, [socket: Ptr, name: Ptr, namelen: Int]),
+    export!("ws2_32", "send", Stdcall, [socket: Ptr, buffer: Ptr, len: Int, flags: Int]),
+    export!("ws2_32", "recv", Stdcall, [socket: Ptr, buffer: Ptr, len: Int, flags: Int]),
+    export!("ws2_32", "htons", Stdcall, [value: Int]),
+    export!("ws2_32", "ntohs", Stdcall, [value: Int]),
+    export!("ws2_32", "htonl", Stdcall, [value: Int]),
+    export!("ws2_32", "ntohl", Stdcall, [value: Int]),
+    export!("ws2_32", "inet_addr", Stdcall, [address: Str]),
+];
+
+/// Finds `symbol` in any module waygate serves. Only for callers that do
+/// not know which DLL the symbol was imported from; see [`resolve`].
+pub fn lookup(symbol: &str) -> Option<&'static Export> {
+    EXPORTS.iter().find(|export| export.matches(symbol))
+}
+
+/// Finds `symbol` as exported by `module`. A same-named export of an
+/// app-local DLL is not a waygate symbol and resolves to `None`.
+pub fn resolve(module: &str, symbol: &str) -> Option<&'static Export> {
+    let host = host_module(module);
+    EXPORTS
+        .iter()
+        .find(|export| export.dll == host && export.matches(symbol))
+}
+
+pub fn is_implemented(symbol: &str) -> bool {
+    lookup(symbol).is_some()
+}
+
+/// Replays a planned call. With `module` set the symbol must come from that
+/// DLL; without it any module's export of that name is used.
+pub fn dispatch(module: Option<&str>, symbol: &str, args: &[String]) -> WaygateResult {
+    let export = match module {
+        Some(module) => resolve(module, symbol),
+        None => lookup(symbol),
+    };
+    match (export, module) {
+        (Some(export), _) => stub(export.name, args),
+        (None, Some(module)) => Err(format!(
+            "waygate: symbol '{module}!{symbol}' is not implemented"
+        )),
+        (None, None) => Err(format!("waygate: symbol '{symbol}' is not implemented")),
+    }
+}
+
+/// System DLLs waygate stands in for. Like KnownDLLs on Windows, these are
+/// never loaded from the application directory.
+const BUILTIN_MODULES: &[&str] = &[
+    "kernel32",
+    "kernelbase",
+    "ntdll",
+    "user32",
+    "gdi32",
+    "advapi32",
+    "sechost",
+    "ole32",
+    "oleaut32",
+    "shell32",
+    "shlwapi",
+    "comctl32",
+    "comdlg32",
+    "ws2_32",
+    "wsock32",
+    "msvcrt",
+    "rpcrt4",
+    "imm32",
+    "version",
+    "winmm",
+    "crypt32",
+    "bcrypt",
+    "ucrtbase",
+    "shcore",
+];
+
+/// True when `dll` is one of the system modules waygate serves, so the
+/// loader binds its imports to waygate instead of searching for a file.
+pub fn is_builtin_module(dll: &str) -> bool {
+    let host = host_module(dll);
+    is_api_set(dll) || BUILTIN_MODULES.contains(&host.as_str())
+}
+
+/// Normalizes a DLL name to the module waygate serves it from: lowercase,
+/// no `.dll`, with API set contracts and thin wrapper DLLs folded into
+/// their host.
+pub(crate) fn host_module(dll: &str) -> String {
+    if let Some(host) = api_set_host(dll) {
+        return host.to_string();
+    }
+    let dll = dll.to_ascii_lowercase();
+    let stem = dll.strip_suffix(".dll").unwrap_or(&dll);
+    match stem {
+        "kernelbase" => "kernel32",
+        "wsock32" => "ws2_32",
+        other => other,
+    }
+    .to_string()
+}
+
+/// Calls `symbol` with arguments already decoded from registers or the stack.
+pub fn invoke(symbol: &str, args: &[Arg]) -> Result<Invocation, String> {
+    let export =
+        lookup(symbol).ok_or_else(|| format!("waygate: symbol '{symbol}' is not implemented"))?;
+    let named: Vec<String> = export
+        .params
+        .iter()
+        .zip(args)
+        .map(|(param, arg)| format!("{}={arg}", param.name))
+        .collect();
+    let message = stub(export.name, &named)?;
+    Ok(Invocation {
+        ret: execute(export, args),
+        message,
+    })
+}
+
+/// Stable fake HMODULE for a DLL served by waygate. Values are page aligned
+/// and far away from anything the loader maps, so they never alias memory.
+pub fn module_handle(dll: &str) -> u64 {
+    let hash = dll
+        .to_ascii_lowercase()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+            (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+    0x7ff0_0000_0000 | ((hash & 0xff_ffff) << 12)
+}
+
+thread_local! {
+    static LAST_ERROR: Cell<u32> = const { Cell::new(0) };
+}
+
+const PERFORMANCE_FREQUENCY: u64 = 1_000_000_000;
+
+const ERROR_INSUFFICIENT_BUFFER: u32 = 122;
+const E_INVALIDARG: u64 = 0x8007_0057;
+
+fn process_start() -> Instant {
+    static START: OnceLock<Instant> = OnceLock::new();
+    *START.get_or_init(Instant::now)
+}
+
+/// Behaviour behind each export. Anything without real semantics yet
+/// reports success (`TRUE`) so programs keep running past the stub.
+fn execute(export: &Export, args: &[Arg]) -> u64 {
+    let arg = |idx: usize| args.get(idx).map(Arg::as_u64).unwrap_or(0);
+    match export.family {
+        "GetLastError" | "WSAGetLastError" => LAST_ERROR.with(Cell::get) as u64,
+        "SetLastError" => {
+            LAST_ERROR.with(|e| e.set(arg(0) as u32));
+            0
+        }
+        "ExitProcess" => {
+            detach_modules();
+            std::process::exit(arg(0) as i32)
+        }
+        "GetCurrentProcess" => u64::MAX,
+        // Only image code gets real threads: a replayed plan has no thread
+        // routine to run. Suspended threads are never resumed, so they stay
+        // stubs too.
+        "CreateThread" if tls::has_thread_tls() && arg(4) & thread::CREATE_SUSPENDED == 0 => {
+            thread::create_thread(arg(2), arg(3), arg(5) as *mut u32)
+        }
+        "WaitForSingleObject" => thread::wait_thread(arg(0), arg(1) as u32).unwrap_or(1),
+        "Sleep" => {
+            std::thread::sleep(Duration::from_millis(arg(0) as u32 as u64));
+            0
+        }
+        "GetTickCount" => process_start().elapsed().as_millis() as u32 as u64,
+        "QueryPerformanceCounter" | "QueryPerformanceFrequency" => {
+            let out = arg(0) as *mut u64;
+            if out.is_null() {
+                return 0;
+            }
+            let value = if export.family == "QueryPerformanceFrequency" {
+                PERFORMANCE_FREQUENCY
+            } else {
+                process_start().elapsed().as_nanos() as u64
+            };
+            // SAFETY: the program handed us a LARGE_INTEGER out-pointer.
+            unsafe { out.write_unaligned(value) };
+            1
+        }
+        "GetVersion" => {
+            let version = app_compat().version;
+            ((version.build << 16) | (version.minor << 8) | version.major) as u64
+        }
+        "GetVersionEx" | "RtlGetVersion" => {
+            let info = arg(0) as *mut u32;
+            let version = if export.family == "RtlGetVersion" {
+                OsVersion::WIN10
+            } else {
+                app_compat().version
+            };
+            // SAFETY: the program handed us an OSVERSIONINFO out-pointer.
+            let written = !info.is_null() && unsafe { write_version_info(info, version) };
+            match (export.family, written) {
+                ("RtlGetVersion", true) => 0,
+                // STATUS_INVALID_PARAMETER
+                ("RtlGetVersion", false) => 0xC000_000D,
+                (_, true) => 1,
+                (_, false) => {
+                    LAST_ERROR.with(|e| e.set(ERROR_INSUFFICIENT_BUFFER));
+                    0
+                }
+            }
+        }
+        "SetProcessDPIAware" => {
+            set_process_dpi_aware();
+            1
+        }
+        "IsProcessDPIAware" => (process_dpi_awareness() != 0) as u64,
+        "GetProcessDpiAwareness" => {
+            let out = arg(1) as *mut u32;
+            if out.is_null() {
+                return E_INVALIDARG;
+            }
+            // SAFETY: the program handed us a PROCESS_DPI_AWARENESS out-pointer.
+            unsafe { out.write_unaligned(process_dpi_awareness()) };
+            0
+        }
+        "DllGetVersion" => {
+            // DLLVERSIONINFO: cbSize, dwMajorVersion, dwMinorVersion,
+            // dwBuildNumber, dwPlatformID. Only apps that ask for Common
+            // Controls 6 in their manifest get the v6 assembly.
+            let info = arg(0) as *mut u32;
+            if info.is_null() {
+                return E_INVALIDARG;
+            }
+            let (major, minor) = if app_compat().common_controls_v6 {
+                (6, 16)
+            } else {
+                (5, 82)
+            };
+            // SAFETY: the program handed us a DLLVERSIONINFO out-pointer.
+            unsafe {
+                if info.read_unaligned() < 20 {
+                    return E_INVALIDARG;
+                }
+                for (idx, value) in [major, minor, OsVersion::WIN10.build, 2]
+                    .into_iter()
+                    .enumerate()
+                {
+                    info.add(idx + 1).write_unaligned(value);
+                }
+            }
+            0
+        }
+        // Winsock reports success as 0 rather than TRUE.
+        "WSAStartup" | "WSACleanup" | "closesocket" => 0,
+        "htons" | "ntohs" => (arg(0) as u16).swap_bytes() as u64,
+        "htonl" | "ntohl" => (arg(0) as u32).swap_bytes() as u64,
+        "inet_addr" => match args.first() {
+            // INADDR_NONE for anything that is not a dotted quad.
+            Some(Arg::Str { text, .. }) => text
+                .parse::<std::net::Ipv4Addr>()
+                .map_or(u32::MAX, |addr| u32::from_ne_bytes(addr.octets()))
+                as u64,
+            _ => u32::MAX as u64,
+        },
+        _ => 1,
     }
 }
 
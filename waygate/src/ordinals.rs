@@ -0,0 +1,244 @@
+use crate::host_module;
+
+/// Ordinal -> name tables for system DLLs whose exports are commonly (or
+/// only) imported by ordinal.
+struct OrdinalTable {
+    dll: &'static str,
+    entries: &'static [(u16, &'static str)],
+}
+
+const WS2_32: &[(u16, &str)] = &[
+    (1, "accept"),
+    (2, "bind"),
+    (3, "closesocket"),
+    (4, "connect"),
+    (5, "getpeername"),
+    (6, "getsockname"),
+    (7, "getsockopt"),
+    (8, "htonl"),
+    (9, "htons"),
+    (10, "ioctlsocket"),
+    (11, "inet_addr"),
+    (12, "inet_ntoa"),
+    (13, "listen"),
+    (14, "ntohl"),
+    (15, "ntohs"),
+    (16, "recv"),
+    (17, "recvfrom"),
+    (18, "select"),
+    (19, "send"),
+    (20, "sendto"),
+    (21, "setsockopt"),
+    (22, "shutdown"),
+    (23, "socket"),
+    (51, "gethostbyaddr"),
+    (52, "gethostbyname"),
+    (53, "getprotobyname"),
+    (54, "getprotobynumber"),
+    (55, "getservbyname"),
+    (56, "getservbyport"),
+    (57, "gethostname"),
+    (101, "WSAAsyncSelect"),
+    (102, "WSAAsyncGetHostByAddr"),
+    (103, "WSAAsyncGetHostByName"),
+    (104, "WSAAsyncGetProtoByNumber"),
+    (105, "WSAAsyncGetProtoByName"),
+    (106, "WSAAsyncGetServByPort"),
+    (107, "WSAAsyncGetServByName"),
+    (108, "WSACancelAsyncRequest"),
+    (109, "WSASetBlockingHook"),
+    (110, "WSAUnhookBlockingHook"),
+    (111, "WSAGetLastError"),
+    (112, "WSASetLastError"),
+    (113, "WSACancelBlockingCall"),
+    (114, "WSAIsBlocking"),
+    (115, "WSAStartup"),
+    (116, "WSACleanup"),
+    (151, "__WSAFDIsSet"),
+    (500, "WEP"),
+];
+
+const OLEAUT32: &[(u16, &str)] = &[
+    (2, "SysAllocString"),
+    (3, "SysReAllocString"),
+    (4, "SysAllocStringLen"),
+    (5, "SysReAllocStringLen"),
+    (6, "SysFreeString"),
+    (7, "SysStringLen"),
+    (8, "VariantInit"),
+    (9, "VariantClear"),
+    (10, "VariantCopy"),
+    (11, "VariantCopyInd"),
+    (12, "VariantChangeType"),
+    (13, "VariantTimeToDosDateTime"),
+    (14, "DosDateTimeToVariantTime"),
+    (15, "SafeArrayCreate"),
+    (16, "SafeArrayDestroy"),
+    (17, "SafeArrayGetDim"),
+    (18, "SafeArrayGetElemsize"),
+    (19, "SafeArrayGetUBound"),
+    (20, "SafeArrayGetLBound"),
+    (21, "SafeArrayLock"),
+    (22, "SafeArrayUnlock"),
+    (23, "SafeArrayAccessData"),
+    (24, "SafeArrayUnaccessData"),
+    (25, "SafeArrayGetElement"),
+    (26, "SafeArrayPutElement"),
+    (27, "SafeArrayCopy"),
+    (28, "DispGetParam"),
+    (29, "DispGetIDsOfNames"),
+    (30, "DispInvoke"),
+    (31, "CreateDispTypeInfo"),
+    (32, "CreateStdDispatch"),
+    (33, "RegisterActiveObject"),
+    (34, "RevokeActiveObject"),
+    (35, "GetActiveObject"),
+    (36, "SafeArrayAllocDescriptor"),
+    (37, "SafeArrayAllocData"),
+    (38, "SafeArrayDestroyDescriptor"),
+    (39, "SafeArrayDestroyData"),
+    (40, "SafeArrayRedim"),
+    (147, "VariantChangeTypeEx"),
+    (148, "SafeArrayPtrOfIndex"),
+    (149, "SysStringByteLen"),
+    (150, "SysAllocStringByteLen"),
+    (161, "LoadTypeLib"),
+    (162, "LoadRegTypeLib"),
+    (163, "RegisterTypeLib"),
+    (164, "QueryPathOfRegTypeLib"),
+    (183, "LoadTypeLibEx"),
+    (184, "SystemTimeToVariantTime"),
+    (185, "VariantTimeToSystemTime"),
+    (186, "UnRegisterTypeLib"),
+    (200, "GetErrorInfo"),
+    (201, "SetErrorInfo"),
+    (202, "CreateErrorInfo"),
+];
+
+const COMCTL32: &[(u16, &str)] = &[
+    (2, "MenuHelp"),
+    (3, "ShowHideMenuCtl"),
+    (4, "GetEffectiveClientRect"),
+    (5, "DrawStatusTextA"),
+    (6, "CreateStatusWindowA"),
+    (7, "CreateToolbar"),
+    (8, "CreateMappedBitmap"),
+    (9, "DPA_LoadStream"),
+    (10, "DPA_SaveStream"),
+    (11, "DPA_Merge"),
+    (13, "MakeDragList"),
+    (14, "LBItemFromPt"),
+    (15, "DrawInsert"),
+    (16, "CreateUpDownControl"),
+    (17, "InitCommonControls"),
+    (71, "Alloc"),
+    (72, "ReAlloc"),
+    (73, "Free"),
+    (74, "GetSize"),
+    (233, "Str_GetPtrA"),
+    (234, "Str_SetPtrA"),
+    (235, "Str_GetPtrW"),
+    (236, "Str_SetPtrW"),
+    (320, "DSA_Create"),
+    (321, "DSA_Destroy"),
+    (322, "DSA_GetItem"),
+    (323, "DSA_GetItemPtr"),
+    (324, "DSA_InsertItem"),
+    (325, "DSA_SetItem"),
+    (326, "DSA_DeleteItem"),
+    (327, "DSA_DeleteAllItems"),
+    (328, "DPA_Create"),
+    (329, "DPA_Destroy"),
+    (330, "DPA_Grow"),
+    (331, "DPA_Clone"),
+    (332, "DPA_GetPtr"),
+    (333, "DPA_GetPtrIndex"),
+    (334, "DPA_InsertPtr"),
+    (335, "DPA_SetPtr"),
+    (336, "DPA_DeletePtr"),
+    (337, "DPA_DeleteAllPtrs"),
+    (338, "DPA_Sort"),
+    (339, "DPA_Search"),
+    (340, "DPA_CreateEx"),
+    (341, "SendNotify"),
+    (342, "SendNotifyEx"),
+    (385, "DPA_EnumCallback"),
+    (386, "DPA_DestroyCallback"),
+    (387, "DSA_EnumCallback"),
+    (388, "DSA_DestroyCallback"),
+    (410, "SetWindowSubclass"),
+    (411, "GetWindowSubclass"),
+    (412, "RemoveWindowSubclass"),
+    (413, "DefSubclassProc"),
+];
+
+const SHLWAPI: &[(u16, &str)] = &[
+    (1, "ParseURLA"),
+    (2, "ParseURLW"),
+    (3, "PathFileExistsDefExtA"),
+    (4, "PathFileExistsDefExtW"),
+    (5, "PathFindOnPathExA"),
+    (6, "PathFindOnPathExW"),
+    (7, "SHAllocShared"),
+    (8, "SHLockShared"),
+    (9, "SHUnlockShared"),
+    (10, "SHFreeShared"),
+    (16, "SHCreateThread"),
+    (23, "SHStringFromGUIDA"),
+    (24, "SHStringFromGUIDW"),
+    (25, "IsCharAlphaWrapW"),
+    (26, "IsCharUpperWrapW"),
+    (27, "IsCharLowerWrapW"),
+    (28, "IsCharAlphaNumericWrapW"),
+    (29, "IsCharSpaceW"),
+    (30, "IsCharBlankW"),
+    (31, "IsCharPunctW"),
+    (32, "IsCharCntrlW"),
+    (33, "IsCharDigitW"),
+    (34, "IsCharXDigitW"),
+    (151, "StrCmpNCA"),
+    (152, "StrCmpNCW"),
+    (153, "StrCmpNICA"),
+    (154, "StrCmpNICW"),
+    (155, "StrCmpCA"),
+    (156, "StrCmpCW"),
+    (157, "StrCmpICA"),
+    (158, "StrCmpICW"),
+    (176, "IUnknown_QueryService"),
+    (215, "SHAnsiToUnicode"),
+    (217, "SHUnicodeToAnsi"),
+    (219, "QISearch"),
+    (437, "IsOS"),
+];
+
+const TABLES: &[OrdinalTable] = &[
+    OrdinalTable {
+        dll: "ws2_32",
+        entries: WS2_32,
+    },
+    OrdinalTable {
+        dll: "oleaut32",
+        entries: OLEAUT32,
+    },
+    OrdinalTable {
+        dll: "comctl32",
+        entries: COMCTL32,
+    },
+    OrdinalTable {
+        dll: "shlwapi",
+        entries: SHLWAPI,
+    },
+];
+
+/// Maps `dll` (any case, with or without `.dll`) and an import ordinal to
+/// the export name it stands for. wsock32 shares ws2_32's table, since it
+/// forwards its Winsock 1.1 ordinals there unchanged.
+pub fn ordinal_name(dll: &str, ordinal: u16) -> Option<&'static str> {
+    let host = host_module(dll);
+    let table = TABLES.iter().find(|table| table.dll == host)?;
+    table
+        .entries
+        .iter()
+        .find(|(n, _)| *n == ordinal)
+        .map(|(_, name)| *name)
+}
@@ -0,0 +1,93 @@
+use std::sync::Mutex;
+
+use crate::unwind::{Frame, UnwindTable};
+
+/// Maps a code address to a printable location, or `None` when the
+/// address is not inside the image it describes.
+pub type Symbolizer = Box<dyn Fn(u64) -> Option<String> + Send>;
+
+/// Deepest backtrace a trace line gets.
+const MAX_FRAMES: usize = 32;
+/// Largest distance from the first frame's stack pointer an unwound frame
+/// may reach; anything further is a corrupt unwind, not a deeper caller.
+const MAX_STACK_SPAN: u64 = 64 * 1024 * 1024;
+
+/// A mapped image, as traces and error reports see it.
+pub struct CodeImage {
+    pub base: u64,
+    pub size: usize,
+    /// RVA and size of the exception directory (`.pdata`), zero when the
+    /// image has none. Backtraces are unwound through it.
+    pub exception_rva: u32,
+    pub exception_size: u32,
+    pub symbolizer: Symbolizer,
+}
+
+static IMAGES: Mutex<Vec<CodeImage>> = Mutex::new(Vec::new());
+
+/// Registers one loaded image. Loaders register every image they map so
+/// traces and error reports can name the calling code.
+pub fn register_image(image: CodeImage) {
+    IMAGES.lock().unwrap_or_else(|e| e.into_inner()).push(image);
+}
+
+/// Describes `address` with the first registered symbolizer that claims
+/// it, e.g. `app.exe!WinMain+0x42 (main.c:17)`.
+pub fn symbolize(address: u64) -> Option<String> {
+    IMAGES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find_map(|image| (image.symbolizer)(address))
+}
+
+/// Describes every frame from `frame` outward while the code belongs to a
+/// registered image, innermost first. The walk stops at the first frame
+/// outside them, which is host code such as the entry point bridge, or
+/// in an image without an exception directory. 32-bit calls only name
+/// their direct caller.
+///
+/// # Safety
+///
+/// `frame` must be the state of a live Win64 or 32-bit call on the
+/// current thread's stack, taken when image code called into waygate.
+pub(crate) unsafe fn backtrace(frame: Frame) -> Vec<String> {
+    let images = IMAGES.lock().unwrap_or_else(|e| e.into_inner());
+    let mut frames = Vec::new();
+    let mut frame = frame;
+    let bottom = frame.rsp;
+    while frames.len() < MAX_FRAMES {
+        let Some(image) = images
+            .iter()
+            .find(|image| frame.rip.wrapping_sub(image.base) < image.size as u64)
+        else {
+            break;
+        };
+        let Some(location) = (image.symbolizer)(frame.rip) else {
+            break;
+        };
+        frames.push(location);
+        // Without `.pdata` every function would pass for a leaf, and 32-bit
+        // images have none.
+        if image.exception_size == 0 || !cfg!(target_arch = "x86_64") {
+            break;
+        }
+        let table = UnwindTable {
+            base: image.base,
+            size: image.size,
+            rva: image.exception_rva,
+            len: image.exception_size,
+        };
+        match table.caller(&frame) {
+            Some(caller)
+                if caller.rsp > frame.rsp
+                    && caller.rsp - bottom <= MAX_STACK_SPAN
+                    && caller.rsp % 8 == 0 =>
+            {
+                frame = caller
+            }
+            _ => break,
+        }
+    }
+    frames
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::bridge::call_thread_start;
+use crate::dll::{attach_thread, detach_thread};
+use crate::tls::install_thread_tls;
+
+pub(crate) const CREATE_SUSPENDED: u64 = 0x4;
+pub(crate) const INFINITE: u32 = u32::MAX;
+pub(crate) const WAIT_OBJECT_0: u64 = 0;
+pub(crate) const WAIT_TIMEOUT: u64 = 0x102;
+
+/// Threads started by image code, by the fake handle `CreateThread` gave
+/// out. The join handle is taken by the first wait that sees the thread
+/// finish; the entry stays so later waits still see it signaled.
+static THREADS: Mutex<Option<HashMap<u64, Option<JoinHandle<u32>>>>> = Mutex::new(None);
+
+/// Thread handles and IDs share one counter; handles stay well clear of
+/// the small values other stubs return.
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(0x1000);
+
+/// Starts `start(param)` on a host thread. The thread gets its own TEB and
+/// TLS blocks and sends DLL_THREAD_ATTACH before the start routine runs,
+/// DLL_THREAD_DETACH after it returns. Returns the thread handle, or 0 when
+/// the host refuses to spawn a thread.
+pub(crate) fn create_thread(start: u64, param: u64, thread_id: *mut u32) -> u64 {
+    let handle = NEXT_THREAD.fetch_add(4, Ordering::Relaxed);
+    let spawned = std::thread::Builder::new()
+        .name(format!("win32-thread-{handle:#x}"))
+        .spawn(move || {
+            if let Err(err) = install_thread_tls().and_then(|()| attach_thread()) {
+                eprintln!("{err}");
+                return 1;
+            }
+            // SAFETY: image code passed its own thread routine to
+            // CreateThread, and the image stays mapped while it runs.
+            let code = unsafe { call_thread_start(start, param) }.unwrap_or_else(|err| {
+                eprintln!("{err}");
+                1
+            });
+            detach_thread();
+            code
+        });
+    let Ok(join) = spawned else {
+        return 0;
+    };
+    if !thread_id.is_null() {
+        // SAFETY: the program handed us a DWORD out-pointer.
+        unsafe { thread_id.write_unaligned(handle as u32) };
+    }
+    THREADS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(HashMap::new)
+        .insert(handle, Some(join));
+    handle
+}
+
+/// Waits up to `timeout` milliseconds for a thread from [`create_thread`].
+/// `None` when `handle` is not such a thread. Nothing reads exit codes, so
+/// the thread's return value is dropped.
+pub(crate) fn wait_thread(handle: u64, timeout: u32) -> Option<u64> {
+    let deadline =
+        (timeout != INFINITE).then(|| Instant::now() + Duration::from_millis(timeout.into()));
+    loop {
+        let mut threads = THREADS.lock().unwrap_or_else(|e| e.into_inner());
+        let state = threads.as_mut()?.get_mut(&handle)?;
+        match state {
+            None => return Some(WAIT_OBJECT_0),
+            Some(join) if join.is_finished() || deadline.is_none() => {
+                let join = state.take()?;
+                // Joined outside the lock so the thread can still create
+                // threads of its own while it finishes.
+                drop(threads);
+                let _ = join.join();
+                return Some(WAIT_OBJECT_0);
+            }
+            Some(_) => {}
+        }
+        drop(threads);
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Some(WAIT_TIMEOUT);
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
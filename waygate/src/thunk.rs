@@ -0,0 +1,182 @@
+use std::ffi::{c_int, c_void};
+use std::io;
+use std::ptr;
+
+use crate::bridge::{encode_stub, import_entry, missing_import_entry, STUB_SIZE};
+use crate::resolve;
+
+const PROT_READ: c_int = 0x1;
+const PROT_WRITE: c_int = 0x2;
+const PROT_EXEC: c_int = 0x4;
+const MAP_PRIVATE: c_int = 0x02;
+const MAP_ANONYMOUS: c_int = 0x20;
+const MAP_FAILED: *mut c_void = !0usize as *mut c_void;
+
+extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        offset: i64,
+    ) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, len: usize, prot: c_int) -> c_int;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+}
+
+/// One imported symbol as seen by the program: the DLL it was imported from
+/// and the name (or `#ordinal`) it was imported by.
+#[derive(Clone, Debug)]
+pub struct ThunkSlot {
+    pub module: String,
+    pub symbol: String,
+    /// Present for delay-loaded imports that should resolve on first call.
+    pub delay: Option<DelayLoad>,
+}
+
+/// Cells of a delay-load descriptor, as absolute addresses in the image.
+#[derive(Copy, Clone, Debug)]
+pub struct DelayLoad {
+    /// IAT cell rewritten with the resolved entry after the first call.
+    pub iat_cell: u64,
+    /// HMODULE cell filled in on first use, or 0 when there is none.
+    pub module_handle_cell: u64,
+}
+
+pub(crate) struct SlotState {
+    pub(crate) slot: ThunkSlot,
+    pub(crate) trace: bool,
+    /// Set on delay-load stubs: the entry their first call patches into
+    /// the IAT, so later calls skip the helper.
+    pub(crate) resolved_entry: Option<u64>,
+}
+
+/// Executable stubs that machine code can call through an IAT slot.
+///
+/// Each stub loads a pointer to its slot description into R10 (EAX on x86)
+/// and jumps to either the calling-convention bridge for implemented
+/// symbols or the missing-import trap, which reports `DLL!symbol` and
+/// terminates the process. Delay-loaded slots get a second, lazy stub that
+/// runs the delay-load helper before the call goes through.
+pub struct ThunkTable {
+    code: *mut u8,
+    code_len: usize,
+    slots: Box<[SlotState]>,
+    /// Per requested slot, the index of its lazy delay-load stub, if any.
+    lazy: Box<[Option<usize>]>,
+    requested: usize,
+}
+
+impl ThunkTable {
+    pub fn build(slots: Vec<ThunkSlot>, trace: bool) -> Result<Self, String> {
+        let requested = slots.len();
+        let stub_count = requested + slots.iter().filter(|s| s.delay.is_some()).count();
+        let code_len = (stub_count.max(1) * STUB_SIZE).div_ceil(0x1000) * 0x1000;
+
+        // SAFETY: fresh anonymous mapping, never aliases Rust-owned memory.
+        let code = unsafe {
+            mmap(
+                ptr::null_mut(),
+                code_len,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if code == MAP_FAILED {
+            return Err(format!(
+                "waygate: failed to allocate thunk page: {}",
+                io::Error::last_os_error()
+            ));
+        }
+
+        let stub_address = |idx: usize| code as u64 + (idx * STUB_SIZE) as u64;
+        let mut lazy = vec![None; requested];
+        let mut lazy_states = Vec::new();
+        for (idx, slot) in slots.iter().enumerate() {
+            if slot.delay.is_some() {
+                lazy[idx] = Some(requested + lazy_states.len());
+                lazy_states.push(SlotState {
+                    slot: slot.clone(),
+                    trace,
+                    resolved_entry: Some(stub_address(idx)),
+                });
+            }
+        }
+        let states: Box<[SlotState]> = slots
+            .into_iter()
+            .map(|slot| SlotState {
+                slot,
+                trace,
+                resolved_entry: None,
+            })
+            .chain(lazy_states)
+            .collect();
+
+        let table = Self {
+            code: code as *mut u8,
+            code_len,
+            slots: states,
+            lazy: lazy.into_boxed_slice(),
+            requested,
+        };
+        for (idx, state) in table.slots.iter().enumerate() {
+            let target = if resolve(&state.slot.module, &state.slot.symbol).is_some() {
+                import_entry()?
+            } else {
+                missing_import_entry()?
+            };
+            let stub = encode_stub(state as *const SlotState as u64, target);
+            // SAFETY: each stub fits in its STUB_SIZE window inside `code`.
+            unsafe {
+                ptr::copy_nonoverlapping(stub.as_ptr(), table.code.add(idx * STUB_SIZE), stub.len())
+            };
+        }
+
+        // SAFETY: `code`/`code_len` describe the mapping created above.
+        if unsafe { mprotect(code, code_len, PROT_READ | PROT_EXEC) } != 0 {
+            return Err(format!(
+                "waygate: failed to make thunk page executable: {}",
+                io::Error::last_os_error()
+            ));
+        }
+
+        Ok(table)
+    }
+
+    /// Number of slots passed to [`ThunkTable::build`].
+    pub fn len(&self) -> usize {
+        self.requested
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requested == 0
+    }
+
+    pub fn slot(&self, index: usize) -> Option<&ThunkSlot> {
+        self.slots[..self.requested]
+            .get(index)
+            .map(|state| &state.slot)
+    }
+
+    /// Address to store in the IAT for the slot at `index`: the lazy
+    /// delay-load stub for delay-loaded slots, the direct stub otherwise.
+    pub fn entry(&self, index: usize) -> Option<u64> {
+        let stub = match self.lazy.get(index)? {
+            Some(lazy) => *lazy,
+            None => index,
+        };
+        Some(self.code as u64 + (stub * STUB_SIZE) as u64)
+    }
+}
+
+impl Drop for ThunkTable {
+    fn drop(&mut self) {
+        // SAFETY: `code`/`code_len` describe the mapping created in `build`.
+        unsafe {
+            munmap(self.code as *mut c_void, self.code_len);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+use std::cell::RefCell;
+use std::sync::Mutex;
+
+/// `NT_TIB.Self`, the TEB's own address (`gs:[0x30]`).
+const TEB_SELF: usize = 0x30 / 8;
+/// `TEB.ThreadLocalStoragePointer`, the per-thread array of TLS blocks
+/// indexed by `_tls_index` (`gs:[0x58]`).
+const TEB_TLS_POINTER: usize = 0x58 / 8;
+/// Size of the zeroed TEB handed to image code: one page, as on Windows.
+const TEB_WORDS: usize = 0x1000 / 8;
+
+/// Smallest alignment of a TLS block, whatever the image asks for.
+const MIN_TLS_ALIGN: usize = 16;
+
+/// A module's static TLS template, at its mapped addresses.
+#[derive(Clone)]
+pub struct TlsTemplate {
+    pub module: String,
+    /// Initialized template bytes, copied into each thread's block.
+    pub start: u64,
+    pub size: usize,
+    /// Zero bytes following the template in each block.
+    pub zero_fill: usize,
+    pub alignment: usize,
+    /// The module's `_tls_index`, written when the index is allocated.
+    pub index_address: u64,
+}
+
+impl TlsTemplate {
+    fn layout(&self) -> Option<Layout> {
+        let size = self.size.checked_add(self.zero_fill)?.max(1);
+        Layout::from_size_align(size, self.alignment.max(MIN_TLS_ALIGN)).ok()
+    }
+}
+
+/// Templates of the loaded modules; a module's TLS index is its position.
+static TEMPLATES: Mutex<Vec<TlsTemplate>> = Mutex::new(Vec::new());
+
+/// The TEB and TLS blocks of one thread running image code.
+struct ThreadTls {
+    teb: Box<[u64; TEB_WORDS]>,
+    blocks: Vec<*mut u8>,
+    layouts: Vec<Layout>,
+}
+
+impl ThreadTls {
+    /// Appends a fresh copy of `template` and republishes the block array,
+    /// which may have moved.
+    unsafe fn add_block(&mut self, template: &TlsTemplate) -> Result<(), String> {
+        let layout = template
+            .layout()
+            .ok_or_else(|| format!("waygate: {}: invalid TLS template size", template.module))?;
+        let block = alloc_zeroed(layout);
+        if block.is_null() {
+            return Err(format!(
+                "waygate: {}: cannot allocate a {}-byte TLS block",
+                template.module,
+                layout.size()
+            ));
+        }
+        std::ptr::copy_nonoverlapping(template.start as *const u8, block, template.size);
+        self.blocks.push(block);
+        self.layouts.push(layout);
+        self.teb[TEB_TLS_POINTER] = self.blocks.as_ptr() as u64;
+        Ok(())
+    }
+}
+
+impl Drop for ThreadTls {
+    fn drop(&mut self) {
+        for (block, layout) in self.blocks.iter().zip(&self.layouts) {
+            // SAFETY: each block was allocated by `add_block` with this layout.
+            unsafe { dealloc(*block, *layout) };
+        }
+    }
+}
+
+thread_local! {
+    static THREAD_TLS: RefCell<Option<ThreadTls>> = const { RefCell::new(None) };
+}
+
+/// Gives the calling thread a minimal TEB, with `NT_TIB.Self` and
+/// `ThreadLocalStoragePointer` filled in, and a private copy of every
+/// registered TLS template. Compiler-generated TLS accesses
+/// (`gs:[0x58]` + `_tls_index`) then reach this thread's variables. Does
+/// nothing when the thread already has one.
+pub fn install_thread_tls() -> Result<(), String> {
+    THREAD_TLS.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        if cell.is_some() {
+            return Ok(());
+        }
+        let mut tls = ThreadTls {
+            teb: Box::new([0; TEB_WORDS]),
+            blocks: Vec::new(),
+            layouts: Vec::new(),
+        };
+        tls.teb[TEB_SELF] = tls.teb.as_ptr() as u64;
+        let templates = TEMPLATES.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        for template in &templates {
+            // SAFETY: registered templates point into images that stay
+            // mapped for the life of the process.
+            unsafe { tls.add_block(template) }?;
+        }
+        tls.teb[TEB_TLS_POINTER] = tls.blocks.as_ptr() as u64;
+        arch::set_teb(tls.teb.as_ptr() as u64)?;
+        *cell = Some(tls);
+        Ok(())
+    })
+}
+
+/// True when the calling thread was set up by [`install_thread_tls`], i.e.
+/// it runs image code rather than replaying a plan.
+pub(crate) fn has_thread_tls() -> bool {
+    THREAD_TLS.with(|cell| cell.borrow().is_some())
+}
+
+/// Allocates the next TLS index for a module, writes it to the module's
+/// `_tls_index` and gives the calling thread its copy of the template.
+/// Threads created afterwards copy it when they start.
+///
+/// # Safety
+///
+/// `template` must describe readable memory of a mapped image whose
+/// `index_address` is writable and stays mapped for the life of the process.
+pub unsafe fn allocate_tls_index(template: TlsTemplate) -> Result<u32, String> {
+    let mut templates = TEMPLATES.lock().unwrap_or_else(|e| e.into_inner());
+    let index = templates.len() as u32;
+    (template.index_address as *mut u32).write_unaligned(index);
+    THREAD_TLS.with(|cell| match cell.borrow_mut().as_mut() {
+        Some(tls) => tls.add_block(&template),
+        None => Ok(()),
+    })?;
+    templates.push(template);
+    Ok(index)
+}
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    const SYS_ARCH_PRCTL: i64 = 158;
+    const ARCH_SET_GS: i64 = 0x1001;
+
+    extern "C" {
+        fn syscall(number: i64, ...) -> i64;
+    }
+
+    /// Points the GS base at the TEB, as Windows does on x64. Linux leaves
+    /// GS unused in user space (thread-locals live behind FS).
+    pub fn set_teb(teb: u64) -> Result<(), String> {
+        // SAFETY: ARCH_SET_GS only changes this thread's GS base.
+        match unsafe { syscall(SYS_ARCH_PRCTL, ARCH_SET_GS, teb) } {
+            0 => Ok(()),
+            _ => Err(format!(
+                "waygate: cannot point GS at the TEB: {}",
+                std::io::Error::last_os_error()
+            )),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod arch {
+    pub fn set_teb(_teb: u64) -> Result<(), String> {
+        Err("waygate: a TEB can only be installed on an x86_64 host".to_string())
+    }
+}
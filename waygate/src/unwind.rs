@@ -0,0 +1,195 @@
+/// Size of one `RUNTIME_FUNCTION` entry in the exception directory.
+const RUNTIME_FUNCTION_SIZE: u32 = 12;
+/// Unwind info continues in another function's entry (`UNW_FLAG_CHAININFO`).
+const UNW_FLAG_CHAININFO: u8 = 0x4;
+/// Longest chain of unwind infos followed for one function.
+const MAX_CHAINED: usize = 32;
+
+const UWOP_PUSH_NONVOL: u16 = 0;
+const UWOP_ALLOC_LARGE: u16 = 1;
+const UWOP_ALLOC_SMALL: u16 = 2;
+const UWOP_SET_FPREG: u16 = 3;
+const UWOP_SAVE_NONVOL: u16 = 4;
+const UWOP_SAVE_NONVOL_FAR: u16 = 5;
+/// `UWOP_EPILOG` in version 2 unwind info, `UWOP_SAVE_XMM` before; both
+/// take two slots.
+const UWOP_EPILOG: u16 = 6;
+const UWOP_SPARE_CODE: u16 = 7;
+const UWOP_SAVE_XMM128: u16 = 8;
+const UWOP_SAVE_XMM128_FAR: u16 = 9;
+const UWOP_PUSH_MACHFRAME: u16 = 10;
+
+/// RBP's number in unwind codes.
+const RBP: usize = 5;
+
+/// Registers of one Win64 stack frame, as far as the unwinder knows them.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Frame {
+    /// Where the frame's code continues: a return address.
+    pub(crate) rip: u64,
+    /// RSP once the call that left the frame has returned.
+    pub(crate) rsp: u64,
+    /// General-purpose registers by unwind-code number (RAX = 0 .. R15 =
+    /// 15); only nonvolatile ones restored on the way out are known.
+    regs: [Option<u64>; 16],
+}
+
+impl Frame {
+    pub(crate) fn new(rip: u64, rsp: u64, rbp: u64) -> Self {
+        let mut regs = [None; 16];
+        regs[RBP] = Some(rbp);
+        Self { rip, rsp, regs }
+    }
+}
+
+/// A mapped image's exception directory (`.pdata`), which holds the
+/// unwind info of every non-leaf function.
+pub(crate) struct UnwindTable {
+    pub(crate) base: u64,
+    pub(crate) size: usize,
+    pub(crate) rva: u32,
+    pub(crate) len: u32,
+}
+
+impl UnwindTable {
+    /// The frame `frame`'s function returns into, undoing its prolog the
+    /// way RtlVirtualUnwind does. Functions without an entry are leaves
+    /// whose return address sits at RSP. `frame.rip` must lie inside this
+    /// image, and `frame.rsp` on the stack of the current thread.
+    pub(crate) unsafe fn caller(&self, frame: &Frame) -> Option<Frame> {
+        let rip_rva = u32::try_from(frame.rip.checked_sub(self.base)?).ok()?;
+        // A return address points past the call, which may be the last
+        // instruction of the function.
+        let Some(mut info) = self.function(rip_rva.checked_sub(1)?) else {
+            return pop_return(frame.rsp, frame.regs);
+        };
+        let mut rsp = frame.rsp;
+        let mut regs = frame.regs;
+        for _ in 0..MAX_CHAINED {
+            let flags = self.read_u8(info)? >> 3;
+            let count = u32::from(self.read_u8(info.checked_add(2)?)?);
+            let frame_byte = self.read_u8(info.checked_add(3)?)?;
+            let frame_reg = usize::from(frame_byte & 0xF);
+            let frame_offset = u64::from(frame_byte >> 4) * 16;
+            let slot = |index: u32| self.read_u16(info.checked_add(4 + 2 * index)?);
+            // Return addresses never sit inside a prolog, so every code of
+            // the function applies.
+            let mut index = 0;
+            while index < count {
+                let code = slot(index)?;
+                let op = (code >> 8) & 0xF;
+                let op_info = usize::from(code >> 12);
+                index += match op {
+                    UWOP_PUSH_NONVOL => {
+                        regs[op_info] = Some(read_stack(rsp));
+                        rsp = rsp.checked_add(8)?;
+                        1
+                    }
+                    UWOP_ALLOC_LARGE if op_info == 0 => {
+                        rsp = rsp.checked_add(u64::from(slot(index + 1)?) * 8)?;
+                        2
+                    }
+                    UWOP_ALLOC_LARGE => {
+                        let size = u64::from(slot(index + 1)?) | u64::from(slot(index + 2)?) << 16;
+                        rsp = rsp.checked_add(size)?;
+                        3
+                    }
+                    UWOP_ALLOC_SMALL => {
+                        rsp = rsp.checked_add(op_info as u64 * 8 + 8)?;
+                        1
+                    }
+                    UWOP_SET_FPREG => {
+                        rsp = regs[frame_reg]?.checked_sub(frame_offset)?;
+                        1
+                    }
+                    UWOP_SAVE_NONVOL => {
+                        let offset = u64::from(slot(index + 1)?) * 8;
+                        regs[op_info] = Some(read_stack(rsp.checked_add(offset)?));
+                        2
+                    }
+                    UWOP_SAVE_NONVOL_FAR => {
+                        let offset =
+                            u64::from(slot(index + 1)?) | u64::from(slot(index + 2)?) << 16;
+                        regs[op_info] = Some(read_stack(rsp.checked_add(offset)?));
+                        3
+                    }
+                    UWOP_EPILOG | UWOP_SAVE_XMM128 => 2,
+                    UWOP_SPARE_CODE | UWOP_SAVE_XMM128_FAR => 3,
+                    UWOP_PUSH_MACHFRAME => {
+                        // An interrupt or exception frame: RIP and RSP come
+                        // from the frame the processor pushed.
+                        let frame = rsp.checked_add(8 * op_info as u64)?;
+                        let rip = read_stack(frame);
+                        let rsp = read_stack(frame.checked_add(24)?);
+                        return Some(Frame { rip, rsp, regs });
+                    }
+                    _ => return None,
+                };
+            }
+            if flags & UNW_FLAG_CHAININFO == 0 {
+                return pop_return(rsp, regs);
+            }
+            // The chained RUNTIME_FUNCTION follows the codes, which are
+            // padded to an even count.
+            let chained = info.checked_add(4 + 2 * ((count + 1) & !1))?;
+            info = self.read_u32(chained.checked_add(8)?)?;
+        }
+        None
+    }
+
+    /// RVA of the unwind info of the function containing `rva`, found by
+    /// binary search over the entries, which the linker sorts by address.
+    fn function(&self, rva: u32) -> Option<u32> {
+        let count = self.len / RUNTIME_FUNCTION_SIZE;
+        let (mut low, mut high) = (0, count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let entry = self.rva.checked_add(mid * RUNTIME_FUNCTION_SIZE)?;
+            let begin = self.read_u32(entry)?;
+            let end = self.read_u32(entry.checked_add(4)?)?;
+            if rva < begin {
+                high = mid;
+            } else if rva >= end {
+                low = mid + 1;
+            } else {
+                return self.read_u32(entry.checked_add(8)?);
+            }
+        }
+        None
+    }
+
+    fn read_u8(&self, rva: u32) -> Option<u8> {
+        self.read::<1>(rva).map(|b| b[0])
+    }
+
+    fn read_u16(&self, rva: u32) -> Option<u16> {
+        self.read(rva).map(u16::from_le_bytes)
+    }
+
+    fn read_u32(&self, rva: u32) -> Option<u32> {
+        self.read(rva).map(u32::from_le_bytes)
+    }
+
+    fn read<const N: usize>(&self, rva: u32) -> Option<[u8; N]> {
+        let rva = rva as usize;
+        if rva.checked_add(N)? > self.size {
+            return None;
+        }
+        // SAFETY: the range lies inside the mapped image; `.pdata` and the
+        // unwind info it points at live in readable sections.
+        Some(unsafe { ((self.base as usize + rva) as *const [u8; N]).read_unaligned() })
+    }
+}
+
+/// Leaves a frame whose return address is at `rsp`.
+unsafe fn pop_return(rsp: u64, regs: [Option<u64>; 16]) -> Option<Frame> {
+    Some(Frame {
+        rip: read_stack(rsp),
+        rsp: rsp.checked_add(8)?,
+        regs,
+    })
+}
+
+unsafe fn read_stack(address: u64) -> u64 {
+    (address as usize as *const u64).read_unaligned()
+}
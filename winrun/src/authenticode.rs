@@ -0,0 +1,499 @@
+use crate::digest::{Sha1, Sha256};
+use crate::pe::{read_u16, read_u32, PeContext, IMAGE_DIRECTORY_ENTRY_SECURITY};
+
+/// `WIN_CERTIFICATE.wCertificateType` for PKCS#7 SignedData.
+const WIN_CERT_TYPE_PKCS_SIGNED_DATA: u16 = 2;
+
+const OID_SIGNED_DATA: &str = "1.2.840.113549.1.7.2";
+const OID_SPC_INDIRECT_DATA: &str = "1.3.6.1.4.1.311.2.1.4";
+const OID_MESSAGE_DIGEST: &str = "1.2.840.113549.1.9.4";
+const OID_SIGNING_TIME: &str = "1.2.840.113549.1.9.5";
+const OID_COUNTERSIGNATURE: &str = "1.2.840.113549.1.9.6";
+const OID_RFC3161_TIMESTAMP: &str = "1.3.6.1.4.1.311.3.3.1";
+const OID_SHA1: &str = "1.3.14.3.2.26";
+const OID_SHA256: &str = "2.16.840.1.101.3.4.2.1";
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_UTF8_STRING: u8 = 0x0C;
+const TAG_PRINTABLE_STRING: u8 = 0x13;
+const TAG_T61_STRING: u8 = 0x14;
+const TAG_IA5_STRING: u8 = 0x16;
+const TAG_UTC_TIME: u8 = 0x17;
+const TAG_GENERALIZED_TIME: u8 = 0x18;
+const TAG_BMP_STRING: u8 = 0x1E;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_SET: u8 = 0x31;
+const TAG_CONTEXT_0: u8 = 0xA0;
+const TAG_CONTEXT_1: u8 = 0xA1;
+
+/// Attribute types shown in distinguished names.
+const NAME_ATTRIBUTES: &[(&str, &str)] = &[
+    ("2.5.4.3", "CN"),
+    ("2.5.4.6", "C"),
+    ("2.5.4.7", "L"),
+    ("2.5.4.8", "ST"),
+    ("2.5.4.10", "O"),
+    ("2.5.4.11", "OU"),
+    ("1.2.840.113549.1.9.1", "E"),
+];
+
+/// One DER element: its tag and content bytes.
+#[derive(Copy, Clone)]
+struct Der<'a> {
+    tag: u8,
+    content: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    /// Reads the element at the start of `data`, returning it and what
+    /// follows. Only definite lengths are accepted, as DER requires.
+    fn read(data: &'a [u8]) -> Option<(Self, &'a [u8])> {
+        let tag = *data.first()?;
+        let first = *data.get(1)? as usize;
+        let (len, header) = if first < 0x80 {
+            (first, 2)
+        } else {
+            let count = first & 0x7F;
+            if count == 0 || count > 4 {
+                return None;
+            }
+            let len = data
+                .get(2..2 + count)?
+                .iter()
+                .fold(0usize, |len, b| (len << 8) | *b as usize);
+            (len, 2 + count)
+        };
+        let content = data.get(header..header.checked_add(len)?)?;
+        Some((Self { tag, content }, &data[header + len..]))
+    }
+
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        Self::read(data).map(|(der, _)| der)
+    }
+
+    /// Child elements of a constructed element.
+    fn children(&self) -> Option<Vec<Der<'a>>> {
+        let mut rest = self.content;
+        let mut children = Vec::new();
+        while !rest.is_empty() {
+            let (child, after) = Der::read(rest)?;
+            children.push(child);
+            rest = after;
+        }
+        Some(children)
+    }
+
+    fn expect(self, tag: u8) -> Option<Self> {
+        (self.tag == tag).then_some(self)
+    }
+
+    fn oid(&self) -> Option<String> {
+        if self.tag != TAG_OID || self.content.is_empty() {
+            return None;
+        }
+        let mut arcs = Vec::new();
+        let mut value = 0u64;
+        for byte in self.content {
+            value = (value << 7) | (byte & 0x7F) as u64;
+            if byte & 0x80 == 0 {
+                arcs.push(value);
+                value = 0;
+            }
+        }
+        let first = arcs.first().copied()?;
+        let (a, b) = match first {
+            0..=39 => (0, first),
+            40..=79 => (1, first - 40),
+            _ => (2, first - 80),
+        };
+        let mut text = format!("{a}.{b}");
+        for arc in &arcs[1..] {
+            text.push_str(&format!(".{arc}"));
+        }
+        Some(text)
+    }
+
+    fn text(&self) -> Option<String> {
+        match self.tag {
+            TAG_UTF8_STRING | TAG_PRINTABLE_STRING | TAG_IA5_STRING => {
+                Some(String::from_utf8_lossy(self.content).into_owned())
+            }
+            // Teletex in practice carries Latin-1.
+            TAG_T61_STRING => Some(self.content.iter().map(|b| *b as char).collect()),
+            TAG_BMP_STRING => {
+                let units: Vec<u16> = self
+                    .content
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                    .collect();
+                Some(String::from_utf16_lossy(&units))
+            }
+            _ => None,
+        }
+    }
+
+    /// UTCTime or GeneralizedTime as `YYYY-MM-DD HH:MM:SS UTC`.
+    fn time(&self) -> Option<String> {
+        let text = std::str::from_utf8(self.content).ok()?;
+        let digits = match self.tag {
+            TAG_UTC_TIME => {
+                let year: u32 = text.get(..2)?.parse().ok()?;
+                let century = if year < 50 { "20" } else { "19" };
+                format!("{century}{}", text.get(..12)?)
+            }
+            TAG_GENERALIZED_TIME => text.get(..14)?.to_string(),
+            _ => return None,
+        };
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        Some(format!(
+            "{}-{}-{} {}:{}:{} UTC",
+            &digits[..4],
+            &digits[4..6],
+            &digits[6..8],
+            &digits[8..10],
+            &digits[10..12],
+            &digits[12..14]
+        ))
+    }
+}
+
+/// Hash algorithm of the signed image digest.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DigestAlgorithm {
+    Sha1,
+    Sha256,
+    Other(String),
+}
+
+impl DigestAlgorithm {
+    fn from_oid(oid: &str) -> Self {
+        match oid {
+            OID_SHA1 => DigestAlgorithm::Sha1,
+            OID_SHA256 => DigestAlgorithm::Sha256,
+            other => DigestAlgorithm::Other(other.to_string()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            DigestAlgorithm::Sha1 => "SHA-1",
+            DigestAlgorithm::Sha256 => "SHA-256",
+            DigestAlgorithm::Other(oid) => oid,
+        }
+    }
+}
+
+/// Where a signer's signing time came from.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TimeSource {
+    /// `signingTime` among the signer's own authenticated attributes,
+    /// which the signer's clock supplies and nobody vouches for.
+    Signer,
+    /// A PKCS#9 countersignature from a timestamping authority.
+    Countersignature,
+    /// An RFC 3161 timestamp token.
+    Rfc3161,
+}
+
+impl TimeSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            TimeSource::Signer => "signer's clock, not timestamped",
+            TimeSource::Countersignature => "countersignature",
+            TimeSource::Rfc3161 => "RFC 3161 timestamp",
+        }
+    }
+}
+
+/// Whether a signer's `messageDigest` attribute is the hash of the signed
+/// SpcIndirectDataContent, which is what ties its signature to the image
+/// digest.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ContentCheck {
+    Matches,
+    Mismatch,
+    /// No authenticated `messageDigest` attribute: nothing binds the
+    /// signature to the content.
+    Missing,
+    /// The signer's digest algorithm is not one winrun implements.
+    Unsupported(DigestAlgorithm),
+}
+
+#[derive(Clone, Debug)]
+pub struct Signer {
+    /// Subject of the certificate matching the signer's issuer and serial,
+    /// when the signature embeds it.
+    pub subject: Option<String>,
+    pub issuer: String,
+    pub serial: Vec<u8>,
+    pub signing_time: Option<(String, TimeSource)>,
+    pub content: ContentCheck,
+}
+
+/// The Authenticode signature from the certificate table.
+#[derive(Clone, Debug)]
+pub struct Signature {
+    pub digest_algorithm: DigestAlgorithm,
+    /// Image hash the publisher signed.
+    pub signed_digest: Vec<u8>,
+    pub signers: Vec<Signer>,
+    pub certificate_count: usize,
+}
+
+impl Signature {
+    /// True when some signer's signed attributes do not cover the embedded
+    /// image digest, so that digest proves nothing about the publisher.
+    pub fn is_invalid(&self) -> bool {
+        self.signers.iter().any(|signer| {
+            matches!(
+                signer.content,
+                ContentCheck::Mismatch | ContentCheck::Missing
+            )
+        })
+    }
+}
+
+/// Reads the first PKCS#7 entry of the certificate table (data directory
+/// 4, whose address is a file offset rather than an RVA). `Ok(None)` means
+/// the image is unsigned.
+pub fn parse_signature(bytes: &[u8], pe: &PeContext) -> Result<Option<Signature>, String> {
+    let Some(dir) = pe.directory(IMAGE_DIRECTORY_ENTRY_SECURITY) else {
+        return Ok(None);
+    };
+    let table = bytes
+        .get(dir.rva as usize..dir.rva as usize + dir.size as usize)
+        .ok_or_else(|| "certificate table lies outside the file".to_string())?;
+    let mut offset = 0;
+    while offset + 8 <= table.len() {
+        let length = read_u32(table, offset).unwrap_or(0) as usize;
+        let kind = read_u16(table, offset + 6).unwrap_or(0);
+        if length < 8 {
+            break;
+        }
+        if kind == WIN_CERT_TYPE_PKCS_SIGNED_DATA {
+            let blob = table
+                .get(offset + 8..offset + length)
+                .ok_or_else(|| "truncated WIN_CERTIFICATE entry".to_string())?;
+            return parse_signed_data(blob)
+                .map(Some)
+                .ok_or_else(|| "malformed PKCS#7 SignedData".to_string());
+        }
+        offset += (length + 7) & !7;
+    }
+    Err("certificate table has no PKCS#7 signature".to_string())
+}
+
+fn parse_signed_data(blob: &[u8]) -> Option<Signature> {
+    let content_info = Der::parse(blob)?.expect(TAG_SEQUENCE)?.children()?;
+    if content_info.first()?.oid()? != OID_SIGNED_DATA {
+        return None;
+    }
+    let signed_data = Der::parse(content_info.get(1)?.expect(TAG_CONTEXT_0)?.content)?
+        .expect(TAG_SEQUENCE)?
+        .children()?;
+
+    // encapContentInfo: SpcIndirectDataContent { data, DigestInfo }.
+    let encapsulated = signed_data.get(2)?.children()?;
+    if encapsulated.first()?.oid()? != OID_SPC_INDIRECT_DATA {
+        return None;
+    }
+    // PKCS#7 v1.5 hashes the content octets without their tag and length.
+    let indirect_der = Der::parse(encapsulated.get(1)?.expect(TAG_CONTEXT_0)?.content)?;
+    let indirect = indirect_der.children()?;
+    let digest_info = indirect.get(1)?.children()?;
+    let algorithm = digest_info.first()?.children()?.first()?.oid()?;
+    let signed_digest = digest_info
+        .get(1)?
+        .expect(TAG_OCTET_STRING)?
+        .content
+        .to_vec();
+
+    let certificates = signed_data
+        .iter()
+        .find(|der| der.tag == TAG_CONTEXT_0)
+        .map(|der| der.children())
+        .unwrap_or(Some(Vec::new()))?;
+    let signer_infos = signed_data.last()?.expect(TAG_SET)?.children()?;
+    let signers = signer_infos
+        .iter()
+        .map(|info| parse_signer(info, &certificates, indirect_der.content))
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(Signature {
+        digest_algorithm: DigestAlgorithm::from_oid(&algorithm),
+        signed_digest,
+        signers,
+        certificate_count: certificates.len(),
+    })
+}
+
+fn parse_signer(info: &Der, certificates: &[Der], content: &[u8]) -> Option<Signer> {
+    let fields = info.children()?;
+    let algorithm = DigestAlgorithm::from_oid(&fields.get(2)?.children()?.first()?.oid()?);
+    let issuer_and_serial = fields.get(1)?.children()?;
+    let issuer = issuer_and_serial.first()?;
+    let serial = issuer_and_serial.get(1)?.expect(TAG_INTEGER)?;
+    let subject = certificates
+        .iter()
+        .find_map(|cert| certificate_subject(cert, issuer, &serial));
+
+    let authenticated = fields.iter().find(|der| der.tag == TAG_CONTEXT_0);
+    let unauthenticated = fields.iter().find(|der| der.tag == TAG_CONTEXT_1);
+    let signing_time = unauthenticated
+        .and_then(|attrs| timestamp_time(attrs))
+        .or_else(|| {
+            authenticated
+                .and_then(|attrs| attribute(attrs, OID_SIGNING_TIME))
+                .and_then(|value| value.time())
+                .map(|time| (time, TimeSource::Signer))
+        });
+    let message_digest = authenticated
+        .and_then(|attrs| attribute(attrs, OID_MESSAGE_DIGEST))
+        .and_then(|value| value.expect(TAG_OCTET_STRING));
+    let content = match (message_digest, digest(&algorithm, &[content])) {
+        (None, _) => ContentCheck::Missing,
+        (Some(_), None) => ContentCheck::Unsupported(algorithm),
+        (Some(value), Some(hash)) if value.content == hash.as_slice() => ContentCheck::Matches,
+        (Some(_), Some(_)) => ContentCheck::Mismatch,
+    };
+
+    Some(Signer {
+        subject,
+        issuer: format_name(issuer)?,
+        serial: serial.content.to_vec(),
+        signing_time,
+        content,
+    })
+}
+
+/// Signing time vouched for by a timestamping authority, from the
+/// signer's unauthenticated attributes.
+fn timestamp_time(attrs: &Der) -> Option<(String, TimeSource)> {
+    if let Some(countersignature) = attribute(attrs, OID_COUNTERSIGNATURE) {
+        let time = countersignature
+            .children()?
+            .iter()
+            .find(|der| der.tag == TAG_CONTEXT_0)
+            .and_then(|attrs| attribute(attrs, OID_SIGNING_TIME))?
+            .time()?;
+        return Some((time, TimeSource::Countersignature));
+    }
+    // The token is itself a SignedData whose content is a TSTInfo with
+    // genTime as its only GeneralizedTime.
+    let token = attribute(attrs, OID_RFC3161_TIMESTAMP)?.children()?;
+    let signed_data = Der::parse(token.get(1)?.expect(TAG_CONTEXT_0)?.content)?.children()?;
+    let encapsulated = signed_data.get(2)?.children()?;
+    let tst_octets = Der::parse(encapsulated.get(1)?.expect(TAG_CONTEXT_0)?.content)?
+        .expect(TAG_OCTET_STRING)?;
+    let time = Der::parse(tst_octets.content)?
+        .children()?
+        .iter()
+        .find(|der| der.tag == TAG_GENERALIZED_TIME)?
+        .time()?;
+    Some((time, TimeSource::Rfc3161))
+}
+
+/// First value of attribute `oid` in a `[0]`/`[1]` attribute set.
+fn attribute<'a>(attrs: &Der<'a>, oid: &str) -> Option<Der<'a>> {
+    attrs.children()?.into_iter().find_map(|attr| {
+        let parts = attr.children()?;
+        if parts.first()?.oid()? != oid {
+            return None;
+        }
+        parts.get(1)?.children()?.into_iter().next()
+    })
+}
+
+/// Subject of `cert` if its issuer and serial are the given ones.
+fn certificate_subject(cert: &Der, issuer: &Der, serial: &Der) -> Option<String> {
+    let tbs = cert.children()?.into_iter().next()?.children()?;
+    // Skip the optional explicit `[0] version`.
+    let fields = match tbs.first()?.tag {
+        TAG_CONTEXT_0 => &tbs[1..],
+        _ => &tbs[..],
+    };
+    let (cert_serial, cert_issuer, subject) = (fields.first()?, fields.get(2)?, fields.get(4)?);
+    if cert_serial.content != serial.content || cert_issuer.content != issuer.content {
+        return None;
+    }
+    format_name(subject)
+}
+
+/// X.501 name in RFC 4514 order (most specific first): `CN=..., O=..., C=US`.
+fn format_name(name: &Der) -> Option<String> {
+    let mut parts = Vec::new();
+    for rdn in name.expect(TAG_SEQUENCE)?.children()? {
+        for attr in rdn.children()? {
+            let fields = attr.children()?;
+            let oid = fields.first()?.oid()?;
+            let value = fields.get(1)?.text().unwrap_or_default();
+            let key = NAME_ATTRIBUTES
+                .iter()
+                .find(|(id, _)| *id == oid)
+                .map_or(oid.as_str(), |(_, key)| key);
+            parts.push(format!("{key}={value}"));
+        }
+    }
+    parts.reverse();
+    Some(parts.join(", "))
+}
+
+/// Serial number in hex, without the sign byte DER adds to keep large
+/// serials positive.
+pub fn format_serial(serial: &[u8]) -> String {
+    let trimmed = match serial {
+        [0, rest @ ..] if !rest.is_empty() => rest,
+        _ => serial,
+    };
+    trimmed.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The Authenticode image hash: the whole file except the CheckSum field,
+/// the certificate table's directory entry and the certificate table
+/// itself. `None` when the algorithm is not one winrun implements.
+pub fn image_hash(bytes: &[u8], pe: &PeContext, algorithm: &DigestAlgorithm) -> Option<Vec<u8>> {
+    let entry = pe.data_directory_offset + IMAGE_DIRECTORY_ENTRY_SECURITY * 8;
+    let table =
+        pe.directory(IMAGE_DIRECTORY_ENTRY_SECURITY)
+            .map_or(bytes.len()..bytes.len(), |dir| {
+                let start = (dir.rva as usize).min(bytes.len());
+                start..(start + dir.size as usize).min(bytes.len())
+            });
+    let mut skip = [
+        pe.checksum_offset..pe.checksum_offset + 4,
+        entry..entry + 8,
+        table,
+    ];
+    skip.sort_by_key(|range| range.start);
+
+    let mut ranges = Vec::new();
+    let mut position = 0;
+    for range in skip {
+        ranges.push(&bytes[position..range.start.max(position)]);
+        position = position.max(range.end);
+    }
+    ranges.push(&bytes[position.min(bytes.len())..]);
+    digest(algorithm, &ranges)
+}
+
+/// Hash of `ranges` in order, `None` for algorithms winrun does not
+/// implement.
+fn digest(algorithm: &DigestAlgorithm, ranges: &[&[u8]]) -> Option<Vec<u8>> {
+    match algorithm {
+        DigestAlgorithm::Sha1 => {
+            let mut hasher = Sha1::new();
+            ranges.iter().for_each(|range| hasher.update(range));
+            Some(hasher.finish())
+        }
+        DigestAlgorithm::Sha256 => {
+            let mut hasher = Sha256::new();
+            ranges.iter().for_each(|range| hasher.update(range));
+            Some(hasher.finish())
+        }
+        DigestAlgorithm::Other(_) => None,
+    }
+}
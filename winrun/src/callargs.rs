@@ -0,0 +1,433 @@
+use std::collections::HashSet;
+
+use waygate::{Arg, ArgKind, Export};
+
+use crate::callsites::{CallKind, CallSite, Decoded};
+use crate::pe::PeContext;
+use crate::x86::Instruction;
+
+/// Instructions looked at before a call site.
+const MAX_LOOKBACK: usize = 32;
+/// Win64 passes the first four arguments in RCX, RDX, R8 and R9, and the
+/// rest on the stack above the 32-byte home area.
+const ARG_REGISTERS_64: [u8; 4] = [1, 2, 8, 9];
+const HOME_AREA_64: i64 = 0x20;
+/// RSP/ESP.
+const STACK_POINTER: u8 = 4;
+/// Longest string literal read for a string argument.
+const MAX_STRING: usize = 256;
+
+/// Where an argument sits when the call executes.
+#[derive(Copy, Clone)]
+enum Location {
+    Register(u8),
+    /// `[rsp + disp]` / `[esp + disp]`.
+    Stack(i64),
+}
+
+/// Recovers the constant arguments of the call at `site` from the
+/// instructions leading up to it: immediates moved into argument registers
+/// or stack slots, pushed immediates, zeroed registers and `lea`/`push` of
+/// string literals. Only the straight-line code since the previous call,
+/// return, jump or conditional branch is considered, and none before an
+/// instruction in `targets` (from [`branch_targets`]), since other paths
+/// join there. Returns `name=value` per parameter of `export`, `name=?` for
+/// those that stay unknown, or nothing when no argument was recovered.
+///
+/// [`branch_targets`]: crate::callsites::branch_targets
+pub fn recover_args(
+    bytes: &[u8],
+    pe: &PeContext,
+    code: &[Vec<Decoded>],
+    targets: &HashSet<u32>,
+    site: &CallSite,
+    export: &Export,
+) -> Vec<String> {
+    if site.kind == CallKind::Jump || export.params.is_empty() {
+        return Vec::new();
+    }
+    let Some((section, index)) = code.iter().find_map(|section| {
+        let index = section
+            .binary_search_by_key(&site.rva, |decoded| decoded.rva)
+            .ok()?;
+        Some((section, index))
+    }) else {
+        return Vec::new();
+    };
+    let before = &section[index.saturating_sub(MAX_LOOKBACK)..index];
+    let after_exit = before.iter().rposition(ends_block).map_or(0, |i| i + 1);
+    let join = before
+        .iter()
+        .rposition(|decoded| targets.contains(&decoded.rva))
+        .unwrap_or(0);
+    let window = &before[after_exit.max(join)..];
+
+    let values: Vec<Option<u64>> = if pe.is_pe64 {
+        (0..export.params.len())
+            .map(|i| {
+                let location = match ARG_REGISTERS_64.get(i) {
+                    Some(reg) => Location::Register(*reg),
+                    None => Location::Stack(HOME_AREA_64 + 8 * (i as i64 - 4)),
+                };
+                value_at(window, window.len(), location, pe)
+            })
+            .collect()
+    } else {
+        stack_args_32(window, export.params.len(), pe)
+    };
+    if values.iter().all(Option::is_none) {
+        return Vec::new();
+    }
+    export
+        .params
+        .iter()
+        .zip(values)
+        .map(|(param, value)| match value {
+            Some(value) => format!(
+                "{}={}",
+                param.name,
+                typed_value(bytes, pe, param.kind, value)
+            ),
+            None => format!("{}=?", param.name),
+        })
+        .collect()
+}
+
+/// Control flow that leaves the block, possibly only on some paths, or a
+/// call that clobbers what came before it.
+fn ends_block(decoded: &Decoded) -> bool {
+    let insn = &decoded.insn;
+    let extension = insn.modrm.map(|modrm| modrm.reg & 7);
+    match insn.opcode {
+        0x70..=0x7F | 0x0F80..=0x0F8F | 0xE0..=0xE3 => true,
+        0xC2 | 0xC3 | 0xCC | 0xE8 | 0xE9 | 0xEB => true,
+        0xFF => matches!(extension, Some(2..=5)),
+        _ => false,
+    }
+}
+
+/// 32-bit arguments: pushed right to left, so the push nearest the call
+/// is the first argument. Code that stores into `[esp + n]` instead of
+/// pushing is read slot by slot.
+fn stack_args_32(window: &[Decoded], count: usize, pe: &PeContext) -> Vec<Option<u64>> {
+    let mut values = Vec::new();
+    let mut end = window.len();
+    while values.len() < count && end > 0 {
+        end -= 1;
+        let insn = &window[end].insn;
+        let extension = insn.modrm.map(|modrm| modrm.reg & 7);
+        match insn.opcode {
+            0x68 | 0x6A => values.push(insn.imm.map(|imm| imm as u32 as u64)),
+            op @ 0x50..=0x57 => {
+                values.push(value_at(window, end, Location::Register(op as u8 & 7), pe))
+            }
+            0xFF if extension == Some(6) => values.push(None),
+            // Anything else that moves ESP ends the argument pushes.
+            0x58..=0x5F | 0x60 | 0x61 | 0x9C | 0x9D | 0xC8 | 0xC9 => break,
+            _ if insn.writes(STACK_POINTER) => break,
+            _ => {}
+        }
+    }
+    if values.is_empty() {
+        return (0..count)
+            .map(|i| value_at(window, window.len(), Location::Stack(4 * i as i64), pe))
+            .collect();
+    }
+    values.resize(count, None);
+    values
+}
+
+/// Value `location` holds right before `window[end]`, found by walking
+/// back to the instruction that set it. Register-to-register moves are
+/// followed; anything computed or loaded from memory is unknown.
+fn value_at(window: &[Decoded], end: usize, location: Location, pe: &PeContext) -> Option<u64> {
+    for i in (0..end).rev() {
+        let decoded = &window[i];
+        let insn = &decoded.insn;
+        let modrm = insn.modrm;
+        match location {
+            Location::Register(reg) => {
+                let in_opcode = (insn.opcode as u8 & 7) | (insn.rex & 0x01) << 3;
+                match (insn.opcode, modrm) {
+                    (0xB8..=0xBF, _) if in_opcode == reg => {
+                        return insn.imm.map(|imm| immediate(insn, imm));
+                    }
+                    (0xC7, Some(modrm)) if modrm.mode == 3 && modrm.rm == reg => {
+                        return insn.imm.map(|imm| immediate(insn, imm));
+                    }
+                    // xor reg, reg and sub reg, reg.
+                    (0x29 | 0x2B | 0x31 | 0x33, Some(modrm))
+                        if modrm.mode == 3 && modrm.reg == reg && modrm.rm == reg =>
+                    {
+                        return Some(0);
+                    }
+                    (0x8D, Some(modrm)) if modrm.reg == reg => {
+                        return insn.memory_target(pe.image_base + decoded.rva as u64);
+                    }
+                    (0x8B, Some(modrm)) if modrm.mode == 3 && modrm.reg == reg => {
+                        return value_at(window, i, Location::Register(modrm.rm), pe);
+                    }
+                    (0x89, Some(modrm)) if modrm.mode == 3 && modrm.rm == reg => {
+                        return value_at(window, i, Location::Register(modrm.reg), pe);
+                    }
+                    _ if insn.writes(reg) => return None,
+                    _ => {}
+                }
+            }
+            Location::Stack(disp) => {
+                let slot = modrm.and_then(|modrm| modrm.mem).filter(|mem| {
+                    mem.base == Some(STACK_POINTER) && mem.index.is_none() && mem.disp == disp
+                });
+                match (insn.opcode, slot, modrm) {
+                    (0xC7, Some(_), _) => return insn.imm.map(|imm| immediate(insn, imm)),
+                    (0x89, Some(_), Some(modrm)) => {
+                        return value_at(window, i, Location::Register(modrm.reg), pe);
+                    }
+                    (_, Some(_), _) => return None,
+                    _ if insn.writes(STACK_POINTER) => return None,
+                    // Pushes and pops move the slot.
+                    (0x50..=0x61 | 0x68 | 0x6A | 0x9C | 0x9D, _, _) => return None,
+                    _ => {}
+                }
+            }
+        }
+    }
+    None
+}
+
+/// An immediate as the destination receives it: 32-bit moves zero-extend.
+fn immediate(insn: &Instruction, imm: i64) -> u64 {
+    if insn.wide() {
+        imm as u64
+    } else {
+        imm as u32 as u64
+    }
+}
+
+/// Renders a raw argument the way waygate prints decoded ones; string
+/// parameters pointing at a literal in the file show the literal.
+fn typed_value(bytes: &[u8], pe: &PeContext, kind: ArgKind, value: u64) -> Arg {
+    match kind {
+        ArgKind::Int => Arg::Int(value as u32 as i32 as i64),
+        ArgKind::Size => Arg::Int(value as i64),
+        ArgKind::Ptr => Arg::Ptr(value),
+        ArgKind::Str | ArgKind::WStr => match string_at(bytes, pe, value, kind == ArgKind::WStr) {
+            Some(text) => Arg::Str {
+                address: value,
+                text,
+            },
+            None => Arg::Ptr(value),
+        },
+    }
+}
+
+/// A NUL-terminated printable string at virtual address `va`.
+fn string_at(bytes: &[u8], pe: &PeContext, va: u64, wide: bool) -> Option<String> {
+    let rva = usize::try_from(va.checked_sub(pe.image_base)?).ok()?;
+    let offset = pe.rva_to_offset(rva)?;
+    let data = bytes.get(offset..)?;
+    let text = if wide {
+        let units: Vec<u16> = data
+            .chunks_exact(2)
+            .take(MAX_STRING)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .take_while(|unit| *unit != 0)
+            .collect();
+        if units.len() == MAX_STRING {
+            return None;
+        }
+        String::from_utf16(&units).ok()?
+    } else {
+        let end = data.iter().take(MAX_STRING).position(|b| *b == 0)?;
+        String::from_utf8(data[..end].to_vec()).ok()?
+    };
+    text.chars()
+        .all(|c| !c.is_control() || matches!(c, '\t' | '\r' | '\n'))
+        .then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callsites::{branch_targets, CallKind};
+    use crate::testpe::{TestPe, IMAGE_SCN_CODE, IMAGE_SCN_RDATA};
+    use crate::x86::decode;
+
+    const TEXT_RVA: u32 = 0x1000;
+    const RDATA_RVA: u32 = 0x2000;
+
+    /// A two-section image: `text` at RVA 0x1000 and `rdata` at 0x2000.
+    fn image(is64: bool, text: &[u8], rdata: &[u8]) -> (Vec<u8>, PeContext) {
+        let bytes = TestPe::new(is64)
+            .section(".text", TEXT_RVA, text, IMAGE_SCN_CODE)
+            .section(".rdata", RDATA_RVA, rdata, IMAGE_SCN_RDATA)
+            .build();
+        let pe = PeContext::parse(&bytes).expect("test image parses");
+        (bytes, pe)
+    }
+
+    /// Decodes `text` and recovers the arguments of its last instruction,
+    /// a call to `symbol`.
+    fn args(is64: bool, text: &[u8], rdata: &[u8], symbol: &str) -> Vec<String> {
+        let (bytes, pe) = image(is64, text, rdata);
+        let mut decoded = Vec::new();
+        let mut offset = 0;
+        while offset < text.len() {
+            let insn = decode(&text[offset..], is64).expect("test code decodes");
+            decoded.push(Decoded {
+                rva: TEXT_RVA + offset as u32,
+                insn,
+            });
+            offset += insn.len;
+        }
+        let site = CallSite {
+            rva: decoded.last().unwrap().rva,
+            iat_rva: 0,
+            kind: CallKind::Call,
+            args: Vec::new(),
+        };
+        let export = waygate::lookup(symbol).expect("catalog export");
+        let code = [decoded];
+        recover_args(&bytes, &pe, &code, &branch_targets(&code), &site, export)
+    }
+
+    #[test]
+    fn pushes_are_read_nearest_first() {
+        let text = [
+            0x6A, 0x40, // push 0x40
+            0x68, 0x08, 0x20, 0x40, 0x00, // push "Title"
+            0x68, 0x00, 0x20, 0x40, 0x00, // push "Hello"
+            0x6A, 0x00, // push 0
+            0xFF, 0x15, 0x00, 0x30, 0x40, 0x00, // call [MessageBoxA]
+        ];
+        assert_eq!(
+            args(false, &text, b"Hello\0\0\0Title\0", "MessageBoxA"),
+            [
+                "hwnd=NULL",
+                "text=\"Hello\"",
+                "caption=\"Title\"",
+                "kind=64"
+            ]
+        );
+    }
+
+    #[test]
+    fn pushed_registers_take_their_last_value() {
+        let text = [
+            0x31, 0xC0, // xor eax, eax
+            0xB8, 0x64, 0x00, 0x00, 0x00, // mov eax, 100
+            0x50, // push eax
+            0x68, 0xF4, 0x01, 0x00, 0x00, // push 500
+            0xFF, 0x15, 0x00, 0x30, 0x40, 0x00, // call [SetCursorPos]
+        ];
+        assert_eq!(args(false, &text, &[], "SetCursorPos"), ["x=500", "y=100"]);
+    }
+
+    #[test]
+    fn win64_registers_and_stack_slots() {
+        let text = [
+            0x48, 0x8D, 0x0D, 0xF9, 0x0F, 0x00, 0x00, // lea rcx, [rip + "a.txt"]
+            0xBA, 0x00, 0x00, 0x00, 0x40, // mov edx, 0x40000000
+            0x45, 0x31, 0xC0, // xor r8d, r8d
+            0x4D, 0x31, 0xC9, // xor r9, r9
+            0xC7, 0x44, 0x24, 0x20, 0x03, 0x00, 0x00, 0x00, // mov dword [rsp + 0x20], 3
+            0xC7, 0x44, 0x24, 0x28, 0x80, 0x00, 0x00, 0x00, // mov dword [rsp + 0x28], 0x80
+            0x48, 0xC7, 0x44, 0x24, 0x30, 0x00, 0x00, 0x00, 0x00, // mov qword [rsp + 0x30], 0
+            0xFF, 0x15, 0x00, 0x10, 0x00, 0x00, // call [CreateFileA]
+        ];
+        assert_eq!(
+            args(true, &text, b"a.txt\0", "CreateFileA"),
+            [
+                "path=\"a.txt\"",
+                "access=1073741824",
+                "share=0",
+                "security=NULL",
+                "disposition=3",
+                "flags=128",
+                "template=NULL",
+            ]
+        );
+    }
+
+    #[test]
+    fn register_moves_are_followed() {
+        let text = [
+            0xB8, 0x07, 0x00, 0x00, 0x00, // mov eax, 7
+            0x89, 0xC1, // mov ecx, eax
+            0x48, 0x8B, 0xD1, // mov rdx, rcx
+            0xFF, 0x15, 0x00, 0x10, 0x00, 0x00, // call [SetCursorPos]
+        ];
+        assert_eq!(args(true, &text, &[], "SetCursorPos"), ["x=7", "y=7"]);
+    }
+
+    #[test]
+    fn stores_through_the_stack_slot_follow_the_source_register() {
+        let text = [
+            0xB8, 0x05, 0x00, 0x00, 0x00, // mov eax, 5
+            0x89, 0x44, 0x24, 0x20, // mov [rsp + 0x20], eax
+            0xFF, 0x15, 0x00, 0x10, 0x00, 0x00, // call [CreateFileA]
+        ];
+        let recovered = args(true, &text, &[], "CreateFileA");
+        assert_eq!(recovered[4], "disposition=5");
+        assert_eq!(recovered[0], "path=?");
+    }
+
+    #[test]
+    fn earlier_calls_end_the_window() {
+        let text = [
+            0xB9, 0x05, 0x00, 0x00, 0x00, // mov ecx, 5
+            0xE8, 0x00, 0x00, 0x00, 0x00, // call next
+            0xBA, 0x06, 0x00, 0x00, 0x00, // mov edx, 6
+            0xFF, 0x15, 0x00, 0x10, 0x00, 0x00, // call [SetCursorPos]
+        ];
+        assert_eq!(args(true, &text, &[], "SetCursorPos"), ["x=?", "y=6"]);
+    }
+
+    #[test]
+    fn conditional_branches_end_the_window() {
+        let text = [
+            0x31, 0xDB, // xor ebx, ebx
+            0x85, 0xC0, // test eax, eax
+            0x74, 0x20, // je elsewhere
+            0x6A, 0x07, // push 7
+            0x53, // push ebx
+            0xFF, 0x15, 0x00, 0x30, 0x40, 0x00, // call [SetCursorPos]
+        ];
+        assert_eq!(args(false, &text, &[], "SetCursorPos"), ["x=?", "y=7"]);
+    }
+
+    #[test]
+    fn branch_targets_end_the_window() {
+        let text = [
+            0x3B, 0xC3, // cmp eax, ebx
+            0x0F, 0x85, 0x02, 0x00, 0x00, 0x00, // jne push
+            0x33, 0xDB, // xor ebx, ebx
+            0x6A, 0x07, // push: push 7
+            0x53, // push ebx
+            0xFF, 0x15, 0x00, 0x30, 0x40, 0x00, // call [SetCursorPos]
+        ];
+        assert_eq!(args(false, &text, &[], "SetCursorPos"), ["x=?", "y=7"]);
+    }
+
+    #[test]
+    fn computed_values_stay_unknown() {
+        let text = [
+            0xB9, 0x05, 0x00, 0x00, 0x00, // mov ecx, 5
+            0x83, 0xC1, 0x01, // add ecx, 1
+            0xFF, 0x15, 0x00, 0x10, 0x00, 0x00, // call [SetCursorPos]
+        ];
+        assert!(args(true, &text, &[], "SetCursorPos").is_empty());
+    }
+
+    #[test]
+    fn unprintable_strings_stay_pointers() {
+        let text = [
+            0x48, 0x8D, 0x0D, 0xF9, 0x0F, 0x00, 0x00, // lea rcx, [rip + rdata]
+            0xFF, 0x15, 0x00, 0x10, 0x00, 0x00, // call [GetModuleHandleA]
+        ];
+        assert_eq!(
+            args(true, &text, b"\x01\x02\0", "GetModuleHandleA"),
+            ["module=0x140002000"]
+        );
+    }
+}
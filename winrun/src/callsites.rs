@@ -0,0 +1,250 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::pe::{PeContext, IMAGE_SCN_CNT_CODE, IMAGE_SCN_MEM_EXECUTE};
+use crate::x86::{decode, Instruction};
+
+/// Registers a Win64 callee may clobber; 32-bit code loses EAX/ECX/EDX.
+const VOLATILE_64: &[u8] = &[0, 1, 2, 8, 9, 10, 11];
+const VOLATILE_32: &[u8] = &[0, 1, 2];
+
+/// How code reaches an imported function.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CallKind {
+    /// `call [IAT slot]`.
+    Call,
+    /// `jmp [IAT slot]`: an import thunk or a tail call.
+    Jump,
+    /// `call rel32` to a `jmp [IAT slot]` thunk.
+    Thunk,
+    /// `call reg` after `mov reg, [IAT slot]`, with the register named.
+    Register(&'static str),
+}
+
+impl CallKind {
+    /// Short label for reports and plan rows.
+    pub fn label(self) -> String {
+        match self {
+            CallKind::Call => "call".to_string(),
+            CallKind::Jump => "jmp".to_string(),
+            CallKind::Thunk => "thunk".to_string(),
+            CallKind::Register(reg) => format!("call-{reg}"),
+        }
+    }
+}
+
+/// One place in the code that transfers control to an import.
+#[derive(Clone, Debug)]
+pub struct CallSite {
+    pub rva: u32,
+    /// RVA of the IAT slot the call goes through.
+    pub iat_rva: u32,
+    pub kind: CallKind,
+    /// `name=value` arguments recovered from the code before the call;
+    /// empty when none could be.
+    pub args: Vec<String>,
+}
+
+pub fn register_name(reg: u8, is64: bool) -> &'static str {
+    const NAMES_64: [&str; 16] = [
+        "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12",
+        "r13", "r14", "r15",
+    ];
+    const NAMES_32: [&str; 8] = ["eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi"];
+    if is64 {
+        NAMES_64[reg as usize & 15]
+    } else {
+        NAMES_32[reg as usize & 7]
+    }
+}
+
+/// One decoded instruction of a code section, with its RVA.
+pub struct Decoded {
+    pub rva: u32,
+    pub insn: Instruction,
+}
+
+impl Decoded {
+    /// RVA a direct `call`, `jmp`, `jcc`, `loop` or `jecxz` transfers to.
+    pub fn branch_target(&self) -> Option<u32> {
+        match self.insn.opcode {
+            0x70..=0x7F | 0x0F80..=0x0F8F | 0xE0..=0xE3 | 0xE8 | 0xE9 | 0xEB => {
+                let target = self.rva as i64 + self.insn.len as i64 + self.insn.imm?;
+                u32::try_from(target).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+/// RVAs that some decoded instruction branches to: code there may be
+/// reached from elsewhere, not only from the instruction before it.
+pub fn branch_targets(code: &[Vec<Decoded>]) -> HashSet<u32> {
+    code.iter()
+        .flatten()
+        .filter_map(Decoded::branch_target)
+        .collect()
+}
+
+/// Decodes every executable section front to back. Bytes that do not
+/// decode are skipped one at a time, so data embedded in code costs a few
+/// bogus instructions rather than the rest of the section. Sections
+/// linkers mark as code count even when the execute bit is missing.
+pub fn sweep(bytes: &[u8], pe: &PeContext) -> Vec<Vec<Decoded>> {
+    pe.sections
+        .iter()
+        .filter(|section| {
+            section.characteristics & (IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_CNT_CODE) != 0
+        })
+        .filter_map(|section| {
+            let size = match section.virtual_size {
+                0 => section.raw_size,
+                size => size.min(section.raw_size),
+            };
+            let end = (section.raw_ptr + size).min(bytes.len());
+            let code = bytes.get(section.raw_ptr..end)?;
+            let mut decoded = Vec::new();
+            let mut pos = 0;
+            while pos < code.len() {
+                let rva = (section.virtual_address + pos) as u32;
+                match decode(&code[pos..], pe.is_pe64) {
+                    Some(insn) => {
+                        decoded.push(Decoded { rva, insn });
+                        pos += insn.len;
+                    }
+                    None => pos += 1,
+                }
+            }
+            Some(decoded)
+        })
+        .collect()
+}
+
+/// IAT slot a memory operand reads, if it names one of `slots`.
+pub fn iat_operand(pe: &PeContext, decoded: &Decoded, slots: &HashMap<u32, usize>) -> Option<u32> {
+    let target = decoded
+        .insn
+        .memory_target(pe.image_base + decoded.rva as u64)?;
+    let rva = u32::try_from(target.checked_sub(pe.image_base)?).ok()?;
+    slots.contains_key(&rva).then_some(rva)
+}
+
+/// Finds every call into the import table: direct `call`/`jmp` through an
+/// IAT slot, calls to `jmp [IAT]` thunks, and `call reg` after the
+/// register was loaded from a slot. `slots` maps IAT slot RVAs to the
+/// caller's import index and `code` comes from [`sweep`]. Sites come back
+/// sorted by RVA.
+pub fn find_call_sites(
+    code: &[Vec<Decoded>],
+    pe: &PeContext,
+    slots: &HashMap<u32, usize>,
+) -> Vec<CallSite> {
+    let volatile = if pe.is_pe64 { VOLATILE_64 } else { VOLATILE_32 };
+    let mut sites = Vec::new();
+    let mut direct_calls = Vec::new();
+    for section in code {
+        // Registers currently holding an import's address.
+        let mut loaded: HashMap<u8, u32> = HashMap::new();
+        for decoded in section {
+            let insn = &decoded.insn;
+            let reg_field = insn.modrm.map(|modrm| modrm.reg & 7);
+            let slot = iat_operand(pe, decoded, slots);
+            match (insn.opcode, reg_field, slot) {
+                (0xFF, Some(2), Some(iat_rva)) => sites.push(CallSite {
+                    rva: decoded.rva,
+                    iat_rva,
+                    kind: CallKind::Call,
+                    args: Vec::new(),
+                }),
+                (0xFF, Some(4), Some(iat_rva)) => sites.push(CallSite {
+                    rva: decoded.rva,
+                    iat_rva,
+                    kind: CallKind::Jump,
+                    args: Vec::new(),
+                }),
+                (0xFF, Some(2), None) => {
+                    let reg = insn.rm_register();
+                    if let Some((reg, iat_rva)) = reg.and_then(|reg| Some((reg, loaded.get(&reg)?)))
+                    {
+                        sites.push(CallSite {
+                            rva: decoded.rva,
+                            iat_rva: *iat_rva,
+                            kind: CallKind::Register(register_name(reg, pe.is_pe64)),
+                            args: Vec::new(),
+                        });
+                    }
+                }
+                (0xE8, _, _) => {
+                    if let Some(target) = decoded.branch_target() {
+                        direct_calls.push((decoded.rva, target));
+                    }
+                }
+                _ => {}
+            }
+            track_loads(insn, slot, pe.is_pe64, volatile, &mut loaded);
+        }
+    }
+
+    let thunks: HashMap<u32, u32> = sites
+        .iter()
+        .filter(|site| site.kind == CallKind::Jump)
+        .map(|site| (site.rva, site.iat_rva))
+        .collect();
+    sites.extend(direct_calls.into_iter().filter_map(|(rva, target)| {
+        thunks.get(&target).map(|iat_rva| CallSite {
+            rva,
+            iat_rva: *iat_rva,
+            kind: CallKind::Thunk,
+            args: Vec::new(),
+        })
+    }));
+    sites.sort_by_key(|site| site.rva);
+    sites
+}
+
+/// Updates which registers hold an import address after `insn`. Anything
+/// that may write a register forgets it; only control flow that cannot
+/// fall through (`ret`, `jmp`) forgets everything, since callers commonly
+/// keep an import in a non-volatile register across calls.
+fn track_loads(
+    insn: &Instruction,
+    slot: Option<u32>,
+    is64: bool,
+    volatile: &[u8],
+    loaded: &mut HashMap<u8, u32>,
+) {
+    let modrm = insn.modrm;
+    match insn.opcode {
+        // mov reg, [IAT slot], pointer-sized.
+        0x8B if slot.is_some() && (insn.wide() || !is64) => {
+            if let (Some(modrm), Some(slot)) = (modrm, slot) {
+                loaded.insert(modrm.reg, slot);
+            }
+            return;
+        }
+        // mov eax, [IAT slot] in its short moffs form.
+        0xA1 if slot.is_some() && (insn.wide() || !is64) => {
+            if let Some(slot) = slot {
+                loaded.insert(0, slot);
+            }
+            return;
+        }
+        0xC2 | 0xC3 | 0xE9 | 0xEB => {
+            loaded.clear();
+            return;
+        }
+        0xFF if modrm.is_some_and(|modrm| modrm.reg & 7 == 4) => {
+            loaded.clear();
+            return;
+        }
+        0xE8 => {
+            loaded.retain(|reg, _| !volatile.contains(reg));
+            return;
+        }
+        0xFF if modrm.is_some_and(|modrm| modrm.reg & 7 == 2) => {
+            loaded.retain(|reg, _| !volatile.contains(reg));
+            return;
+        }
+        _ => {}
+    }
+    loaded.retain(|reg, _| !insn.writes(*reg));
+}
@@ -0,0 +1,469 @@
+use crate::pe::{read_u16, read_u32, PeContext, IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR};
+use Column::{Blob, Coded, Fixed, Guid, Index, Str};
+
+/// `BSJB`, the metadata root signature.
+const METADATA_SIGNATURE: u32 = 0x424A_5342;
+
+/// IMAGE_COR20_HEADER `Flags`.
+const CLR_FLAGS: &[(u32, &str)] = &[
+    (0x0000_0001, "ILONLY"),
+    (0x0000_0002, "32BITREQUIRED"),
+    (0x0000_0004, "IL_LIBRARY"),
+    (0x0000_0008, "STRONGNAMESIGNED"),
+    (0x0000_0010, "NATIVE_ENTRYPOINT"),
+    (0x0001_0000, "TRACKDEBUGDATA"),
+    (0x0002_0000, "32BITPREFERRED"),
+];
+
+/// ImplMap `MappingFlags`.
+const PINVOKE_NO_MANGLE: u16 = 0x0001;
+const PINVOKE_CHAR_SET_MASK: u16 = 0x0006;
+const PINVOKE_CHAR_SET_UNICODE: u16 = 0x0004;
+const PINVOKE_CHAR_SET_AUTO: u16 = 0x0006;
+const PINVOKE_SUPPORTS_LAST_ERROR: u16 = 0x0040;
+
+const MODULE: usize = 0x00;
+const TYPE_REF: usize = 0x01;
+const TYPE_DEF: usize = 0x02;
+const FIELD: usize = 0x04;
+const METHOD_DEF: usize = 0x06;
+const PARAM: usize = 0x08;
+const MEMBER_REF: usize = 0x0A;
+const CUSTOM_ATTRIBUTE: usize = 0x0C;
+const DECL_SECURITY: usize = 0x0E;
+const EVENT: usize = 0x14;
+const PROPERTY: usize = 0x17;
+const MODULE_REF: usize = 0x1A;
+const TYPE_SPEC: usize = 0x1B;
+const IMPL_MAP: usize = 0x1C;
+const ASSEMBLY: usize = 0x20;
+const ASSEMBLY_REF: usize = 0x23;
+/// Placeholder for coded-index tags ECMA-335 leaves unused.
+const UNUSED: usize = usize::MAX;
+
+const TYPE_DEF_OR_REF: &[usize] = &[TYPE_DEF, TYPE_REF, TYPE_SPEC];
+const HAS_CONSTANT: &[usize] = &[FIELD, PARAM, PROPERTY];
+const HAS_CUSTOM_ATTRIBUTE: &[usize] = &[
+    METHOD_DEF,
+    FIELD,
+    TYPE_REF,
+    TYPE_DEF,
+    PARAM,
+    0x09,
+    MEMBER_REF,
+    MODULE,
+    DECL_SECURITY,
+    PROPERTY,
+    EVENT,
+    0x11,
+    MODULE_REF,
+    TYPE_SPEC,
+    ASSEMBLY,
+    ASSEMBLY_REF,
+    0x26,
+    0x27,
+    0x28,
+    0x2A,
+    0x2C,
+    0x2B,
+];
+const HAS_FIELD_MARSHAL: &[usize] = &[FIELD, PARAM];
+const HAS_DECL_SECURITY: &[usize] = &[TYPE_DEF, METHOD_DEF, ASSEMBLY];
+const MEMBER_REF_PARENT: &[usize] = &[TYPE_DEF, TYPE_REF, MODULE_REF, METHOD_DEF, TYPE_SPEC];
+const HAS_SEMANTICS: &[usize] = &[EVENT, PROPERTY];
+const METHOD_DEF_OR_REF: &[usize] = &[METHOD_DEF, MEMBER_REF];
+const MEMBER_FORWARDED: &[usize] = &[FIELD, METHOD_DEF];
+const RESOLUTION_SCOPE: &[usize] = &[MODULE, MODULE_REF, ASSEMBLY_REF, TYPE_REF];
+const CUSTOM_ATTRIBUTE_TYPE: &[usize] = &[UNUSED, UNUSED, METHOD_DEF, MEMBER_REF, UNUSED];
+
+#[derive(Copy, Clone)]
+enum Column {
+    Fixed(usize),
+    Str,
+    Guid,
+    Blob,
+    Index(usize),
+    Coded(&'static [usize]),
+}
+
+/// Row layouts of tables 0x00 (Module) through 0x20 (Assembly), ECMA-335
+/// II.22. Later tables are never read, and come after these in the stream.
+const SCHEMAS: [&[Column]; 0x21] = [
+    &[Fixed(2), Str, Guid, Guid, Guid],
+    &[Coded(RESOLUTION_SCOPE), Str, Str],
+    &[
+        Fixed(4),
+        Str,
+        Str,
+        Coded(TYPE_DEF_OR_REF),
+        Index(FIELD),
+        Index(METHOD_DEF),
+    ],
+    &[Index(FIELD)],
+    &[Fixed(2), Str, Blob],
+    &[Index(METHOD_DEF)],
+    &[Fixed(4), Fixed(2), Fixed(2), Str, Blob, Index(PARAM)],
+    &[Index(PARAM)],
+    &[Fixed(2), Fixed(2), Str],
+    &[Index(TYPE_DEF), Coded(TYPE_DEF_OR_REF)],
+    &[Coded(MEMBER_REF_PARENT), Str, Blob],
+    &[Fixed(2), Coded(HAS_CONSTANT), Blob],
+    &[
+        Coded(HAS_CUSTOM_ATTRIBUTE),
+        Coded(CUSTOM_ATTRIBUTE_TYPE),
+        Blob,
+    ],
+    &[Coded(HAS_FIELD_MARSHAL), Blob],
+    &[Fixed(2), Coded(HAS_DECL_SECURITY), Blob],
+    &[Fixed(2), Fixed(4), Index(TYPE_DEF)],
+    &[Fixed(4), Index(FIELD)],
+    &[Blob],
+    &[Index(TYPE_DEF), Index(EVENT)],
+    &[Index(EVENT)],
+    &[Fixed(2), Str, Coded(TYPE_DEF_OR_REF)],
+    &[Index(TYPE_DEF), Index(PROPERTY)],
+    &[Index(PROPERTY)],
+    &[Fixed(2), Str, Blob],
+    &[Fixed(2), Index(METHOD_DEF), Coded(HAS_SEMANTICS)],
+    &[
+        Index(TYPE_DEF),
+        Coded(METHOD_DEF_OR_REF),
+        Coded(METHOD_DEF_OR_REF),
+    ],
+    &[Str],
+    &[Blob],
+    &[Fixed(2), Coded(MEMBER_FORWARDED), Str, Index(MODULE_REF)],
+    &[Fixed(4), Index(FIELD)],
+    &[Fixed(4), Fixed(4)],
+    &[Fixed(4)],
+    &[
+        Fixed(4),
+        Fixed(2),
+        Fixed(2),
+        Fixed(2),
+        Fixed(2),
+        Fixed(4),
+        Blob,
+        Str,
+        Str,
+    ],
+];
+
+/// What the CLR header and metadata say about a managed image.
+pub struct ClrInfo {
+    /// Runtime version from the CLR header (2.5 for every modern compiler).
+    pub header_version: (u16, u16),
+    pub flags: u32,
+    /// Metadata root version string, such as `v4.0.30319`.
+    pub metadata_version: String,
+    /// Assembly name and version, from the Assembly table.
+    pub assembly: Option<(String, String)>,
+    /// `[assembly: TargetFramework]`, e.g. `.NETCoreApp,Version=v8.0`.
+    pub target_framework: Option<String>,
+    pub pinvokes: Vec<PInvoke>,
+}
+
+impl ClrInfo {
+    pub fn flag_names(&self) -> Vec<&'static str> {
+        CLR_FLAGS
+            .iter()
+            .filter(|(bit, _)| self.flags & bit != 0)
+            .map(|(_, name)| *name)
+            .collect()
+    }
+
+    /// IL-only images have no native code at all; mixed-mode (C++/CLI)
+    /// images carry both.
+    pub fn is_il_only(&self) -> bool {
+        self.flags & 1 != 0
+    }
+}
+
+/// One `[DllImport]` method: an ImplMap row and what it forwards.
+pub struct PInvoke {
+    /// ModuleRef name as written, which may omit `.dll`.
+    pub module: String,
+    /// ImportName: the `EntryPoint`, or the method name when none is given.
+    pub entry_point: String,
+    /// The managed method declaring the import.
+    pub method: String,
+    pub flags: u16,
+}
+
+impl PInvoke {
+    /// The DLL the runtime loads, with `.dll` appended to bare names.
+    pub fn dll(&self) -> String {
+        if self.module.contains('.') {
+            self.module.clone()
+        } else {
+            format!("{}.dll", self.module)
+        }
+    }
+
+    /// `#N` entry points import by ordinal.
+    pub fn ordinal(&self) -> Option<u16> {
+        self.entry_point.strip_prefix('#')?.parse().ok()
+    }
+
+    pub fn char_set(&self) -> &'static str {
+        match self.flags & PINVOKE_CHAR_SET_MASK {
+            PINVOKE_CHAR_SET_UNICODE => "Unicode",
+            PINVOKE_CHAR_SET_AUTO => "Auto",
+            _ => "Ansi",
+        }
+    }
+
+    pub fn sets_last_error(&self) -> bool {
+        self.flags & PINVOKE_SUPPORTS_LAST_ERROR != 0
+    }
+
+    /// Export names the runtime tries, in order. Without `ExactSpelling`,
+    /// Unicode and Auto (Unicode on Windows NT) try the `W` form first and
+    /// Ansi tries the plain name before the `A` form.
+    pub fn candidates(&self) -> Vec<String> {
+        let name = self.entry_point.clone();
+        if self.flags & PINVOKE_NO_MANGLE != 0 || self.ordinal().is_some() {
+            return vec![name];
+        }
+        match self.flags & PINVOKE_CHAR_SET_MASK {
+            PINVOKE_CHAR_SET_UNICODE | PINVOKE_CHAR_SET_AUTO => vec![format!("{name}W"), name],
+            _ => vec![name.clone(), format!("{name}A")],
+        }
+    }
+}
+
+/// Parses the CLR header and metadata. Returns `None` for native images,
+/// and for managed ones whose metadata is malformed.
+pub fn parse_clr(bytes: &[u8], pe: &PeContext) -> Option<ClrInfo> {
+    let dir = pe.directory(IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR)?;
+    let header = pe.rva_to_offset(dir.rva as usize)?;
+    let header_version = (read_u16(bytes, header + 4)?, read_u16(bytes, header + 6)?);
+    let metadata_rva = read_u32(bytes, header + 8)? as usize;
+    let flags = read_u32(bytes, header + 16)?;
+
+    let root = pe.rva_to_offset(metadata_rva)?;
+    if read_u32(bytes, root)? != METADATA_SIGNATURE {
+        return None;
+    }
+    let version_len = read_u32(bytes, root + 12)? as usize;
+    let version = bytes.get(root + 16..root + 16 + version_len)?;
+    let end = version
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(version.len());
+    let metadata_version = String::from_utf8_lossy(&version[..end]).into_owned();
+
+    let mut pos = root + 16 + version_len.next_multiple_of(4);
+    let stream_count = read_u16(bytes, pos + 2)? as usize;
+    pos += 4;
+    let (mut tables, mut strings, mut blobs) = (None, &[][..], &[][..]);
+    for _ in 0..stream_count {
+        let offset = read_u32(bytes, pos)? as usize;
+        let size = read_u32(bytes, pos + 4)? as usize;
+        let name_start = pos + 8;
+        let name_len = bytes.get(name_start..)?.iter().position(|b| *b == 0)?;
+        let data = bytes.get(root + offset..root + offset + size)?;
+        match &bytes[name_start..name_start + name_len] {
+            b"#~" | b"#-" => tables = Some(data),
+            b"#Strings" => strings = data,
+            b"#Blob" => blobs = data,
+            _ => {}
+        }
+        pos = name_start + (name_len + 1).next_multiple_of(4);
+    }
+    let tables = Tables::parse(tables?, strings, blobs)?;
+
+    Some(ClrInfo {
+        header_version,
+        flags,
+        metadata_version,
+        assembly: tables.assembly(),
+        target_framework: tables.target_framework(),
+        pinvokes: tables.pinvokes()?,
+    })
+}
+
+/// The `#~` stream with enough layout to read any cell of tables
+/// 0x00-0x20.
+struct Tables<'a> {
+    data: &'a [u8],
+    strings: &'a [u8],
+    blobs: &'a [u8],
+    heap_sizes: u8,
+    rows: [usize; 64],
+    offsets: [usize; SCHEMAS.len()],
+}
+
+impl<'a> Tables<'a> {
+    fn parse(data: &'a [u8], strings: &'a [u8], blobs: &'a [u8]) -> Option<Self> {
+        let heap_sizes = *data.get(6)?;
+        let valid = u64::from_le_bytes(data.get(8..16)?.try_into().ok()?);
+        let mut rows = [0usize; 64];
+        let mut pos = 24;
+        for (table, count) in rows.iter_mut().enumerate() {
+            if valid & (1 << table) != 0 {
+                *count = read_u32(data, pos)? as usize;
+                pos += 4;
+            }
+        }
+        // Extra data flag: four bytes follow the row counts.
+        if heap_sizes & 0x40 != 0 {
+            pos += 4;
+        }
+        let mut tables = Self {
+            data,
+            strings,
+            blobs,
+            heap_sizes,
+            rows,
+            offsets: [0; SCHEMAS.len()],
+        };
+        let mut offsets = [0; SCHEMAS.len()];
+        for (table, offset) in offsets.iter_mut().enumerate() {
+            *offset = pos;
+            pos = pos.checked_add(tables.row_size(table).checked_mul(rows[table])?)?;
+        }
+        tables.offsets = offsets;
+        Some(tables)
+    }
+
+    fn column_size(&self, column: Column) -> usize {
+        let wide = |rows: usize, bits: u32| if rows < 1 << (16 - bits) { 2 } else { 4 };
+        match column {
+            Fixed(size) => size,
+            Str => 2 + 2 * (self.heap_sizes & 0x01 != 0) as usize,
+            Guid => 2 + 2 * (self.heap_sizes & 0x02 != 0) as usize,
+            Blob => 2 + 2 * (self.heap_sizes & 0x04 != 0) as usize,
+            Index(table) => wide(self.rows[table], 0),
+            Coded(tables) => {
+                let bits = usize::BITS - (tables.len() - 1).leading_zeros();
+                let most = tables
+                    .iter()
+                    .filter(|table| **table != UNUSED)
+                    .map(|table| self.rows[*table])
+                    .max()
+                    .unwrap_or(0);
+                wide(most, bits)
+            }
+        }
+    }
+
+    fn row_size(&self, table: usize) -> usize {
+        SCHEMAS[table]
+            .iter()
+            .map(|column| self.column_size(*column))
+            .sum()
+    }
+
+    /// Reads column `column` of the 1-based `row`.
+    fn cell(&self, table: usize, row: usize, column: usize) -> Option<u32> {
+        if row == 0 || row > self.rows[table] {
+            return None;
+        }
+        let schema = SCHEMAS[table];
+        let within: usize = schema[..column]
+            .iter()
+            .map(|column| self.column_size(*column))
+            .sum();
+        let at = self.offsets[table] + (row - 1) * self.row_size(table) + within;
+        match self.column_size(schema[column]) {
+            2 => read_u16(self.data, at).map(u32::from),
+            4 => read_u32(self.data, at),
+            _ => None,
+        }
+    }
+
+    fn string(&self, table: usize, row: usize, column: usize) -> Option<String> {
+        let start = self.cell(table, row, column)? as usize;
+        let rest = self.strings.get(start..)?;
+        let end = rest.iter().position(|b| *b == 0)?;
+        Some(String::from_utf8_lossy(&rest[..end]).into_owned())
+    }
+
+    fn blob(&self, table: usize, row: usize, column: usize) -> Option<&'a [u8]> {
+        let start = self.cell(table, row, column)? as usize;
+        let (len, header) = compressed_u32(self.blobs.get(start..)?)?;
+        self.blobs
+            .get(start + header..start + header + len as usize)
+    }
+
+    /// Splits a coded index into its table and 1-based row.
+    fn coded(&self, table: usize, row: usize, column: usize) -> Option<(usize, usize)> {
+        let Coded(tables) = SCHEMAS[table][column] else {
+            return None;
+        };
+        let bits = usize::BITS - (tables.len() - 1).leading_zeros();
+        let value = self.cell(table, row, column)? as usize;
+        let target = *tables.get(value & ((1 << bits) - 1))?;
+        (target != UNUSED).then_some((target, value >> bits))
+    }
+
+    fn pinvokes(&self) -> Option<Vec<PInvoke>> {
+        let mut pinvokes = Vec::new();
+        for row in 1..=self.rows[IMPL_MAP] {
+            let flags = self.cell(IMPL_MAP, row, 0)? as u16;
+            let method = match self.coded(IMPL_MAP, row, 1)? {
+                (METHOD_DEF, method) => self.string(METHOD_DEF, method, 3)?,
+                _ => continue,
+            };
+            let scope = self.cell(IMPL_MAP, row, 3)? as usize;
+            pinvokes.push(PInvoke {
+                module: self.string(MODULE_REF, scope, 0)?,
+                entry_point: self.string(IMPL_MAP, row, 2)?,
+                method,
+                flags,
+            });
+        }
+        Some(pinvokes)
+    }
+
+    fn assembly(&self) -> Option<(String, String)> {
+        let part = |column| self.cell(ASSEMBLY, 1, column);
+        let version = format!("{}.{}.{}.{}", part(1)?, part(2)?, part(3)?, part(4)?);
+        Some((self.string(ASSEMBLY, 1, 7)?, version))
+    }
+
+    /// Finds the assembly-level `TargetFrameworkAttribute` and decodes its
+    /// constructor argument.
+    fn target_framework(&self) -> Option<String> {
+        (1..=self.rows[CUSTOM_ATTRIBUTE]).find_map(|row| {
+            if self.coded(CUSTOM_ATTRIBUTE, row, 0)?.0 != ASSEMBLY {
+                return None;
+            }
+            let (MEMBER_REF, constructor) = self.coded(CUSTOM_ATTRIBUTE, row, 1)? else {
+                return None;
+            };
+            let (TYPE_REF, class) = self.coded(MEMBER_REF, constructor, 0)? else {
+                return None;
+            };
+            if self.string(TYPE_REF, class, 1)? != "TargetFrameworkAttribute" {
+                return None;
+            }
+            // Prolog 0x0001, then the string as a SerString.
+            let value = self.blob(CUSTOM_ATTRIBUTE, row, 2)?;
+            let rest = value.strip_prefix(&[1, 0])?;
+            let (len, header) = compressed_u32(rest)?;
+            let text = rest.get(header..header + len as usize)?;
+            Some(String::from_utf8_lossy(text).into_owned())
+        })
+    }
+}
+
+/// ECMA-335 II.23.2 compressed unsigned integer: returns the value and the
+/// number of bytes it took.
+fn compressed_u32(data: &[u8]) -> Option<(u32, usize)> {
+    let first = *data.first()? as u32;
+    match first {
+        0x00..=0x7F => Some((first, 1)),
+        0x80..=0xBF => Some((((first & 0x3F) << 8) | *data.get(1)? as u32, 2)),
+        0xC0..=0xDF => {
+            let rest = data.get(1..4)?;
+            let value = ((first & 0x1F) << 24)
+                | (rest[0] as u32) << 16
+                | (rest[1] as u32) << 8
+                | rest[2] as u32;
+            Some((value, 4))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt;
+
+use crate::pe::{read_c_string, read_u32, PeContext, IMAGE_DIRECTORY_ENTRY_DEBUG};
+
+pub const IMAGE_DEBUG_TYPE_COFF: u32 = 1;
+pub const IMAGE_DEBUG_TYPE_CODEVIEW: u32 = 2;
+pub const IMAGE_DEBUG_TYPE_FPO: u32 = 3;
+pub const IMAGE_DEBUG_TYPE_MISC: u32 = 4;
+pub const IMAGE_DEBUG_TYPE_EXCEPTION: u32 = 5;
+pub const IMAGE_DEBUG_TYPE_FIXUP: u32 = 6;
+pub const IMAGE_DEBUG_TYPE_BORLAND: u32 = 9;
+pub const IMAGE_DEBUG_TYPE_CLSID: u32 = 11;
+pub const IMAGE_DEBUG_TYPE_VC_FEATURE: u32 = 12;
+pub const IMAGE_DEBUG_TYPE_POGO: u32 = 13;
+pub const IMAGE_DEBUG_TYPE_ILTCG: u32 = 14;
+pub const IMAGE_DEBUG_TYPE_REPRO: u32 = 16;
+pub const IMAGE_DEBUG_TYPE_EX_DLLCHARACTERISTICS: u32 = 20;
+
+/// Size of one IMAGE_DEBUG_DIRECTORY entry.
+const DEBUG_ENTRY_SIZE: usize = 28;
+
+/// A GUID in its registry text form.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    fn data1(&self) -> u32 {
+        u32::from_le_bytes([self.0[0], self.0[1], self.0[2], self.0[3]])
+    }
+
+    fn data2(&self) -> u16 {
+        u16::from_le_bytes([self.0[4], self.0[5]])
+    }
+
+    fn data3(&self) -> u16 {
+        u16::from_le_bytes([self.0[6], self.0[7]])
+    }
+
+    /// Uppercase hex without separators, as symbol servers spell it.
+    pub fn compact(&self) -> String {
+        let tail: String = self.0[8..].iter().map(|b| format!("{b:02X}")).collect();
+        format!(
+            "{:08X}{:04X}{:04X}{tail}",
+            self.data1(),
+            self.data2(),
+            self.data3()
+        )
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let b = &self.0;
+        write!(
+            f,
+            "{{{:08x}-{:04x}-{:04x}-{:02x}{:02x}-",
+            self.data1(),
+            self.data2(),
+            self.data3(),
+            b[8],
+            b[9]
+        )?;
+        for byte in &b[10..] {
+            write!(f, "{byte:02x}")?;
+        }
+        f.write_str("}")
+    }
+}
+
+/// A CodeView record pointing at the image's PDB.
+#[derive(Clone, Debug)]
+pub enum CodeView {
+    /// `RSDS`: PDB 7.0, matched by GUID and age.
+    Rsds { guid: Guid, age: u32, path: String },
+    /// `NB10`: PDB 2.0, matched by signature timestamp and age.
+    Nb10 {
+        signature: u32,
+        age: u32,
+        path: String,
+    },
+}
+
+impl CodeView {
+    pub fn path(&self) -> &str {
+        match self {
+            CodeView::Rsds { path, .. } | CodeView::Nb10 { path, .. } => path,
+        }
+    }
+
+    /// Symbol server directory key: GUID (or signature) followed by the
+    /// age in hex.
+    pub fn symbol_key(&self) -> String {
+        match self {
+            CodeView::Rsds { guid, age, .. } => format!("{}{age:X}", guid.compact()),
+            CodeView::Nb10 { signature, age, .. } => format!("{signature:08X}{age:X}"),
+        }
+    }
+}
+
+/// Profile-guided / link-time code generation section map (`POGO`).
+#[derive(Clone, Debug)]
+pub struct Pogo {
+    /// `PGU`, `PGI`, `LTCG`, ...
+    pub signature: String,
+    /// `(rva, size, name)` of each contributing section.
+    pub entries: Vec<(u32, u32, String)>,
+}
+
+#[derive(Clone, Debug)]
+pub enum DebugPayload {
+    CodeView(CodeView),
+    Pogo(Pogo),
+    /// Deterministic build marker; the hash is usually a SHA-256 of the
+    /// image and is also folded into the COFF TimeDateStamp.
+    Repro(Vec<u8>),
+    Other,
+}
+
+/// One IMAGE_DEBUG_DIRECTORY entry.
+#[derive(Clone, Debug)]
+pub struct DebugEntry {
+    pub kind: u32,
+    pub size: u32,
+    pub payload: DebugPayload,
+}
+
+impl DebugEntry {
+    pub fn type_name(&self) -> String {
+        let name = match self.kind {
+            IMAGE_DEBUG_TYPE_COFF => "COFF",
+            IMAGE_DEBUG_TYPE_CODEVIEW => "CodeView",
+            IMAGE_DEBUG_TYPE_FPO => "FPO",
+            IMAGE_DEBUG_TYPE_MISC => "MISC",
+            IMAGE_DEBUG_TYPE_EXCEPTION => "EXCEPTION",
+            IMAGE_DEBUG_TYPE_FIXUP => "FIXUP",
+            IMAGE_DEBUG_TYPE_BORLAND => "BORLAND",
+            IMAGE_DEBUG_TYPE_CLSID => "CLSID",
+            IMAGE_DEBUG_TYPE_VC_FEATURE => "VC_FEATURE",
+            IMAGE_DEBUG_TYPE_POGO => "POGO",
+            IMAGE_DEBUG_TYPE_ILTCG => "ILTCG",
+            IMAGE_DEBUG_TYPE_REPRO => "REPRO",
+            IMAGE_DEBUG_TYPE_EX_DLLCHARACTERISTICS => "EX_DLLCHARACTERISTICS",
+            other => return format!("type {other}"),
+        };
+        name.to_string()
+    }
+}
+
+/// Parses IMAGE_DIRECTORY_ENTRY_DEBUG. Payloads are read through
+/// `PointerToRawData` (debug data need not be mapped), falling back to
+/// `AddressOfRawData`; unreadable payloads are kept as [`DebugPayload::Other`].
+pub fn parse_debug_directory(bytes: &[u8], pe: &PeContext) -> Option<Vec<DebugEntry>> {
+    let Some(dir) = pe.directory(IMAGE_DIRECTORY_ENTRY_DEBUG) else {
+        return Some(Vec::new());
+    };
+    let base = pe.rva_to_offset(dir.rva as usize)?;
+    let mut entries = Vec::new();
+    for idx in 0..dir.size as usize / DEBUG_ENTRY_SIZE {
+        let entry = base + idx * DEBUG_ENTRY_SIZE;
+        let kind = read_u32(bytes, entry + 12)?;
+        let size = read_u32(bytes, entry + 16)?;
+        let rva = read_u32(bytes, entry + 20)? as usize;
+        let pointer = read_u32(bytes, entry + 24)? as usize;
+        let offset = if pointer != 0 {
+            Some(pointer)
+        } else {
+            pe.rva_to_offset(rva)
+        };
+        let data = offset.and_then(|off| bytes.get(off..off.checked_add(size as usize)?));
+        let payload = match (kind, data) {
+            (IMAGE_DEBUG_TYPE_CODEVIEW, Some(data)) => {
+                parse_codeview(data).map_or(DebugPayload::Other, DebugPayload::CodeView)
+            }
+            (IMAGE_DEBUG_TYPE_POGO, Some(data)) => {
+                parse_pogo(data).map_or(DebugPayload::Other, DebugPayload::Pogo)
+            }
+            (IMAGE_DEBUG_TYPE_REPRO, Some(data)) => DebugPayload::Repro(parse_repro(data)),
+            (IMAGE_DEBUG_TYPE_REPRO, None) if size == 0 => DebugPayload::Repro(Vec::new()),
+            _ => DebugPayload::Other,
+        };
+        entries.push(DebugEntry {
+            kind,
+            size,
+            payload,
+        });
+    }
+    Some(entries)
+}
+
+/// The image's CodeView record, if it has one.
+pub fn codeview(entries: &[DebugEntry]) -> Option<&CodeView> {
+    entries.iter().find_map(|entry| match &entry.payload {
+        DebugPayload::CodeView(codeview) => Some(codeview),
+        _ => None,
+    })
+}
+
+/// True when the image was linked with `/Brepro`, in which case its
+/// TimeDateStamps are hash bits rather than dates.
+pub fn is_reproducible(entries: &[DebugEntry]) -> bool {
+    entries
+        .iter()
+        .any(|entry| entry.kind == IMAGE_DEBUG_TYPE_REPRO)
+}
+
+fn parse_codeview(data: &[u8]) -> Option<CodeView> {
+    match data.get(..4)? {
+        b"RSDS" => Some(CodeView::Rsds {
+            guid: Guid(data.get(4..20)?.try_into().ok()?),
+            age: read_u32(data, 20)?,
+            path: pdb_path(data, 24)?,
+        }),
+        b"NB10" => Some(CodeView::Nb10 {
+            signature: read_u32(data, 8)?,
+            age: read_u32(data, 12)?,
+            path: pdb_path(data, 16)?,
+        }),
+        _ => None,
+    }
+}
+
+/// NUL-terminated PDB path; linkers write the ANSI code page, so invalid
+/// UTF-8 is replaced rather than rejected.
+fn pdb_path(data: &[u8], offset: usize) -> Option<String> {
+    read_c_string(data, offset).or_else(|| {
+        let raw = data.get(offset..)?;
+        let end = raw.iter().position(|b| *b == 0).unwrap_or(raw.len());
+        Some(String::from_utf8_lossy(&raw[..end]).into_owned())
+    })
+}
+
+fn parse_pogo(data: &[u8]) -> Option<Pogo> {
+    let raw_signature = data.get(..4)?;
+    // Stored as a little-endian u32, so `PGU\0` reads back as `\0UGP`.
+    let signature: String = raw_signature
+        .iter()
+        .rev()
+        .filter(|b| **b != 0)
+        .map(|b| *b as char)
+        .collect();
+    let mut entries = Vec::new();
+    let mut offset = 4;
+    while offset + 8 < data.len() {
+        let rva = read_u32(data, offset)?;
+        let size = read_u32(data, offset + 4)?;
+        let name = read_c_string(data, offset + 8)?;
+        offset = (offset + 8 + name.len() + 1 + 3) & !3;
+        entries.push((rva, size, name));
+    }
+    Some(Pogo { signature, entries })
+}
+
+/// Repro payload: a length-prefixed hash.
+fn parse_repro(data: &[u8]) -> Vec<u8> {
+    let len = read_u32(data, 0).unwrap_or(0) as usize;
+    data.get(4..4 + len).map(<[u8]>::to_vec).unwrap_or_default()
+}
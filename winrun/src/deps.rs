@@ -0,0 +1,277 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::exports::{
+    parse_export_directory, split_forwarder, ExportDirectory, ExportTarget, ForwardedSymbol,
+};
+use crate::imports::{parse_delay_import_modules, parse_import_modules, ImportEntry, ImportModule};
+use crate::modules::{search_dll, DllLocation};
+use crate::pe::{Machine, PeContext, IMAGE_FILE_DLL};
+
+/// Longest forwarder chain followed before an export counts as unresolved.
+const MAX_FORWARDER_HOPS: usize = 16;
+
+/// Why a module depends on another.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Link {
+    Import,
+    DelayImport,
+    /// Named by one of the module's export forwarders.
+    Forwarder,
+}
+
+/// One edge of the dependency tree: a DLL as one module imports it.
+pub struct Dependency {
+    pub dll: String,
+    pub link: Link,
+    pub location: DllLocation,
+    /// Symbols the importer takes from it.
+    pub imports: usize,
+    /// Imports no export satisfies: unknown to waygate for built-in modules,
+    /// not exported (or forwarded nowhere) for app-local ones, and all of
+    /// them for missing DLLs. `#N` for ordinals.
+    pub unresolved: Vec<String>,
+    /// App-local file that is not a usable DLL for this target.
+    pub error: Option<String>,
+    /// Set when the module's own dependencies were already listed further
+    /// up the tree (shared DLLs and import cycles).
+    pub repeated: bool,
+    pub children: Vec<Dependency>,
+}
+
+/// An app-local DLL read from disk, parsed once per walk.
+struct LocalModule {
+    exports: ExportDirectory,
+    imports: Vec<ImportModule>,
+    forwarded: Vec<String>,
+}
+
+/// What answers for a module's exports during the walk.
+enum Exporter {
+    Builtin,
+    Local(Rc<LocalModule>),
+    /// Missing, or an app-local file that cannot be loaded.
+    Nothing,
+}
+
+struct Walker<'a> {
+    app_dir: &'a Path,
+    machine: Machine,
+    /// Search results by lowercase name; each DLL is searched for once.
+    locations: HashMap<String, DllLocation>,
+    /// App-local DLLs by lowercase name, or why they cannot be loaded.
+    files: HashMap<String, Result<Rc<LocalModule>, String>>,
+    expanded: HashSet<String>,
+}
+
+/// Walks the target's imports, delay-load imports included, the way the
+/// loader would find them: waygate's built-in modules first, then files in
+/// `app_dir`. App-local DLLs are read and their own imports and forwarder
+/// targets walked in turn; each module's dependencies are listed once.
+pub fn walk_dependencies(bytes: &[u8], pe: &PeContext, app_dir: &Path) -> Vec<Dependency> {
+    let mut walker = Walker {
+        app_dir,
+        machine: pe.machine,
+        locations: HashMap::new(),
+        files: HashMap::new(),
+        expanded: HashSet::new(),
+    };
+    let mut imports = parse_import_modules(bytes, pe).unwrap_or_default();
+    imports.extend(parse_delay_import_modules(bytes, pe).unwrap_or_default());
+    walker.dependencies(&imports, &[])
+}
+
+/// DLLs found neither among waygate's modules nor beside the target,
+/// anywhere in the tree, in first-seen order.
+pub fn missing_modules(tree: &[Dependency]) -> Vec<String> {
+    let mut missing: Vec<String> = Vec::new();
+    let mut pending: Vec<&Dependency> = tree.iter().rev().collect();
+    while let Some(dependency) = pending.pop() {
+        if matches!(dependency.location, DllLocation::Missing)
+            && !missing
+                .iter()
+                .any(|dll| dll.eq_ignore_ascii_case(&dependency.dll))
+        {
+            missing.push(dependency.dll.clone());
+        }
+        pending.extend(dependency.children.iter().rev());
+    }
+    missing
+}
+
+/// Import name as the tree reports it.
+fn symbol_name(entry: &ImportEntry) -> String {
+    match (&entry.name, entry.ordinal) {
+        (Some(name), _) => name.clone(),
+        (None, ordinal) => format!("#{}", ordinal.unwrap_or(0)),
+    }
+}
+
+impl Walker<'_> {
+    fn dependencies(&mut self, imports: &[ImportModule], forwarded: &[String]) -> Vec<Dependency> {
+        let mut dependencies: Vec<Dependency> = imports
+            .iter()
+            .map(|module| {
+                let link = if module.delay_loaded {
+                    Link::DelayImport
+                } else {
+                    Link::Import
+                };
+                self.dependency(&module.dll, link, &module.entries)
+            })
+            .collect();
+        for dll in forwarded {
+            if !dependencies
+                .iter()
+                .any(|dependency| dependency.dll.eq_ignore_ascii_case(dll))
+            {
+                let dependency = self.dependency(dll, Link::Forwarder, &[]);
+                dependencies.push(dependency);
+            }
+        }
+        dependencies
+    }
+
+    fn dependency(&mut self, dll: &str, link: Link, entries: &[ImportEntry]) -> Dependency {
+        let location = self.locate(dll);
+        let local = match &location {
+            DllLocation::AppLocal(_) => Some(self.local(dll)),
+            _ => None,
+        };
+        let exporter = self.exporter(dll);
+        let unresolved = entries
+            .iter()
+            .filter(|entry| !self.exports(dll, &exporter, entry, 0))
+            .map(symbol_name)
+            .collect();
+        let mut dependency = Dependency {
+            dll: dll.to_string(),
+            link,
+            location,
+            imports: entries.len(),
+            unresolved,
+            error: None,
+            repeated: false,
+            children: Vec::new(),
+        };
+        match local {
+            Some(Ok(module)) => {
+                if self.expanded.insert(dll.to_ascii_lowercase()) {
+                    dependency.children = self.dependencies(&module.imports, &module.forwarded);
+                } else {
+                    dependency.repeated = true;
+                }
+            }
+            Some(Err(error)) => dependency.error = Some(error),
+            None => {}
+        }
+        dependency
+    }
+
+    /// True when `dll`, served by `exporter`, exports what `entry` imports.
+    /// Forwarders are followed like the loader does.
+    fn exports(
+        &mut self,
+        dll: &str,
+        exporter: &Exporter,
+        entry: &ImportEntry,
+        hops: usize,
+    ) -> bool {
+        match exporter {
+            Exporter::Builtin => {
+                let name = match (&entry.name, entry.ordinal) {
+                    (Some(name), _) => Some(name.as_str()),
+                    (None, Some(ordinal)) => waygate::ordinal_name(dll, ordinal),
+                    (None, None) => None,
+                };
+                name.is_some_and(|name| waygate::resolve(dll, name).is_some())
+            }
+            Exporter::Local(module) => {
+                let export = match (&entry.name, entry.ordinal) {
+                    (Some(name), _) => module.exports.by_name(name),
+                    (None, Some(ordinal)) => module.exports.by_ordinal(ordinal),
+                    (None, None) => None,
+                };
+                match export.map(|export| &export.target) {
+                    Some(ExportTarget::Rva(_)) => true,
+                    Some(ExportTarget::Forwarder(forwarder)) if hops < MAX_FORWARDER_HOPS => {
+                        let Some((target, symbol)) = split_forwarder(forwarder) else {
+                            return false;
+                        };
+                        let (name, ordinal) = match symbol {
+                            ForwardedSymbol::Name(name) => (Some(name), None),
+                            ForwardedSymbol::Ordinal(ordinal) => (None, Some(ordinal)),
+                        };
+                        let forwarded = ImportEntry {
+                            name,
+                            ordinal,
+                            iat_rva: 0,
+                            name_rva: None,
+                        };
+                        let exporter = self.exporter(&target);
+                        self.exports(&target, &exporter, &forwarded, hops + 1)
+                    }
+                    _ => false,
+                }
+            }
+            Exporter::Nothing => false,
+        }
+    }
+
+    fn exporter(&mut self, dll: &str) -> Exporter {
+        match self.locate(dll) {
+            DllLocation::Builtin => Exporter::Builtin,
+            DllLocation::AppLocal(_) => self.local(dll).map_or(Exporter::Nothing, Exporter::Local),
+            DllLocation::Missing => Exporter::Nothing,
+        }
+    }
+
+    fn locate(&mut self, dll: &str) -> DllLocation {
+        let app_dir = self.app_dir;
+        self.locations
+            .entry(dll.to_ascii_lowercase())
+            .or_insert_with(|| search_dll(app_dir, dll))
+            .clone()
+    }
+
+    /// The parsed app-local `dll`, read on first use.
+    fn local(&mut self, dll: &str) -> Result<Rc<LocalModule>, String> {
+        let key = dll.to_ascii_lowercase();
+        if !self.files.contains_key(&key) {
+            let module = match self.locate(dll) {
+                DllLocation::AppLocal(path) => self.read(&path).map(Rc::new),
+                _ => Err("not an app-local module".to_string()),
+            };
+            self.files.insert(key.clone(), module);
+        }
+        self.files[&key].clone()
+    }
+
+    /// Reads an app-local DLL, refusing files the loader would refuse.
+    fn read(&self, path: &Path) -> Result<LocalModule, String> {
+        let bytes = fs::read(path).map_err(|e| format!("unreadable: {e}"))?;
+        let pe = PeContext::parse(&bytes).ok_or_else(|| "not a PE image".to_string())?;
+        if pe.characteristics & IMAGE_FILE_DLL == 0 {
+            return Err("not a DLL".to_string());
+        }
+        if pe.machine != self.machine {
+            return Err(format!("{} DLL, target is {}", pe.machine, self.machine));
+        }
+        let exports = parse_export_directory(&bytes, &pe)
+            .ok_or_else(|| "malformed export directory".to_string())?;
+        let mut imports = parse_import_modules(&bytes, &pe)
+            .ok_or_else(|| "malformed import directory".to_string())?;
+        imports.extend(
+            parse_delay_import_modules(&bytes, &pe)
+                .ok_or_else(|| "malformed delay-load import directory".to_string())?,
+        );
+        let forwarded = exports.forwarded_modules();
+        Ok(LocalModule {
+            exports,
+            imports,
+            forwarded,
+        })
+    }
+}
@@ -0,0 +1,288 @@
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+const MD5_K: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+/// Streaming MD5 (RFC 1321). Broken for signatures, but still the digest
+/// imphash and section hashes are traded in.
+pub struct Md5 {
+    state: [u32; 4],
+    blocks: BlockBuffer,
+}
+
+impl Md5 {
+    pub fn new() -> Self {
+        Self {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            blocks: BlockBuffer::little_endian(),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.blocks.update(data, |block| md5_block(state, block));
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let state = &mut self.state;
+        self.blocks.finish(|block| md5_block(state, block));
+        self.state
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+    }
+}
+
+fn md5_block(state: &mut [u32; 4], block: &[u8; 64]) {
+    let mut m = [0u32; 16];
+    for (idx, chunk) in block.chunks_exact(4).enumerate() {
+        m[idx] = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+    let [mut a, mut b, mut c, mut d] = *state;
+    for (idx, k) in MD5_K.iter().enumerate() {
+        let (f, g) = match idx {
+            0..=15 => ((b & c) | (!b & d), idx),
+            16..=31 => ((d & b) | (!d & c), (5 * idx + 1) % 16),
+            32..=47 => (b ^ c ^ d, (3 * idx + 5) % 16),
+            _ => (c ^ (b | !d), (7 * idx) % 16),
+        };
+        let shift = MD5_SHIFTS[idx / 16 * 4 + idx % 4];
+        let rotated = a
+            .wrapping_add(f)
+            .wrapping_add(*k)
+            .wrapping_add(m[g])
+            .rotate_left(shift);
+        a = d;
+        d = c;
+        c = b;
+        b = b.wrapping_add(rotated);
+    }
+    for (slot, value) in state.iter_mut().zip([a, b, c, d]) {
+        *slot = slot.wrapping_add(value);
+    }
+}
+
+/// Streaming SHA-1 (FIPS 180-4), for Authenticode signatures made before
+/// the move to SHA-256.
+pub struct Sha1 {
+    state: [u32; 5],
+    blocks: BlockBuffer,
+}
+
+impl Sha1 {
+    pub fn new() -> Self {
+        Self {
+            state: [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0],
+            blocks: BlockBuffer::new(),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.blocks.update(data, |block| sha1_block(state, block));
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let state = &mut self.state;
+        self.blocks.finish(|block| sha1_block(state, block));
+        self.state
+            .iter()
+            .flat_map(|word| word.to_be_bytes())
+            .collect()
+    }
+}
+
+fn sha1_block(state: &mut [u32; 5], block: &[u8; 64]) {
+    let mut w = [0u32; 80];
+    for (idx, chunk) in block.chunks_exact(4).enumerate() {
+        w[idx] = u32::from_be_bytes(chunk.try_into().unwrap());
+    }
+    for idx in 16..80 {
+        w[idx] = (w[idx - 3] ^ w[idx - 8] ^ w[idx - 14] ^ w[idx - 16]).rotate_left(1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e] = *state;
+    for (idx, word) in w.iter().enumerate() {
+        let (f, k) = match idx {
+            0..=19 => ((b & c) | (!b & d), 0x5A827999),
+            20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+            40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+            _ => (b ^ c ^ d, 0xCA62C1D6),
+        };
+        let temp = a
+            .rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(*word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+    for (slot, value) in state.iter_mut().zip([a, b, c, d, e]) {
+        *slot = slot.wrapping_add(value);
+    }
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Streaming SHA-256 (FIPS 180-4).
+pub struct Sha256 {
+    state: [u32; 8],
+    blocks: BlockBuffer,
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Self {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            blocks: BlockBuffer::new(),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.blocks.update(data, |block| sha256_block(state, block));
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let state = &mut self.state;
+        self.blocks.finish(|block| sha256_block(state, block));
+        self.state
+            .iter()
+            .flat_map(|word| word.to_be_bytes())
+            .collect()
+    }
+}
+
+fn sha256_block(state: &mut [u32; 8], block: &[u8; 64]) {
+    let mut w = [0u32; 64];
+    for (idx, chunk) in block.chunks_exact(4).enumerate() {
+        w[idx] = u32::from_be_bytes(chunk.try_into().unwrap());
+    }
+    for idx in 16..64 {
+        let s0 = w[idx - 15].rotate_right(7) ^ w[idx - 15].rotate_right(18) ^ (w[idx - 15] >> 3);
+        let s1 = w[idx - 2].rotate_right(17) ^ w[idx - 2].rotate_right(19) ^ (w[idx - 2] >> 10);
+        w[idx] = w[idx - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[idx - 7])
+            .wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for (k, word) in SHA256_K.iter().zip(w) {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let temp1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(*k)
+            .wrapping_add(word);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let temp2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(temp1);
+        d = c;
+        c = b;
+        b = a;
+        a = temp1.wrapping_add(temp2);
+    }
+    for (slot, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *slot = slot.wrapping_add(value);
+    }
+}
+
+/// Splits input into 64-byte blocks and applies the Merkle-Damgard
+/// padding shared by MD5, SHA-1 and SHA-256, which differ only in the byte
+/// order of the trailing bit count.
+struct BlockBuffer {
+    pending: [u8; 64],
+    used: usize,
+    total: u64,
+    little_endian: bool,
+}
+
+impl BlockBuffer {
+    fn new() -> Self {
+        Self {
+            pending: [0; 64],
+            used: 0,
+            total: 0,
+            little_endian: false,
+        }
+    }
+
+    fn little_endian() -> Self {
+        Self {
+            little_endian: true,
+            ..Self::new()
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8], mut compress: impl FnMut(&[u8; 64])) {
+        self.total += data.len() as u64;
+        while !data.is_empty() {
+            let take = (64 - self.used).min(data.len());
+            self.pending[self.used..self.used + take].copy_from_slice(&data[..take]);
+            self.used += take;
+            data = &data[take..];
+            if self.used == 64 {
+                compress(&self.pending);
+                self.used = 0;
+            }
+        }
+    }
+
+    fn finish(&mut self, mut compress: impl FnMut(&[u8; 64])) {
+        let bits = self.total.wrapping_mul(8);
+        let mut tail = vec![0x80];
+        tail.resize((119 - self.used) % 64 + 1, 0);
+        if self.little_endian {
+            tail.extend_from_slice(&bits.to_le_bytes());
+        } else {
+            tail.extend_from_slice(&bits.to_be_bytes());
+        }
+        let total = self.total;
+        self.update(&tail, &mut compress);
+        self.total = total;
+    }
+}
+
+/// Adler-32 (RFC 1950), the checksum zlib streams and UPX pack headers
+/// carry.
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// Lowercase hex, as hash tools print digests.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
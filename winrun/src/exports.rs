@@ -0,0 +1,128 @@
+use crate::pe::{read_c_string, read_u16, read_u32, PeContext, IMAGE_DIRECTORY_ENTRY_EXPORT};
+
+/// Where an exported ordinal points.
+#[derive(Clone, Debug)]
+pub enum ExportTarget {
+    /// Code or data inside the exporting image.
+    Rva(u32),
+    /// `MODULE.Function` or `MODULE.#ordinal`, resolved in another DLL.
+    Forwarder(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct ExportEntry {
+    /// Biased ordinal, as imports by ordinal refer to it.
+    pub ordinal: u16,
+    pub name: Option<String>,
+    pub target: ExportTarget,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ExportDirectory {
+    /// Name the DLL was linked as, from the directory itself.
+    pub dll_name: String,
+    pub entries: Vec<ExportEntry>,
+}
+
+impl ExportDirectory {
+    pub fn by_name(&self, name: &str) -> Option<&ExportEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name.as_deref() == Some(name))
+    }
+
+    pub fn by_ordinal(&self, ordinal: u16) -> Option<&ExportEntry> {
+        self.entries.iter().find(|entry| entry.ordinal == ordinal)
+    }
+
+    /// Modules named by forwarders, in first-seen order.
+    pub fn forwarded_modules(&self) -> Vec<String> {
+        let mut modules: Vec<String> = Vec::new();
+        for entry in &self.entries {
+            if let ExportTarget::Forwarder(forwarder) = &entry.target {
+                if let Some((module, _)) = split_forwarder(forwarder) {
+                    if !modules.iter().any(|m| m.eq_ignore_ascii_case(&module)) {
+                        modules.push(module);
+                    }
+                }
+            }
+        }
+        modules
+    }
+}
+
+/// Symbol half of a forwarder string.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ForwardedSymbol {
+    Name(String),
+    Ordinal(u16),
+}
+
+/// Splits `NTDLL.RtlAllocateHeap` / `MYLIB.#12` into the DLL file name
+/// (`.dll` appended) and the symbol. Module names may contain dots
+/// (API sets), function names may not, so the split is at the last one.
+pub fn split_forwarder(forwarder: &str) -> Option<(String, ForwardedSymbol)> {
+    let (module, symbol) = forwarder.rsplit_once('.')?;
+    if module.is_empty() || symbol.is_empty() {
+        return None;
+    }
+    let symbol = match symbol.strip_prefix('#') {
+        Some(ordinal) => ForwardedSymbol::Ordinal(ordinal.parse().ok()?),
+        None => ForwardedSymbol::Name(symbol.to_string()),
+    };
+    Some((format!("{module}.dll"), symbol))
+}
+
+/// Parses IMAGE_DIRECTORY_ENTRY_EXPORT. Exports whose RVA falls inside the
+/// export directory itself are forwarders; zero RVAs are unused ordinals
+/// and skipped.
+pub fn parse_export_directory(bytes: &[u8], pe: &PeContext) -> Option<ExportDirectory> {
+    let Some(dir) = pe.directory(IMAGE_DIRECTORY_ENTRY_EXPORT) else {
+        return Some(ExportDirectory::default());
+    };
+    let base = pe.rva_to_offset(dir.rva as usize)?;
+    let name_rva = read_u32(bytes, base + 12)?;
+    let ordinal_base = read_u32(bytes, base + 16)?;
+    let function_count = read_u32(bytes, base + 20)? as usize;
+    let name_count = read_u32(bytes, base + 24)? as usize;
+    let functions_rva = read_u32(bytes, base + 28)? as usize;
+    let names_rva = read_u32(bytes, base + 32)? as usize;
+    let name_ordinals_rva = read_u32(bytes, base + 36)? as usize;
+    if function_count > 0x1_0000 || name_count > function_count {
+        return None;
+    }
+
+    let dll_name = pe
+        .rva_to_offset(name_rva as usize)
+        .and_then(|off| read_c_string(bytes, off))
+        .unwrap_or_default();
+
+    let mut names = vec![None; function_count];
+    for idx in 0..name_count {
+        let name_rva = read_u32(bytes, pe.rva_to_offset(names_rva + idx * 4)?)?;
+        let index = read_u16(bytes, pe.rva_to_offset(name_ordinals_rva + idx * 2)?)? as usize;
+        let name = read_c_string(bytes, pe.rva_to_offset(name_rva as usize)?)?;
+        *names.get_mut(index)? = Some(name);
+    }
+
+    let directory_range = dir.rva..dir.rva.saturating_add(dir.size);
+    let mut entries = Vec::new();
+    for (index, name) in names.into_iter().enumerate() {
+        let rva = read_u32(bytes, pe.rva_to_offset(functions_rva + index * 4)?)?;
+        if rva == 0 {
+            continue;
+        }
+        let target = if directory_range.contains(&rva) {
+            ExportTarget::Forwarder(read_c_string(bytes, pe.rva_to_offset(rva as usize)?)?)
+        } else {
+            ExportTarget::Rva(rva)
+        };
+        entries.push(ExportEntry {
+            ordinal: u16::try_from(ordinal_base as usize + index).ok()?,
+            name,
+            target,
+        });
+    }
+
+    Some(ExportDirectory { dll_name, entries })
+}
@@ -0,0 +1,138 @@
+use crate::digest::{hex, Md5, Sha256};
+use crate::imports::parse_import_modules;
+use crate::ordlookup;
+use crate::packer::shannon_entropy;
+use crate::pe::{
+    PeContext, IMAGE_SCN_CNT_CODE, IMAGE_SCN_CNT_INITIALIZED_DATA,
+    IMAGE_SCN_CNT_UNINITIALIZED_DATA, IMAGE_SCN_MEM_DISCARDABLE, IMAGE_SCN_MEM_EXECUTE,
+    IMAGE_SCN_MEM_READ, IMAGE_SCN_MEM_SHARED, IMAGE_SCN_MEM_WRITE,
+};
+
+pub struct SectionFingerprint {
+    pub name: String,
+    pub virtual_address: usize,
+    pub virtual_size: usize,
+    pub raw_size: usize,
+    pub characteristics: u32,
+    /// MD5 of the section's raw data.
+    pub md5: String,
+    pub entropy: f64,
+}
+
+/// Identifiers that key an image across copies and rebuilds.
+pub struct Fingerprint {
+    /// SHA-256 of the whole file.
+    pub sha256: String,
+    /// `None` for an image without an import table.
+    pub imphash: Option<String>,
+    pub import_count: usize,
+    pub sections: Vec<SectionFingerprint>,
+}
+
+pub fn fingerprint(bytes: &[u8], pe: &PeContext) -> Fingerprint {
+    let mut sha256 = Sha256::new();
+    sha256.update(bytes);
+    let (imphash, import_count) = match imphash(bytes, pe) {
+        Some((hash, count)) => (Some(hash), count),
+        None => (None, 0),
+    };
+    let sections = pe
+        .sections
+        .iter()
+        .map(|section| {
+            let end = (section.raw_ptr + section.raw_size).min(bytes.len());
+            let data = bytes.get(section.raw_ptr..end).unwrap_or_default();
+            let mut md5 = Md5::new();
+            md5.update(data);
+            SectionFingerprint {
+                name: section.name.clone(),
+                virtual_address: section.virtual_address,
+                virtual_size: section.virtual_size,
+                raw_size: section.raw_size,
+                characteristics: section.characteristics,
+                md5: hex(&md5.finish()),
+                entropy: shannon_entropy(data),
+            }
+        })
+        .collect();
+    Fingerprint {
+        sha256: hex(&sha256.finish()),
+        imphash,
+        import_count,
+        sections,
+    }
+}
+
+/// imphash as pefile and VirusTotal compute it: the MD5 of every regular
+/// import in table order as `dll.function`, lowercase, comma-separated,
+/// with `.dll`/`.ocx`/`.sys` dropped from the DLL name. Returns the hash
+/// and the number of imports hashed.
+pub fn imphash(bytes: &[u8], pe: &PeContext) -> Option<(String, usize)> {
+    let modules = parse_import_modules(bytes, pe)?;
+    let mut entries = Vec::new();
+    for module in &modules {
+        let dll = module.dll.to_ascii_lowercase();
+        let library = match dll.rsplit_once('.') {
+            Some((stem, "dll" | "ocx" | "sys")) => stem,
+            _ => dll.as_str(),
+        };
+        for entry in &module.entries {
+            let function = match (&entry.name, entry.ordinal) {
+                (Some(name), _) => name.to_ascii_lowercase(),
+                (None, Some(ordinal)) => ordlookup::ordinal_name(&dll, ordinal)
+                    .map_or_else(|| format!("ord{ordinal}"), str::to_ascii_lowercase),
+                (None, None) => continue,
+            };
+            entries.push(format!("{library}.{function}"));
+        }
+    }
+    if entries.is_empty() {
+        return None;
+    }
+    let mut md5 = Md5::new();
+    md5.update(entries.join(",").as_bytes());
+    Some((hex(&md5.finish()), entries.len()))
+}
+
+/// `rwx` access followed by what the section holds, e.g. `r-x code`.
+pub fn section_flags(characteristics: u32) -> String {
+    let bit = |flag: u32, c: char| if characteristics & flag != 0 { c } else { '-' };
+    let mut flags = format!(
+        "{}{}{}",
+        bit(IMAGE_SCN_MEM_READ, 'r'),
+        bit(IMAGE_SCN_MEM_WRITE, 'w'),
+        bit(IMAGE_SCN_MEM_EXECUTE, 'x')
+    );
+    for (flag, name) in [
+        (IMAGE_SCN_CNT_CODE, "code"),
+        (IMAGE_SCN_CNT_INITIALIZED_DATA, "data"),
+        (IMAGE_SCN_CNT_UNINITIALIZED_DATA, "bss"),
+        (IMAGE_SCN_MEM_SHARED, "shared"),
+        (IMAGE_SCN_MEM_DISCARDABLE, "discardable"),
+    ] {
+        if characteristics & flag != 0 {
+            flags.push(' ');
+            flags.push_str(name);
+        }
+    }
+    flags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Imports ws2_32 ordinals 23 and 9 (`socket`, `htons`), the unknown
+    /// ordinal 999 and kernel32's `ExitProcess`.
+    const ORD: &[u8] = include_bytes!("../../tests/fixtures/ord.exe");
+
+    #[test]
+    fn imphash_names_known_ordinals() {
+        let pe = PeContext::parse(ORD).expect("image parses");
+        // md5("ws2_32.socket,ws2_32.htons,ws2_32.ord999,kernel32.exitprocess")
+        assert_eq!(
+            imphash(ORD, &pe),
+            Some(("c2e1a540f581cedfb691c18b0710bbb6".to_string(), 4))
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use crate::digest::adler32;
+use crate::pe::{read_u16, read_u32};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Largest chunk of a stored (uncompressed) deflate block.
+const STORED_BLOCK_MAX: usize = 0xFFFF;
+
+/// Converts an RT_ICON image to a PNG file. Vista-style icons already are
+/// PNGs; the classic format is a headerless DIB (BITMAPINFOHEADER with a
+/// doubled height, the colour bitmap, then a 1bpp AND mask), decoded here
+/// for the uncompressed 1/4/8/24/32bpp layouts.
+pub fn icon_to_png(data: &[u8]) -> Option<Vec<u8>> {
+    if data.starts_with(PNG_SIGNATURE) {
+        return Some(data.to_vec());
+    }
+    let (width, height, rgba) = decode_icon_dib(data)?;
+    Some(encode_png(width, height, &rgba))
+}
+
+fn decode_icon_dib(data: &[u8]) -> Option<(u32, u32, Vec<u8>)> {
+    let header_size = read_u32(data, 0)? as usize;
+    let width = read_u32(data, 4)? as i32;
+    let height = read_u32(data, 8)? as i32 / 2;
+    let bit_count = read_u16(data, 14)?;
+    let compression = read_u32(data, 16)?;
+    let colors_used = read_u32(data, 32)? as usize;
+    if header_size < 40 || compression != 0 || !(1..=256).contains(&width) {
+        return None;
+    }
+    if !(1..=256).contains(&height) || ![1, 4, 8, 24, 32].contains(&bit_count) {
+        return None;
+    }
+    let (width, height, bpp) = (width as usize, height as usize, bit_count as usize);
+
+    let palette_len = match bpp {
+        1 | 4 | 8 if colors_used == 0 => 1 << bpp,
+        1 | 4 | 8 => colors_used.min(1 << bpp),
+        _ => 0,
+    };
+    let palette = data.get(header_size..header_size + palette_len * 4)?;
+    let pixels = header_size + palette_len * 4;
+    let stride = (width * bpp).div_ceil(32) * 4;
+    let mask = pixels + stride * height;
+    let mask_stride = width.div_ceil(32) * 4;
+
+    let mut rgba = vec![0u8; width * height * 4];
+    let mut any_alpha = false;
+    for y in 0..height {
+        // DIB rows are stored bottom-up.
+        let row = pixels + (height - 1 - y) * stride;
+        for x in 0..width {
+            let (b, g, r, a) = match bpp {
+                32 => {
+                    let px = data.get(row + x * 4..row + x * 4 + 4)?;
+                    (px[0], px[1], px[2], px[3])
+                }
+                24 => {
+                    let px = data.get(row + x * 3..row + x * 3 + 3)?;
+                    (px[0], px[1], px[2], 0)
+                }
+                _ => {
+                    let bit = x * bpp;
+                    let byte = *data.get(row + bit / 8)?;
+                    let index = (byte >> (8 - bpp - bit % 8)) & ((1 << bpp) - 1) as u8;
+                    let color = palette.get(index as usize * 4..index as usize * 4 + 4)?;
+                    (color[0], color[1], color[2], 0)
+                }
+            };
+            any_alpha |= a != 0;
+            let out = (y * width + x) * 4;
+            rgba[out..out + 4].copy_from_slice(&[r, g, b, a]);
+        }
+    }
+
+    // Without an alpha channel, transparency comes from the AND mask.
+    if !any_alpha {
+        for y in 0..height {
+            let row = mask + (height - 1 - y) * mask_stride;
+            for x in 0..width {
+                let transparent = data
+                    .get(row + x / 8)
+                    .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0);
+                rgba[(y * width + x) * 4 + 3] = if transparent { 0 } else { 0xFF };
+            }
+        }
+    }
+    Some((width as u32, height as u32, rgba))
+}
+
+/// Minimal PNG writer: 8-bit RGBA, no filtering, zlib stream made of
+/// stored deflate blocks.
+fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let row_len = width as usize * 4;
+    let mut raw = Vec::with_capacity((row_len + 1) * height as usize);
+    for row in rgba.chunks_exact(row_len) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(STORED_BLOCK_MAX).peekable();
+    while let Some(block) = blocks.next() {
+        zlib.push(u8::from(blocks.peek().is_none()));
+        let len = block.len() as u16;
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
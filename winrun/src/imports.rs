@@ -0,0 +1,163 @@
+use crate::pe::{read_c_string, read_u32, read_u64, PeContext, IMAGE_DIRECTORY_ENTRY_DELAY_IMPORT};
+
+/// `dlattrRva`: delay descriptor fields are RVAs rather than VAs (VC7+).
+const DELAY_ATTR_RVA: u32 = 0x1;
+
+pub struct ImportEntry {
+    pub name: Option<String>,
+    pub ordinal: Option<u16>,
+    /// RVA of the IAT slot the loader patches for this import.
+    pub iat_rva: usize,
+    /// RVA of the name string inside its hint/name entry.
+    pub name_rva: Option<usize>,
+}
+
+pub struct ImportModule {
+    pub dll: String,
+    /// RVA of the DLL name string.
+    pub name_rva: usize,
+    pub entries: Vec<ImportEntry>,
+    /// Set for IMAGE_DIRECTORY_ENTRY_DELAY_IMPORT descriptors.
+    pub delay_loaded: bool,
+    /// Delay-load only: RVA of the HMODULE cell the helper fills in.
+    pub module_handle_rva: Option<usize>,
+}
+
+/// Walks the import descriptor table, reading names from OriginalFirstThunk
+/// when present (FirstThunk may already be bound) and recording the matching
+/// FirstThunk slot for each entry.
+pub fn parse_import_modules(bytes: &[u8], pe: &PeContext) -> Option<Vec<ImportModule>> {
+    let import_rva = pe.import_rva;
+    if import_rva == 0 {
+        return Some(Vec::new());
+    }
+
+    let mut modules = Vec::new();
+    let mut idx = 0usize;
+    loop {
+        let desc = pe.rva_to_offset(import_rva as usize + (idx * 20))?;
+        let original_first_thunk = read_u32(bytes, desc)?;
+        let name_rva = read_u32(bytes, desc + 12)?;
+        let first_thunk = read_u32(bytes, desc + 16)?;
+        if original_first_thunk == 0 && name_rva == 0 && first_thunk == 0 {
+            break;
+        }
+
+        let dll = pe
+            .rva_to_offset(name_rva as usize)
+            .and_then(|off| read_c_string(bytes, off))
+            .unwrap_or_default();
+        let thunk_rva = if original_first_thunk != 0 {
+            original_first_thunk
+        } else {
+            first_thunk
+        } as usize;
+
+        modules.push(ImportModule {
+            dll,
+            name_rva: name_rva as usize,
+            entries: read_thunks(bytes, pe, thunk_rva, first_thunk as usize, 0)?,
+            delay_loaded: false,
+            module_handle_rva: None,
+        });
+        idx += 1;
+    }
+
+    Some(modules)
+}
+
+/// Walks the delay-load descriptor table (ImgDelayDescr). Old VC6-style
+/// descriptors store VAs instead of RVAs; both are normalized to RVAs.
+pub fn parse_delay_import_modules(bytes: &[u8], pe: &PeContext) -> Option<Vec<ImportModule>> {
+    let Some(dir) = pe.directory(IMAGE_DIRECTORY_ENTRY_DELAY_IMPORT) else {
+        return Some(Vec::new());
+    };
+
+    let mut modules = Vec::new();
+    let mut idx = 0usize;
+    loop {
+        let desc = pe.rva_to_offset(dir.rva as usize + idx * 32)?;
+        let attributes = read_u32(bytes, desc)?;
+        let name = read_u32(bytes, desc + 4)?;
+        let module_handle = read_u32(bytes, desc + 8)?;
+        let iat = read_u32(bytes, desc + 12)?;
+        let name_table = read_u32(bytes, desc + 16)?;
+        if name == 0 && iat == 0 && name_table == 0 {
+            break;
+        }
+
+        let bias = if attributes & DELAY_ATTR_RVA != 0 {
+            0
+        } else {
+            pe.image_base
+        };
+        let to_rva = |value: u32| (value as u64).wrapping_sub(bias) as usize;
+        let dll = pe
+            .rva_to_offset(to_rva(name))
+            .and_then(|off| read_c_string(bytes, off))
+            .unwrap_or_default();
+
+        modules.push(ImportModule {
+            dll,
+            name_rva: to_rva(name),
+            entries: read_thunks(bytes, pe, to_rva(name_table), to_rva(iat), bias)?,
+            delay_loaded: true,
+            module_handle_rva: (module_handle != 0).then(|| to_rva(module_handle)),
+        });
+        idx += 1;
+    }
+
+    Some(modules)
+}
+
+/// Reads a zero-terminated thunk array at `name_table_rva`. Hint/name
+/// pointers are VAs in old-style delay descriptors, hence `bias`.
+fn read_thunks(
+    bytes: &[u8],
+    pe: &PeContext,
+    name_table_rva: usize,
+    iat_rva: usize,
+    bias: u64,
+) -> Option<Vec<ImportEntry>> {
+    let thunk_size = if pe.is_pe64 { 8 } else { 4 };
+    let ordinal_flag: u64 = if pe.is_pe64 {
+        0x8000_0000_0000_0000
+    } else {
+        0x8000_0000
+    };
+
+    let mut entries = Vec::new();
+    let mut t = 0usize;
+    while let Some(thunk_off) = pe.rva_to_offset(name_table_rva + t * thunk_size) {
+        let entry = if pe.is_pe64 {
+            read_u64(bytes, thunk_off)?
+        } else {
+            read_u32(bytes, thunk_off)? as u64
+        };
+        if entry == 0 {
+            break;
+        }
+
+        let slot_rva = iat_rva + t * thunk_size;
+        if entry & ordinal_flag == 0 {
+            let hint_name_rva = (entry.wrapping_sub(bias) & 0x7FFF_FFFF) as usize;
+            let hint_name = pe.rva_to_offset(hint_name_rva)?;
+            entries.push(ImportEntry {
+                name: Some(read_c_string(bytes, hint_name + 2)?),
+                ordinal: None,
+                iat_rva: slot_rva,
+                name_rva: Some(hint_name_rva + 2),
+            });
+        } else {
+            entries.push(ImportEntry {
+                name: None,
+                ordinal: Some((entry & 0xFFFF) as u16),
+                iat_rva: slot_rva,
+                name_rva: None,
+            });
+        }
+        t += 1;
+    }
+
+    Some(entries)
+}
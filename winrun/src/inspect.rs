@@ -0,0 +1,673 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::authenticode::{format_serial, image_hash, parse_signature, ContentCheck};
+use crate::clr::parse_clr;
+use crate::debugdir::{codeview, is_reproducible, parse_debug_directory, CodeView, DebugPayload};
+use crate::deps::{missing_modules, Dependency, Link};
+use crate::digest::hex;
+use crate::fingerprint::{fingerprint, section_flags};
+use crate::icon::icon_to_png;
+use crate::manifest::{load_manifest, Manifest};
+use crate::modules::DllLocation;
+use crate::overlay::find_overlay;
+use crate::packer::{detect_packer, HIGH_ENTROPY};
+use crate::pe::{
+    format_timestamp, PeContext, IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR, IMAGE_DIRECTORY_ENTRY_TLS,
+};
+use crate::resources::{
+    manifest_text, parse_icon_group, parse_resources, parse_string_block, parse_version_info,
+    type_name, Resource, ResourceId, VersionInfo, RT_GROUP_ICON, RT_ICON, RT_MANIFEST, RT_STRING,
+    RT_VERSION,
+};
+use crate::upx;
+
+/// Unresolved symbols named per module before the rest are counted.
+const MAX_UNRESOLVED_SHOWN: usize = 8;
+
+/// Settings for `winrun inspect`.
+pub struct InspectOptions {
+    /// Directory to write the icons to, one PNG per group image.
+    pub icon_dir: Option<PathBuf>,
+    /// File to copy the overlay to.
+    pub overlay_out: Option<PathBuf>,
+}
+
+/// Prints what a PE image declares about itself without loading it.
+pub fn inspect_pe(target: &Path, bytes: &[u8], options: &InspectOptions) -> Result<(), String> {
+    let pe =
+        PeContext::parse(bytes).ok_or_else(|| "inspect: target is not a PE image".to_string())?;
+    let resources =
+        parse_resources(bytes, &pe).ok_or_else(|| "malformed resource directory".to_string())?;
+    let version = resources
+        .iter()
+        .find(|res| res.kind.is(RT_VERSION))
+        .and_then(|res| parse_version_info(&res.data));
+
+    println!("inspect: {}", target.display());
+    println!("  format: {}", if pe.is_pe64 { "PE32+" } else { "PE32" });
+    println!("  machine: {}", pe.machine);
+    println!("  subsystem: {}", pe.subsystem);
+    let product = version.as_ref().and_then(|info| info.string("ProductName"));
+    let file_version = version
+        .as_ref()
+        .and_then(|info| info.string("FileVersion").or(info.file_version.as_deref()));
+    println!("  product: {}", product.unwrap_or("(none)"));
+    println!("  file version: {}", file_version.unwrap_or("(none)"));
+
+    print_build_report(bytes, &pe);
+    print_fingerprint_report(bytes, &pe);
+    print_signature_report(bytes, &pe);
+    print_packer_report(bytes, &pe);
+    print_tls_report(&pe);
+    print_clr_report(bytes, &pe);
+    print_overlay_report(bytes, &pe, options.overlay_out.as_deref())?;
+    print_resource_list(&resources);
+    if let Some(info) = &version {
+        print_version_info(info);
+    }
+    print_manifests(&resources);
+    if let Some(manifest) = load_manifest(bytes, &pe) {
+        print_manifest_report(&manifest);
+    }
+    print_string_tables(&resources);
+    print_icon_groups(target, &resources, options.icon_dir.as_deref())
+}
+
+/// Data past the end of the image and the installer it belongs to,
+/// optionally copied to `out`.
+fn print_overlay_report(bytes: &[u8], pe: &PeContext, out: Option<&Path>) -> Result<(), String> {
+    let Some(overlay) = find_overlay(bytes, pe) else {
+        println!("overlay: none");
+        return match out {
+            Some(_) => Err("inspect: the image has no overlay to extract".to_string()),
+            None => Ok(()),
+        };
+    };
+    println!(
+        "overlay: {:#x}..{:#x} ({} bytes{})",
+        overlay.start,
+        overlay.end,
+        overlay.len(),
+        if overlay.before_certificates {
+            ", certificate table follows"
+        } else {
+            ""
+        }
+    );
+    match &overlay.installer {
+        Some((installer, offset)) => println!("  payload: {installer} at {offset:#x}"),
+        None => println!("  payload: unrecognized"),
+    }
+    if let Some(path) = out {
+        fs::write(path, overlay.data(bytes))
+            .map_err(|e| format!("failed to write overlay {}: {e}", path.display()))?;
+        println!("  extracted to {}", path.display());
+    }
+    Ok(())
+}
+
+/// Who signed the image, whether each signer's signed attributes cover the
+/// embedded image digest, and whether that digest still matches the image.
+/// Certificate chains and the signature value itself are not checked.
+pub fn print_signature_report(bytes: &[u8], pe: &PeContext) {
+    let signature = match parse_signature(bytes, pe) {
+        Ok(Some(signature)) => signature,
+        Ok(None) => {
+            println!("signature: none (unsigned)");
+            return;
+        }
+        Err(err) => {
+            println!("signature: {err}");
+            return;
+        }
+    };
+    println!(
+        "signature: Authenticode, {} signer(s), {} certificate(s)",
+        signature.signers.len(),
+        signature.certificate_count
+    );
+    for signer in &signature.signers {
+        println!(
+            "  signer: {}",
+            signer
+                .subject
+                .as_deref()
+                .unwrap_or("(certificate not embedded)")
+        );
+        println!("  issuer: {}", signer.issuer);
+        println!("  serial: {}", format_serial(&signer.serial));
+        match &signer.signing_time {
+            Some((time, source)) => println!("  signed: {time} ({})", source.name()),
+            None => println!("  signed: no signing time"),
+        }
+        match &signer.content {
+            ContentCheck::Matches => {
+                println!("  message digest: matches the signed content")
+            }
+            ContentCheck::Mismatch => println!(
+                "  message digest: MISMATCH: signed content was altered, signature invalid"
+            ),
+            ContentCheck::Missing => {
+                println!("  message digest: missing from signed attributes, signature invalid")
+            }
+            ContentCheck::Unsupported(algorithm) => println!(
+                "  message digest: not checked ({} unsupported)",
+                algorithm.name()
+            ),
+        }
+    }
+    let algorithm = &signature.digest_algorithm;
+    println!(
+        "  signed digest: {} {}",
+        algorithm.name(),
+        hex(&signature.signed_digest)
+    );
+    match image_hash(bytes, pe, algorithm) {
+        Some(hash) if hash == signature.signed_digest && signature.is_invalid() => println!(
+            "  image hash: {} (matches the embedded digest, which no valid signature covers)",
+            hex(&hash)
+        ),
+        Some(hash) if hash == signature.signed_digest => {
+            println!("  image hash: {} (matches)", hex(&hash))
+        }
+        Some(hash) => println!(
+            "  image hash: {} (MISMATCH: modified after signing)",
+            hex(&hash)
+        ),
+        None => println!(
+            "  image hash: not recomputed ({} unsupported)",
+            algorithm.name()
+        ),
+    }
+    println!("  trust: certificate chain and signature value not verified");
+}
+
+/// Prints the file hash, imphash and each section's flags, hash and
+/// entropy.
+pub fn print_fingerprint_report(bytes: &[u8], pe: &PeContext) {
+    let fingerprint = fingerprint(bytes, pe);
+    println!("fingerprint: sha256 {}", fingerprint.sha256);
+    match &fingerprint.imphash {
+        Some(imphash) => println!(
+            "  imphash: {imphash} ({} import(s))",
+            fingerprint.import_count
+        ),
+        None => println!("  imphash: none (no imports)"),
+    }
+    for section in &fingerprint.sections {
+        println!(
+            "  section {:<8} {:#010x} +{:#x} (raw {:#x}), {}, entropy {:.2}, md5 {}",
+            section.name,
+            section.virtual_address,
+            section.virtual_size,
+            section.raw_size,
+            section_flags(section.characteristics),
+            section.entropy,
+            section.md5
+        );
+    }
+}
+
+/// Prints the dependency tree under `name`, one module per line indented
+/// by depth, then every DLL that could not be found.
+pub fn print_dependency_report(name: &str, tree: &[Dependency]) {
+    println!("dependencies: {name}");
+    print_dependencies(tree, 1);
+    match missing_modules(tree).as_slice() {
+        [] => println!("missing modules: none"),
+        missing => println!("missing modules: {}", missing.join(", ")),
+    }
+}
+
+fn print_dependencies(tree: &[Dependency], depth: usize) {
+    for dependency in tree {
+        let location = match &dependency.location {
+            DllLocation::Builtin => "waygate".to_string(),
+            DllLocation::AppLocal(path) => format!("app-local {}", path.display()),
+            DllLocation::Missing => "MISSING".to_string(),
+        };
+        let link = match dependency.link {
+            Link::Import => "",
+            Link::DelayImport => " [delay-load]",
+            Link::Forwarder => " [forwarder]",
+        };
+        let imports = match (dependency.imports, dependency.unresolved.as_slice()) {
+            (0, _) => String::new(),
+            (count, []) => format!(", {count} import(s), all resolved"),
+            (count, unresolved) => {
+                let shown = unresolved.len().min(MAX_UNRESOLVED_SHOWN);
+                let more = match unresolved.len() - shown {
+                    0 => String::new(),
+                    more => format!(" and {more} more"),
+                };
+                format!(
+                    ", {count} import(s), {} unresolved: {}{more}",
+                    unresolved.len(),
+                    unresolved[..shown].join(", ")
+                )
+            }
+        };
+        let note = match (&dependency.error, dependency.repeated) {
+            (Some(error), _) => format!(" ({error})"),
+            (None, true) => " (dependencies listed above)".to_string(),
+            (None, false) => String::new(),
+        };
+        println!(
+            "{:indent$}{}: {location}{imports}{link}{note}",
+            "",
+            dependency.dll,
+            indent = depth * 2
+        );
+        print_dependencies(&dependency.children, depth + 1);
+    }
+}
+
+/// Prints packer evidence and, for UPX, what decompression recovered.
+pub fn print_packer_report(bytes: &[u8], pe: &PeContext) {
+    let report = detect_packer(bytes, pe);
+    match (&report.signature, report.packer_name()) {
+        (Some((packer, sections)), _) => {
+            println!("packer: {packer} (sections {})", sections.join(", "))
+        }
+        (None, Some(packer)) if report.upx_header => println!("packer: {packer} (pack header)"),
+        (None, Some(packer)) => println!("packer: {packer} (high entropy, stub-sized imports)"),
+        (None, None) => println!("packer: none"),
+    }
+    if !report.sections.is_empty() {
+        let sections: Vec<String> = report
+            .sections
+            .iter()
+            .map(|section| {
+                let high = if section.entropy >= HIGH_ENTROPY {
+                    " (high)"
+                } else {
+                    ""
+                };
+                format!("{} {:.2}{high}", section.name, section.entropy)
+            })
+            .collect();
+        println!("  entropy: {}", sections.join(", "));
+    }
+    if report.loader_only_imports {
+        println!("  imports: loader functions only (the rest are resolved at run time)");
+    }
+    if report.packer_name() != Some("UPX") {
+        return;
+    }
+    match upx::unpack(bytes, pe) {
+        Ok(unpacked) => {
+            let header = &unpacked.header;
+            println!(
+                "  UPX pack header v{}: {} level {}, {} -> {} bytes",
+                header.version,
+                header.method.name(),
+                header.level,
+                header.c_len,
+                header.u_len
+            );
+            let filter = match (header.filter, unpacked.filter_reversed) {
+                (0, _) => String::new(),
+                (id, true) => format!(", call filter {id:#04x} reversed"),
+                (id, false) => format!(", call filter {id:#04x} not reversed"),
+            };
+            println!(
+                "  unpacked: {} import(s) from {} DLL(s){filter}",
+                unpacked.import_count, unpacked.dll_count
+            );
+        }
+        Err(err) => println!("  unpack failed: {err}"),
+    }
+}
+
+/// Prints the TLS directory. Callbacks are flagged because they run before
+/// the entry point, where debuggers usually stop first.
+pub fn print_tls_report(pe: &PeContext) {
+    let Some(tls) = &pe.tls else {
+        match pe.directory(IMAGE_DIRECTORY_ENTRY_TLS) {
+            Some(_) => println!("tls: malformed directory"),
+            None => println!("tls: none"),
+        }
+        return;
+    };
+    println!(
+        "tls: {}-byte template + {} zero-filled, index at {:#x}, {} callback(s)",
+        tls.template_size(),
+        tls.zero_fill,
+        tls.index_address,
+        tls.callbacks.len()
+    );
+    for callback in &tls.callbacks {
+        let section = callback
+            .checked_sub(pe.image_base)
+            .and_then(|rva| {
+                pe.sections.iter().find(|section| {
+                    let start = section.virtual_address as u64;
+                    rva >= start && rva < start + section.mapped_size as u64
+                })
+            })
+            .map_or_else(|| "outside the image".to_string(), |s| s.name.clone());
+        println!("  callback {callback:#x} ({section})");
+    }
+    if !tls.callbacks.is_empty() {
+        println!(
+            "  warning: TLS callbacks run before the entry point, a common anti-analysis trick"
+        );
+    }
+}
+
+/// Prints whether the image is a .NET assembly, what runtime it targets,
+/// and its P/Invoke imports.
+pub fn print_clr_report(bytes: &[u8], pe: &PeContext) {
+    let Some(clr) = parse_clr(bytes, pe) else {
+        match pe.directory(IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR) {
+            Some(_) => println!("managed: .NET (malformed metadata)"),
+            None => println!("managed: no"),
+        }
+        return;
+    };
+    let kind = if clr.is_il_only() {
+        "IL only"
+    } else {
+        "mixed mode"
+    };
+    match &clr.assembly {
+        Some((name, version)) => println!("managed: .NET assembly {name} {version} ({kind})"),
+        None => println!("managed: .NET module ({kind})"),
+    }
+    let (major, minor) = clr.header_version;
+    println!(
+        "  runtime: metadata {}, CLR header {major}.{minor}",
+        clr.metadata_version
+    );
+    if let Some(framework) = &clr.target_framework {
+        println!("  target framework: {framework}");
+    }
+    println!("  flags: {}", clr.flag_names().join(", "));
+    println!("  P/Invoke: {} method(s)", clr.pinvokes.len());
+    for pinvoke in &clr.pinvokes {
+        let last_error = if pinvoke.sets_last_error() {
+            ", SetLastError"
+        } else {
+            ""
+        };
+        println!(
+            "    {} -> {}!{} ({}{last_error})",
+            pinvoke.method,
+            pinvoke.dll(),
+            pinvoke.entry_point,
+            pinvoke.char_set()
+        );
+    }
+}
+
+/// COFF timestamp and debug directory: what identifies this build and
+/// its PDB.
+pub fn print_build_report(bytes: &[u8], pe: &PeContext) {
+    let Some(entries) = parse_debug_directory(bytes, pe) else {
+        println!("debug directory: malformed");
+        return;
+    };
+    if is_reproducible(&entries) {
+        println!(
+            "timestamp: {:#010x} (reproducible build: hash bits, not a date)",
+            pe.time_date_stamp
+        );
+    } else {
+        println!(
+            "timestamp: {} ({:#010x})",
+            format_timestamp(pe.time_date_stamp),
+            pe.time_date_stamp
+        );
+    }
+    if entries.is_empty() {
+        println!("debug directory: none");
+        return;
+    }
+    println!(
+        "debug directory: {} entr{}",
+        entries.len(),
+        if entries.len() == 1 { "y" } else { "ies" }
+    );
+    for entry in &entries {
+        match &entry.payload {
+            DebugPayload::CodeView(CodeView::Rsds { guid, age, path }) => {
+                println!("  CodeView RSDS: GUID {guid} age {age} pdb {path}");
+            }
+            DebugPayload::CodeView(CodeView::Nb10 {
+                signature,
+                age,
+                path,
+            }) => println!("  CodeView NB10: signature {signature:#010x} age {age} pdb {path}"),
+            DebugPayload::Pogo(pogo) => {
+                let names: Vec<&str> = pogo
+                    .entries
+                    .iter()
+                    .map(|(_, _, name)| name.as_str())
+                    .collect();
+                println!(
+                    "  POGO ({}): {} section contribution(s): {}",
+                    pogo.signature,
+                    names.len(),
+                    names.join(" ")
+                );
+            }
+            DebugPayload::Repro(hash) if hash.is_empty() => println!("  REPRO: no hash"),
+            DebugPayload::Repro(hash) => {
+                let hex: String = hash.iter().map(|b| format!("{b:02x}")).collect();
+                println!("  REPRO: {hex}");
+            }
+            DebugPayload::Other => {
+                println!("  {}: {} byte(s)", entry.type_name(), entry.size);
+            }
+        }
+    }
+    if let Some(codeview) = codeview(&entries) {
+        let path = codeview.path();
+        let pdb = path.rsplit(['\\', '/']).next().unwrap_or(path);
+        println!(
+            "  symbol server path: {pdb}/{}/{pdb}",
+            codeview.symbol_key()
+        );
+    }
+}
+
+fn print_resource_list(resources: &[Resource]) {
+    println!(
+        "resources: {} entr{}",
+        resources.len(),
+        if resources.len() == 1 { "y" } else { "ies" }
+    );
+    for res in resources {
+        println!(
+            "  {:<15} {:<8} lang {:#06x} cp {:<5} {} byte(s)",
+            type_name(&res.kind),
+            res.name.to_string(),
+            res.language,
+            res.code_page,
+            res.data.len()
+        );
+    }
+}
+
+fn print_version_info(info: &VersionInfo) {
+    println!("version info:");
+    if let Some(version) = &info.file_version {
+        println!("  fixed file version: {version}");
+    }
+    if let Some(version) = &info.product_version {
+        println!("  fixed product version: {version}");
+    }
+    for (table, key, value) in &info.strings {
+        println!("  [{table}] {key}: {value}");
+    }
+    for (language, code_page) in &info.translations {
+        println!("  translation: {language:#06x} code page {code_page}");
+    }
+}
+
+fn print_manifests(resources: &[Resource]) {
+    for res in resources.iter().filter(|res| res.kind.is(RT_MANIFEST)) {
+        println!("manifest {} (lang {:#06x}):", res.name, res.language);
+        for line in manifest_text(&res.data).lines() {
+            println!("  {line}");
+        }
+    }
+}
+
+/// How the declared manifest requirements are honoured.
+pub fn print_manifest_report(manifest: &Manifest) {
+    println!("manifest requirements:");
+    let level = manifest.execution_level.as_deref().unwrap_or("asInvoker");
+    println!(
+        "  execution level: {level}{}{}",
+        if manifest.ui_access {
+            " (uiAccess)"
+        } else {
+            ""
+        },
+        match level {
+            _ if manifest.requires_admin() => " -- waygate does not elevate",
+            "highestAvailable" => " -- runs as the invoking user",
+            _ => "",
+        }
+    );
+    let version = manifest.reported_version();
+    let supported = manifest.supported_os_names();
+    println!(
+        "  supported OS: {} -> version APIs report {}.{}.{}",
+        if supported.is_empty() {
+            "(none declared)".to_string()
+        } else {
+            supported.join(", ")
+        },
+        version.major,
+        version.minor,
+        version.build
+    );
+    let declared = match (&manifest.dpi_awareness, &manifest.dpi_aware) {
+        (Some(list), _) => format!(" (dpiAwareness {list:?})"),
+        (None, Some(value)) => format!(" (dpiAware {value:?})"),
+        (None, None) => String::new(),
+    };
+    println!("  DPI awareness: {:?}{declared}", manifest.dpi_mode());
+    for dependency in &manifest.dependencies {
+        let served = if dependency.is_common_controls_v6() {
+            "comctl32 v6"
+        } else {
+            "not provided by waygate, its DLLs must be app-local"
+        };
+        println!(
+            "  dependency: {} {} ({}) -> {served}",
+            dependency.name,
+            dependency.version,
+            if dependency.architecture.is_empty() {
+                "any"
+            } else {
+                &dependency.architecture
+            }
+        );
+    }
+}
+
+fn print_string_tables(resources: &[Resource]) {
+    let mut printed_header = false;
+    for res in resources.iter().filter(|res| res.kind.is(RT_STRING)) {
+        let ResourceId::Id(block) = res.name else {
+            continue;
+        };
+        let Some(strings) = parse_string_block(block, &res.data) else {
+            println!("  block {block}: malformed");
+            continue;
+        };
+        if !printed_header {
+            println!("string table:");
+            printed_header = true;
+        }
+        for (id, text) in strings {
+            println!("  {id:>5} (lang {:#06x}): {text:?}", res.language);
+        }
+    }
+}
+
+fn print_icon_groups(
+    target: &Path,
+    resources: &[Resource],
+    icon_dir: Option<&Path>,
+) -> Result<(), String> {
+    if let Some(dir) = icon_dir {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("failed to create icon directory {}: {e}", dir.display()))?;
+    }
+    let stem = target
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("target");
+
+    for group in resources.iter().filter(|res| res.kind.is(RT_GROUP_ICON)) {
+        let Some(entries) = parse_icon_group(&group.data) else {
+            println!("icon group {}: malformed", group.name);
+            continue;
+        };
+        println!(
+            "icon group {} (lang {:#06x}): {} image(s)",
+            group.name,
+            group.language,
+            entries.len()
+        );
+        for (idx, entry) in entries.iter().enumerate() {
+            // Prefer the image in the group's own language.
+            let icon = resources
+                .iter()
+                .filter(|res| res.kind.is(RT_ICON) && res.name.is(entry.icon_id))
+                .min_by_key(|res| res.language != group.language);
+            let Some(icon) = icon else {
+                println!(
+                    "  {}x{} {}bpp: RT_ICON {} missing",
+                    entry.width, entry.height, entry.bit_count, entry.icon_id
+                );
+                continue;
+            };
+            let format = if icon.data.starts_with(b"\x89PNG") {
+                "png"
+            } else {
+                "dib"
+            };
+            print!(
+                "  {}x{} {}bpp {format}, {} byte(s)",
+                entry.width,
+                entry.height,
+                entry.bit_count,
+                icon.data.len()
+            );
+            let Some(dir) = icon_dir else {
+                println!();
+                continue;
+            };
+            let Some(png) = icon_to_png(&icon.data) else {
+                println!(" -> not extracted (unsupported bitmap format)");
+                continue;
+            };
+            // Resource names are arbitrary UTF-16; keep them from naming
+            // paths outside `dir`.
+            let group_name = match &group.name {
+                ResourceId::Id(id) => id.to_string(),
+                ResourceId::Name(name) => name
+                    .chars()
+                    .map(|c| match c {
+                        'A'..='Z' | 'a'..='z' | '0'..='9' | '_' | '-' => c,
+                        _ => '_',
+                    })
+                    .collect(),
+            };
+            let path = dir.join(format!(
+                "{stem}_{group_name}_{idx}_{}x{}.png",
+                entry.width, entry.height
+            ));
+            fs::write(&path, png)
+                .map_err(|e| format!("failed to write icon {}: {e}", path.display()))?;
+            println!(" -> {}", path.display());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,606 @@
+use std::ffi::{c_int, c_void};
+use std::fmt;
+use std::io;
+use std::ptr;
+
+use waygate::{DelayLoad, ThunkSlot, ThunkTable};
+
+use crate::imports::{ImportEntry, ImportModule};
+use crate::modules::Binding;
+use crate::pe::{
+    PeContext, IMAGE_SCN_CNT_UNINITIALIZED_DATA, IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_READ,
+    IMAGE_SCN_MEM_WRITE,
+};
+use crate::reloc::{
+    Relocation, IMAGE_REL_BASED_ABSOLUTE, IMAGE_REL_BASED_DIR64, IMAGE_REL_BASED_HIGH,
+    IMAGE_REL_BASED_HIGHADJ, IMAGE_REL_BASED_HIGHLOW, IMAGE_REL_BASED_LOW,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+
+const PROT_NONE: c_int = 0x0;
+const PROT_READ: c_int = 0x1;
+const PROT_WRITE: c_int = 0x2;
+const PROT_EXEC: c_int = 0x4;
+const MAP_PRIVATE: c_int = 0x02;
+const MAP_ANONYMOUS: c_int = 0x20;
+const MAP_FIXED_NOREPLACE: c_int = 0x10_0000;
+const MAP_FAILED: *mut c_void = !0usize as *mut c_void;
+
+extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        offset: i64,
+    ) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, len: usize, prot: c_int) -> c_int;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Protection {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Protection {
+    fn from_characteristics(characteristics: u32) -> Self {
+        Self {
+            read: characteristics & IMAGE_SCN_MEM_READ != 0,
+            write: characteristics & IMAGE_SCN_MEM_WRITE != 0,
+            execute: characteristics & IMAGE_SCN_MEM_EXECUTE != 0,
+        }
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self {
+            read: self.read || other.read,
+            write: self.write || other.write,
+            execute: self.execute || other.execute,
+        }
+    }
+
+    fn to_prot(self) -> c_int {
+        let mut prot = PROT_NONE;
+        // Windows implies read access for writable or executable pages.
+        if self.read || self.write || self.execute {
+            prot |= PROT_READ;
+        }
+        if self.write {
+            prot |= PROT_WRITE;
+        }
+        if self.execute {
+            prot |= PROT_EXEC;
+        }
+        prot
+    }
+}
+
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            if self.read { 'r' } else { '-' },
+            if self.write { 'w' } else { '-' },
+            if self.execute { 'x' } else { '-' }
+        )
+    }
+}
+
+pub struct MappedSection {
+    pub name: String,
+    pub rva: usize,
+    pub size: usize,
+    pub copied: usize,
+    pub zero_filled: usize,
+    pub protection: Protection,
+}
+
+pub struct RelocationReport {
+    pub delta: i64,
+    pub applied: usize,
+    pub padding: usize,
+    /// Fixup counts keyed by the section holding the patched location, in
+    /// section table order.
+    pub per_section: Vec<(String, usize)>,
+}
+
+pub struct BindReport {
+    pub bound: usize,
+    /// Slots pointed straight into app-local DLLs instead of a thunk.
+    pub local: usize,
+    /// Delay-loaded slots left to resolve lazily on their first call.
+    pub lazy: usize,
+    /// `DLL!symbol` for every slot routed to the missing-import trap.
+    pub missing: Vec<String>,
+}
+
+/// A PE image mapped into this process at its preferred `ImageBase` when
+/// possible, otherwise at whatever address the kernel hands out.
+///
+/// The mapping stays writable until [`LoadedImage::protect`] applies the
+/// per-section protections, so fixups can be written in between.
+pub struct LoadedImage {
+    base: *mut u8,
+    size: usize,
+    header_size: usize,
+    preferred_base: u64,
+    entry_point: u32,
+    is_pe64: bool,
+    sections: Vec<MappedSection>,
+    thunks: Option<ThunkTable>,
+}
+
+impl LoadedImage {
+    pub fn map(bytes: &[u8], pe: &PeContext) -> Result<Self, String> {
+        let size = align_up(pe.size_of_image as usize, PAGE_SIZE);
+        if size == 0 {
+            return Err("image declares SizeOfImage of zero".to_string());
+        }
+
+        let base = reserve(pe.image_base, size)?;
+        let headers = (pe.size_of_headers as usize).min(bytes.len()).min(size);
+        let mut image = Self {
+            base,
+            size,
+            header_size: headers,
+            preferred_base: pe.image_base,
+            entry_point: pe.entry_point,
+            is_pe64: pe.is_pe64,
+            sections: Vec::with_capacity(pe.sections.len()),
+            thunks: None,
+        };
+
+        image.copy_in(0, &bytes[..headers])?;
+
+        for section in &pe.sections {
+            let mem_size = if section.virtual_size == 0 {
+                section.raw_size
+            } else {
+                section.virtual_size
+            };
+            if section.virtual_address.saturating_add(mem_size) > size {
+                return Err(format!(
+                    "section {} (rva {:#x}, size {:#x}) exceeds SizeOfImage {:#x}",
+                    section.name, section.virtual_address, mem_size, size
+                ));
+            }
+
+            let copied = if section.characteristics & IMAGE_SCN_CNT_UNINITIALIZED_DATA != 0
+                || section.raw_ptr == 0
+            {
+                0
+            } else {
+                let end = section
+                    .raw_ptr
+                    .saturating_add(section.raw_size.min(mem_size))
+                    .min(bytes.len());
+                let data = bytes.get(section.raw_ptr..end).unwrap_or_default();
+                image.copy_in(section.virtual_address, data)?;
+                data.len()
+            };
+
+            // The reservation is anonymous memory, so everything past the raw
+            // data (BSS and section tail padding) is already zero-filled.
+            image.sections.push(MappedSection {
+                name: section.name.clone(),
+                rva: section.virtual_address,
+                size: mem_size,
+                copied,
+                zero_filled: mem_size - copied,
+                protection: Protection::from_characteristics(section.characteristics),
+            });
+        }
+
+        Ok(image)
+    }
+
+    /// Rebases the image from its preferred `ImageBase` to where it was
+    /// actually mapped. Must run before [`LoadedImage::protect`].
+    pub fn relocate(&mut self, relocations: &[Relocation]) -> Result<RelocationReport, String> {
+        let delta = self.base().wrapping_sub(self.preferred_base);
+        let mut report = RelocationReport {
+            delta: delta as i64,
+            applied: 0,
+            padding: 0,
+            per_section: self.sections.iter().map(|s| (s.name.clone(), 0)).collect(),
+        };
+
+        for reloc in relocations {
+            match reloc.kind {
+                IMAGE_REL_BASED_ABSOLUTE => {
+                    report.padding += 1;
+                    continue;
+                }
+                IMAGE_REL_BASED_HIGH => {
+                    let value = self.read::<2>(reloc.rva)?;
+                    let value = u16::from_le_bytes(value).wrapping_add((delta >> 16) as u16);
+                    self.write(reloc.rva, value.to_le_bytes())?;
+                }
+                IMAGE_REL_BASED_LOW => {
+                    let value = self.read::<2>(reloc.rva)?;
+                    let value = u16::from_le_bytes(value).wrapping_add(delta as u16);
+                    self.write(reloc.rva, value.to_le_bytes())?;
+                }
+                IMAGE_REL_BASED_HIGHLOW => {
+                    let value = self.read::<4>(reloc.rva)?;
+                    let value = u32::from_le_bytes(value).wrapping_add(delta as u32);
+                    self.write(reloc.rva, value.to_le_bytes())?;
+                }
+                IMAGE_REL_BASED_HIGHADJ => {
+                    let high = u16::from_le_bytes(self.read::<2>(reloc.rva)?);
+                    let full = ((high as i32) << 16)
+                        .wrapping_add(reloc.extra as i16 as i32)
+                        .wrapping_add(delta as i32)
+                        .wrapping_add(0x8000);
+                    self.write(reloc.rva, ((full >> 16) as u16).to_le_bytes())?;
+                }
+                IMAGE_REL_BASED_DIR64 => {
+                    let value = self.read::<8>(reloc.rva)?;
+                    let value = u64::from_le_bytes(value).wrapping_add(delta);
+                    self.write(reloc.rva, value.to_le_bytes())?;
+                }
+                other => {
+                    return Err(format!(
+                        "unsupported base relocation type {other} at rva {:#x}",
+                        reloc.rva
+                    ));
+                }
+            }
+
+            report.applied += 1;
+            if let Some(idx) = self
+                .sections
+                .iter()
+                .position(|s| reloc.rva >= s.rva && reloc.rva < s.rva + s.size)
+            {
+                report.per_section[idx].1 += 1;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Points every IAT slot at a waygate thunk, or directly at the export
+    /// when `resolve` finds the symbol in an app-local DLL. Symbols waygate
+    /// does not implement get a trap that reports `DLL!symbol` when called,
+    /// so the program only fails if it actually reaches a missing import.
+    ///
+    /// Ordinal imports are bound under the name waygate's ordinal table
+    /// gives them, or as `#N` when the ordinal is unknown.
+    ///
+    /// Delay-loaded slots get a lazy thunk that resolves through waygate on
+    /// first call, as long as their IAT stays writable once protected;
+    /// otherwise they are bound eagerly like regular imports.
+    pub fn bind_imports(
+        &mut self,
+        modules: &[ImportModule],
+        resolve: impl Fn(&str, &ImportEntry) -> Option<Binding>,
+        trace: bool,
+    ) -> Result<BindReport, String> {
+        // IAT cells are as wide as the host's pointers.
+        if self.is_pe64 == cfg!(target_arch = "x86") {
+            return Err(format!(
+                "import binding requires a {} image on this host",
+                if self.is_pe64 { "PE32" } else { "PE32+" }
+            ));
+        }
+
+        let mut slots = Vec::new();
+        let mut iat = Vec::new();
+        let mut ordinals = Vec::new();
+        let mut local = 0;
+        for module in modules {
+            for entry in &module.entries {
+                let (module_name, symbol, ordinal) = match resolve(&module.dll, entry) {
+                    Some(Binding::Address(address)) => {
+                        self.write_pointer(entry.iat_rva, address)?;
+                        local += 1;
+                        continue;
+                    }
+                    Some(Binding::Thunk { module, symbol }) => (module, symbol, None),
+                    None => {
+                        let (symbol, ordinal) = match (&entry.name, entry.ordinal) {
+                            (Some(name), _) => (name.clone(), None),
+                            (None, Some(ordinal)) => {
+                                match waygate::ordinal_name(&module.dll, ordinal) {
+                                    Some(name) => (name.to_string(), Some(ordinal)),
+                                    None => (format!("#{ordinal}"), None),
+                                }
+                            }
+                            (None, None) => continue,
+                        };
+                        (module.dll.clone(), symbol, ordinal)
+                    }
+                };
+                let delay =
+                    (module.delay_loaded && self.is_writable(entry.iat_rva)).then(|| DelayLoad {
+                        iat_cell: self.base() + entry.iat_rva as u64,
+                        module_handle_cell: module
+                            .module_handle_rva
+                            .filter(|rva| self.is_writable(*rva))
+                            .map_or(0, |rva| self.base() + rva as u64),
+                    });
+                slots.push(ThunkSlot {
+                    module: module_name,
+                    symbol,
+                    delay,
+                });
+                iat.push(entry.iat_rva);
+                ordinals.push(ordinal);
+            }
+        }
+
+        let lazy = slots.iter().filter(|slot| slot.delay.is_some()).count();
+        let missing = slots
+            .iter()
+            .zip(&ordinals)
+            .filter(|(slot, _)| waygate::resolve(&slot.module, &slot.symbol).is_none())
+            .map(|(slot, ordinal)| match ordinal {
+                Some(ordinal) => format!("{}!#{ordinal} ({})", slot.module, slot.symbol),
+                None => format!("{}!{}", slot.module, slot.symbol),
+            })
+            .collect();
+        let table = ThunkTable::build(slots, trace)?;
+        for (idx, rva) in iat.iter().enumerate() {
+            let Some(address) = table.entry(idx) else {
+                continue;
+            };
+            self.write_pointer(*rva, address)?;
+        }
+
+        let report = BindReport {
+            bound: table.len() + local,
+            local,
+            lazy,
+            missing,
+        };
+        self.thunks = Some(table);
+        Ok(report)
+    }
+
+    /// Applies the characteristics-derived protections to every page.
+    /// Headers become read-only and pages not covered by a section become
+    /// inaccessible. Pages shared by several sections get the union of
+    /// their protections.
+    pub fn protect(&self) -> Result<(), String> {
+        let pages = self.size / PAGE_SIZE;
+        let mut page_prot = vec![None::<Protection>; pages];
+        let header_pages = self.header_size.max(1).div_ceil(PAGE_SIZE);
+        for slot in page_prot.iter_mut().take(header_pages) {
+            *slot = Some(Protection {
+                read: true,
+                ..Protection::default()
+            });
+        }
+
+        for section in &self.sections {
+            if section.size == 0 {
+                continue;
+            }
+            let first = section.rva / PAGE_SIZE;
+            let last = (section.rva + section.size).div_ceil(PAGE_SIZE).min(pages);
+            for slot in &mut page_prot[first..last] {
+                *slot = Some(match slot {
+                    Some(existing) => existing.union(section.protection),
+                    None => section.protection,
+                });
+            }
+        }
+
+        let mut start = 0usize;
+        while start < pages {
+            let prot = page_prot[start].unwrap_or_default();
+            let mut end = start + 1;
+            while end < pages && page_prot[end].unwrap_or_default() == prot {
+                end += 1;
+            }
+            // SAFETY: the range lies inside the mapping owned by `self`.
+            let rc = unsafe {
+                mprotect(
+                    self.base.add(start * PAGE_SIZE) as *mut c_void,
+                    (end - start) * PAGE_SIZE,
+                    prot.to_prot(),
+                )
+            };
+            if rc != 0 {
+                return Err(format!(
+                    "mprotect({:#x}, {prot}) failed: {}",
+                    self.base as usize + start * PAGE_SIZE,
+                    io::Error::last_os_error()
+                ));
+            }
+            start = end;
+        }
+
+        Ok(())
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base as u64
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn preferred_base(&self) -> u64 {
+        self.preferred_base
+    }
+
+    pub fn is_at_preferred_base(&self) -> bool {
+        self.base() == self.preferred_base
+    }
+
+    pub fn entry_address(&self) -> Option<u64> {
+        (self.entry_point != 0).then(|| self.base() + self.entry_point as u64)
+    }
+
+    /// Where a VA at the preferred `ImageBase` lies in this mapping, if
+    /// `len` bytes from there are inside the image.
+    pub fn rebase(&self, va: u64, len: usize) -> Option<u64> {
+        let rva = va.checked_sub(self.preferred_base)?;
+        (rva.checked_add(len as u64)? <= self.size as u64).then(|| self.base() + rva)
+    }
+
+    pub fn sections(&self) -> &[MappedSection] {
+        &self.sections
+    }
+
+    fn is_writable(&self, rva: usize) -> bool {
+        self.sections
+            .iter()
+            .any(|s| rva >= s.rva && rva < s.rva + s.size && s.protection.write)
+    }
+
+    fn read<const N: usize>(&self, rva: usize) -> Result<[u8; N], String> {
+        if rva.saturating_add(N) > self.size {
+            return Err(format!(
+                "read of {N} bytes at rva {rva:#x} exceeds image size"
+            ));
+        }
+        let mut out = [0u8; N];
+        // SAFETY: bounds checked above.
+        unsafe { ptr::copy_nonoverlapping(self.base.add(rva), out.as_mut_ptr(), N) };
+        Ok(out)
+    }
+
+    fn write<const N: usize>(&mut self, rva: usize, data: [u8; N]) -> Result<(), String> {
+        self.copy_in(rva, &data)
+    }
+
+    /// Writes an address in the image's pointer width.
+    fn write_pointer(&mut self, rva: usize, address: u64) -> Result<(), String> {
+        if self.is_pe64 {
+            self.write(rva, address.to_le_bytes())
+        } else {
+            self.write(rva, (address as u32).to_le_bytes())
+        }
+    }
+
+    fn copy_in(&mut self, rva: usize, data: &[u8]) -> Result<(), String> {
+        if rva.saturating_add(data.len()) > self.size {
+            return Err(format!(
+                "write of {:#x} bytes at rva {rva:#x} exceeds image size",
+                data.len()
+            ));
+        }
+        // SAFETY: bounds checked above; the mapping is still read/write.
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), self.base.add(rva), data.len()) };
+        Ok(())
+    }
+}
+
+impl Drop for LoadedImage {
+    fn drop(&mut self) {
+        // SAFETY: `base`/`size` describe the mapping created in `reserve`.
+        unsafe {
+            munmap(self.base as *mut c_void, self.size);
+        }
+    }
+}
+
+fn reserve(preferred: u64, size: usize) -> Result<*mut u8, String> {
+    let prot = PROT_READ | PROT_WRITE;
+    let flags = MAP_PRIVATE | MAP_ANONYMOUS;
+
+    // SAFETY: anonymous mappings never alias existing memory; the
+    // NOREPLACE flag makes the kernel refuse instead of clobbering.
+    let at_preferred = unsafe {
+        mmap(
+            preferred as usize as *mut c_void,
+            size,
+            prot,
+            flags | MAP_FIXED_NOREPLACE,
+            -1,
+            0,
+        )
+    };
+    if at_preferred != MAP_FAILED {
+        return Ok(at_preferred as *mut u8);
+    }
+
+    // SAFETY: as above, letting the kernel choose the address.
+    let anywhere = unsafe { mmap(ptr::null_mut(), size, prot, flags, -1, 0) };
+    if anywhere == MAP_FAILED {
+        return Err(format!(
+            "failed to reserve {size:#x} bytes for image: {}",
+            io::Error::last_os_error()
+        ));
+    }
+    Ok(anywhere as *mut u8)
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    value.div_ceil(align) * align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pe::IMAGE_DIRECTORY_ENTRY_BASERELOC;
+    use crate::reloc::parse_base_relocations;
+    use crate::testpe::{TestPe, IMAGE_SCN_DATA, IMAGE_SCN_RDATA};
+
+    const PREFERRED_BASE: u64 = 0x7_1234_0000;
+    const TARGET_RVA: u64 = 0x2010;
+
+    /// `.data` holds the address of `TARGET_RVA` as a DIR64, a HIGHLOW, and
+    /// a LOW/HIGHADJ pair, with one ABSOLUTE entry padding the block.
+    fn image() -> Vec<u8> {
+        let target = PREFERRED_BASE + TARGET_RVA;
+        let mut data = target.to_le_bytes().to_vec();
+        data.extend_from_slice(&(target as u32).to_le_bytes());
+        data.extend_from_slice(&(target as u16).to_le_bytes());
+        data.extend_from_slice(&((target >> 16) as u16).to_le_bytes());
+        let mut table = 0x2000u32.to_le_bytes().to_vec();
+        table.extend_from_slice(&20u32.to_le_bytes());
+        for entry in [0xA000u16, 0x3008, 0x200C, 0x400E, TARGET_RVA as u16, 0x0000] {
+            table.extend_from_slice(&entry.to_le_bytes());
+        }
+        TestPe::new(true)
+            .image_base(PREFERRED_BASE)
+            .section(".data", 0x2000, &data, IMAGE_SCN_DATA)
+            .section(".reloc", 0x3000, &table, IMAGE_SCN_RDATA)
+            .directory(IMAGE_DIRECTORY_ENTRY_BASERELOC, 0x3000, table.len() as u32)
+            .build()
+    }
+
+    #[test]
+    fn fixups_follow_the_image_to_its_new_base() {
+        let bytes = image();
+        let pe = PeContext::parse(&bytes).expect("image parses");
+        let relocations = parse_base_relocations(&bytes, &pe).expect("relocations parse");
+        // The first mapping holds the preferred base, so the second moves.
+        let _holder = LoadedImage::map(&bytes, &pe).expect("first mapping");
+        let mut image = LoadedImage::map(&bytes, &pe).expect("second mapping");
+        assert!(!image.is_at_preferred_base());
+
+        let report = image.relocate(&relocations).expect("fixups apply");
+        assert_eq!(
+            report.delta,
+            image.base().wrapping_sub(PREFERRED_BASE) as i64
+        );
+        assert_eq!((report.applied, report.padding), (4, 1));
+        assert_eq!(report.per_section[0], (".data".to_string(), 4));
+
+        let moved = image.base() + TARGET_RVA;
+        assert_eq!(u64::from_le_bytes(image.read(0x2000).unwrap()), moved);
+        assert_eq!(
+            u32::from_le_bytes(image.read(0x2008).unwrap()),
+            moved as u32
+        );
+        assert_eq!(
+            u16::from_le_bytes(image.read(0x200C).unwrap()),
+            moved as u16
+        );
+        // HIGHADJ rounds so the sign-extended low half adds back up.
+        let high = ((moved as u32).wrapping_add(0x8000) >> 16) as u16;
+        assert_eq!(u16::from_le_bytes(image.read(0x200E).unwrap()), high);
+    }
+}
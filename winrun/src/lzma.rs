@@ -0,0 +1,270 @@
+/// Raw LZMA decoder (no `.lzma`/`.xz` container), for packers that store
+/// the properties themselves and know the decompressed size up front.
+pub struct LzmaProperties {
+    /// Literal context bits.
+    pub lc: u32,
+    /// Literal position bits.
+    pub lp: u32,
+    /// Position bits.
+    pub pb: u32,
+}
+
+const NUM_STATES: usize = 12;
+const POS_STATES_MAX: usize = 1 << 4;
+const LEN_TO_POS_STATES: usize = 4;
+const END_POS_MODEL_INDEX: u32 = 14;
+const NUM_FULL_DISTANCES: usize = 1 << (END_POS_MODEL_INDEX >> 1);
+const ALIGN_BITS: u32 = 4;
+const MATCH_MIN_LEN: usize = 2;
+const PROB_INIT: u16 = 1 << 10;
+
+struct RangeDecoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+    range: u32,
+    code: u32,
+}
+
+impl<'a> RangeDecoder<'a> {
+    fn new(input: &'a [u8]) -> Option<Self> {
+        // The first byte is always zero; the next four prime `code`.
+        if *input.first()? != 0 {
+            return None;
+        }
+        let mut decoder = Self {
+            input,
+            pos: 1,
+            range: u32::MAX,
+            code: 0,
+        };
+        for _ in 0..4 {
+            decoder.code = (decoder.code << 8) | decoder.next_byte()? as u32;
+        }
+        Some(decoder)
+    }
+
+    fn next_byte(&mut self) -> Option<u8> {
+        let byte = *self.input.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn normalize(&mut self) -> Option<()> {
+        if self.range < 1 << 24 {
+            self.range <<= 8;
+            self.code = (self.code << 8) | self.next_byte()? as u32;
+        }
+        Some(())
+    }
+
+    fn bit(&mut self, prob: &mut u16) -> Option<u32> {
+        let bound = (self.range >> 11) * *prob as u32;
+        let bit = if self.code < bound {
+            *prob += ((1 << 11) - *prob) >> 5;
+            self.range = bound;
+            0
+        } else {
+            *prob -= *prob >> 5;
+            self.code -= bound;
+            self.range -= bound;
+            1
+        };
+        self.normalize()?;
+        Some(bit)
+    }
+
+    fn direct_bits(&mut self, count: u32) -> Option<u32> {
+        let mut value = 0u32;
+        for _ in 0..count {
+            self.range >>= 1;
+            let bit = u32::from(self.code >= self.range);
+            if bit == 1 {
+                self.code -= self.range;
+            }
+            value = (value << 1) | bit;
+            self.normalize()?;
+        }
+        Some(value)
+    }
+
+    fn tree(&mut self, probs: &mut [u16], bits: u32) -> Option<u32> {
+        let mut m = 1usize;
+        for _ in 0..bits {
+            m = (m << 1) | self.bit(&mut probs[m])? as usize;
+        }
+        Some(m as u32 - (1 << bits))
+    }
+
+    fn reverse_tree(&mut self, probs: &mut [u16], bits: u32) -> Option<u32> {
+        let mut m = 1usize;
+        let mut value = 0u32;
+        for idx in 0..bits {
+            let bit = self.bit(&mut probs[m])?;
+            m = (m << 1) | bit as usize;
+            value |= bit << idx;
+        }
+        Some(value)
+    }
+}
+
+struct LenDecoder {
+    choice: u16,
+    choice2: u16,
+    low: [[u16; 1 << 3]; POS_STATES_MAX],
+    mid: [[u16; 1 << 3]; POS_STATES_MAX],
+    high: [u16; 1 << 8],
+}
+
+impl LenDecoder {
+    fn new() -> Self {
+        Self {
+            choice: PROB_INIT,
+            choice2: PROB_INIT,
+            low: [[PROB_INIT; 1 << 3]; POS_STATES_MAX],
+            mid: [[PROB_INIT; 1 << 3]; POS_STATES_MAX],
+            high: [PROB_INIT; 1 << 8],
+        }
+    }
+
+    fn decode(&mut self, rc: &mut RangeDecoder, pos_state: usize) -> Option<usize> {
+        let len = if rc.bit(&mut self.choice)? == 0 {
+            rc.tree(&mut self.low[pos_state], 3)?
+        } else if rc.bit(&mut self.choice2)? == 0 {
+            8 + rc.tree(&mut self.mid[pos_state], 3)?
+        } else {
+            16 + rc.tree(&mut self.high, 8)?
+        };
+        Some(len as usize)
+    }
+}
+
+/// Decodes `stream` until `out_len` bytes are produced or an end marker is
+/// seen. Returns `None` for corrupt input.
+pub fn decompress(stream: &[u8], props: &LzmaProperties, out_len: usize) -> Option<Vec<u8>> {
+    if props.lc > 8 || props.lp > 4 || props.pb > 4 {
+        return None;
+    }
+    let mut rc = RangeDecoder::new(stream)?;
+    let mut out = Vec::with_capacity(out_len);
+
+    let mut literals = vec![PROB_INIT; 0x300 << (props.lc + props.lp)];
+    let mut is_match = [PROB_INIT; NUM_STATES << 4];
+    let mut is_rep = [PROB_INIT; NUM_STATES];
+    let mut is_rep_g0 = [PROB_INIT; NUM_STATES];
+    let mut is_rep_g1 = [PROB_INIT; NUM_STATES];
+    let mut is_rep_g2 = [PROB_INIT; NUM_STATES];
+    let mut is_rep0_long = [PROB_INIT; NUM_STATES << 4];
+    let mut pos_slots = [[PROB_INIT; 1 << 6]; LEN_TO_POS_STATES];
+    let mut pos_decoders = [PROB_INIT; 1 + NUM_FULL_DISTANCES - END_POS_MODEL_INDEX as usize];
+    let mut align = [PROB_INIT; 1 << ALIGN_BITS];
+    let mut lengths = LenDecoder::new();
+    let mut rep_lengths = LenDecoder::new();
+
+    let pos_mask = (1usize << props.pb) - 1;
+    let literal_pos_mask = (1usize << props.lp) - 1;
+    let mut state = 0usize;
+    let mut reps = [0usize; 4];
+
+    while out.len() < out_len {
+        let pos_state = out.len() & pos_mask;
+        if rc.bit(&mut is_match[(state << 4) + pos_state])? == 0 {
+            let prev = out.last().copied().unwrap_or(0) as usize;
+            let context = ((out.len() & literal_pos_mask) << props.lc) + (prev >> (8 - props.lc));
+            let probs = &mut literals[0x300 * context..0x300 * (context + 1)];
+            let mut symbol = 1usize;
+            if state >= 7 {
+                let mut match_byte = *out.get(out.len().checked_sub(reps[0] + 1)?)? as usize;
+                while symbol < 0x100 {
+                    let match_bit = (match_byte >> 7) & 1;
+                    match_byte <<= 1;
+                    let bit = rc.bit(&mut probs[((1 + match_bit) << 8) + symbol])? as usize;
+                    symbol = (symbol << 1) | bit;
+                    if match_bit != bit {
+                        break;
+                    }
+                }
+            }
+            while symbol < 0x100 {
+                symbol = (symbol << 1) | rc.bit(&mut probs[symbol])? as usize;
+            }
+            out.push((symbol - 0x100) as u8);
+            state = match state {
+                0..=3 => 0,
+                4..=9 => state - 3,
+                _ => state - 6,
+            };
+            continue;
+        }
+
+        let len;
+        if rc.bit(&mut is_rep[state])? == 0 {
+            len = lengths.decode(&mut rc, pos_state)?;
+            state = if state < 7 { 7 } else { 10 };
+            let distance = decode_distance(
+                &mut rc,
+                &mut pos_slots[len.min(LEN_TO_POS_STATES - 1)],
+                &mut pos_decoders,
+                &mut align,
+            )?;
+            if distance == u32::MAX {
+                break;
+            }
+            reps = [distance as usize, reps[0], reps[1], reps[2]];
+        } else {
+            if rc.bit(&mut is_rep_g0[state])? == 0 {
+                if rc.bit(&mut is_rep0_long[(state << 4) + pos_state])? == 0 {
+                    state = if state < 7 { 9 } else { 11 };
+                    let byte = *out.get(out.len().checked_sub(reps[0] + 1)?)?;
+                    out.push(byte);
+                    continue;
+                }
+            } else {
+                let distance = if rc.bit(&mut is_rep_g1[state])? == 0 {
+                    reps[1]
+                } else {
+                    let distance = if rc.bit(&mut is_rep_g2[state])? == 0 {
+                        reps[2]
+                    } else {
+                        let distance = reps[3];
+                        reps[3] = reps[2];
+                        distance
+                    };
+                    reps[2] = reps[1];
+                    distance
+                };
+                reps[1] = reps[0];
+                reps[0] = distance;
+            }
+            len = rep_lengths.decode(&mut rc, pos_state)?;
+            state = if state < 7 { 8 } else { 11 };
+        }
+
+        let start = out.len().checked_sub(reps[0] + 1)?;
+        for idx in 0..(len + MATCH_MIN_LEN).min(out_len - out.len()) {
+            out.push(out[start + idx]);
+        }
+    }
+    Some(out)
+}
+
+fn decode_distance(
+    rc: &mut RangeDecoder,
+    pos_slot: &mut [u16],
+    pos_decoders: &mut [u16],
+    align: &mut [u16],
+) -> Option<u32> {
+    let slot = rc.tree(pos_slot, 6)?;
+    if slot < 4 {
+        return Some(slot);
+    }
+    let direct_bits = (slot >> 1) - 1;
+    let mut distance = (2 | (slot & 1)) << direct_bits;
+    if slot < END_POS_MODEL_INDEX {
+        let base = (distance - slot) as usize;
+        distance += rc.reverse_tree(&mut pos_decoders[base..], direct_bits)?;
+    } else {
+        distance += rc.direct_bits(direct_bits - ALIGN_BITS)? << ALIGN_BITS;
+        distance = distance.wrapping_add(rc.reverse_tree(align, ALIGN_BITS)?);
+    }
+    Some(distance)
+}
@@ -1,4 +1,5 @@
-use std::collections::{BTreeSet, HashSet};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::env;
 use std::fs;
 use std::io;
@@ -9,12 +10,72 @@ use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+mod authenticode;
+mod callargs;
+mod callsites;
+mod clr;
+mod debugdir;
+mod deps;
+mod digest;
+mod exports;
+mod fingerprint;
+mod icon;
+mod imports;
+mod inspect;
+mod loader;
+mod lzma;
+mod manifest;
+mod modules;
+mod ordlookup;
+mod overlay;
+mod packer;
+mod pdb;
+mod pe;
+mod reloc;
+mod resources;
+mod strings;
+#[cfg(test)]
+mod testpe;
+mod ucl;
+mod upx;
+mod x86;
+
+use authenticode::{image_hash, parse_signature};
+use callargs::recover_args;
+use callsites::{branch_targets, find_call_sites, sweep, CallSite};
+use clr::parse_clr;
+use debugdir::{codeview, parse_debug_directory};
+use deps::{missing_modules, walk_dependencies};
+use exports::{parse_export_directory, ExportDirectory, ExportTarget};
+use imports::{parse_delay_import_modules, parse_import_modules};
+use inspect::{
+    inspect_pe, print_build_report, print_clr_report, print_dependency_report,
+    print_fingerprint_report, print_manifest_report, print_packer_report, print_signature_report,
+    print_tls_report, InspectOptions,
+};
+use loader::{BindReport, LoadedImage, RelocationReport};
+use manifest::load_manifest;
+use modules::{DllLocation, ModuleSet};
+use pdb::{load_pdb, ImageSymbols};
+use pe::{
+    Machine, PeContext, Subsystem, TlsDirectory, IMAGE_DIRECTORY_ENTRY_EXCEPTION, IMAGE_FILE_DLL,
+    IMAGE_FILE_RELOCS_STRIPPED,
+};
+use reloc::parse_base_relocations;
+use strings::{read_only_strings, DataString};
+use waygate::ArgKind;
+
+/// Symbols the analysis recognizes. Bare names such as `LoadLibrary` are
+/// what C sources spell through the header macros; import tables only ever
+/// carry the `A`/`W` entry points.
 const KNOWN_WINAPI: &[&str] = &[
     "CreateFileA",
+    "CreateFileW",
     "ReadFile",
     "WriteFile",
     "CloseHandle",
     "MessageBoxA",
+    "MessageBoxW",
     "VirtualAlloc",
     "VirtualFree",
     "GetLastError",
@@ -24,8 +85,16 @@ const KNOWN_WINAPI: &[&str] = &[
     "Sleep",
     "GetTickCount",
     "GetModuleHandle",
+    "GetModuleHandleA",
+    "GetModuleHandleW",
+    "GetModuleHandleExA",
+    "GetModuleHandleExW",
     "GetProcAddress",
     "LoadLibrary",
+    "LoadLibraryA",
+    "LoadLibraryW",
+    "LoadLibraryExA",
+    "LoadLibraryExW",
     "FreeLibrary",
     "SendInput",
     "mouse_event",
@@ -35,39 +104,95 @@ const KNOWN_WINAPI: &[&str] = &[
     "GetAsyncKeyState",
     "GetKeyState",
     "MapVirtualKey",
+    "MapVirtualKeyA",
+    "MapVirtualKeyW",
+    "MapVirtualKeyExA",
+    "MapVirtualKeyExW",
     "ShowCursor",
     "ClipCursor",
     "CreateThread",
     "WaitForSingleObject",
     "CreateEvent",
+    "CreateEventA",
+    "CreateEventW",
+    "CreateEventExA",
+    "CreateEventExW",
     "SetEvent",
     "ResetEvent",
     "QueryPerformanceCounter",
     "QueryPerformanceFrequency",
     "GetSystemTime",
     "GetLocalTime",
+    "GetVersion",
+    "GetVersionExA",
+    "GetVersionExW",
+    "RtlGetVersion",
+    "SetProcessDPIAware",
+    "IsProcessDPIAware",
+    "GetProcessDpiAwareness",
+    "InitCommonControls",
+    "InitCommonControlsEx",
+    "DllGetVersion",
 ];
 
 const TRACE_MAX_STOPS: usize = 128;
 
+/// Exit code for images built for a CPU waygate cannot execute.
+const EXIT_UNSUPPORTED_MACHINE: i32 = 3;
+/// Exit code for drivers, EFI and other non-Win32 subsystems.
+const EXIT_UNSUPPORTED_SUBSYSTEM: i32 = 4;
+
+/// First arguments that select a mode instead of naming the binary.
+const SUBCOMMANDS: &[&str] = &["inspect", "deps"];
+
 fn main() {
     match run() {
         Ok(code) => std::process::exit(code),
-        Err(err) => {
-            eprintln!("winrun error: {err}");
-            std::process::exit(1);
+        Err(failure) => {
+            eprintln!("winrun error: {}", failure.message);
+            std::process::exit(failure.code);
         }
     }
 }
 
+/// Error out of `run`: what to print and the process exit code. Plain
+/// string errors exit with 1.
+struct Failure {
+    code: i32,
+    message: String,
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Self { code: 1, message }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 enum Mode {
     Run,
     CompileOnly,
+    Inspect,
+    Dependencies,
 }
 
-fn run() -> Result<i32, String> {
-    let (mode, debug, target) = parse_args()?;
+struct Options {
+    mode: Mode,
+    debug: bool,
+    target: PathBuf,
+    inspect: InspectOptions,
+    /// Directory searched for PDBs besides the binary's own (`--symbols`).
+    symbol_dir: Option<PathBuf>,
+}
+
+fn run() -> Result<i32, Failure> {
+    let Options {
+        mode,
+        debug,
+        target,
+        inspect,
+        symbol_dir,
+    } = parse_args()?;
 
     if debug {
         println!("=== winrun debug mode ===");
@@ -78,7 +203,7 @@ fn run() -> Result<i32, String> {
 
     let metadata = fs::metadata(&target).map_err(|e| format!("failed to stat target: {e}"))?;
     if !metadata.is_file() {
-        return Err(format!("target is not a file: {}", target.display()));
+        return Err(format!("target is not a file: {}", target.display()).into());
     }
 
     let bytes = fs::read(&target).map_err(|e| format!("failed to read target: {e}"))?;
@@ -90,11 +215,92 @@ fn run() -> Result<i32, String> {
         debug_log("inspect", "format detection finished");
     }
 
+    if let Some(pe) = PeContext::parse(&bytes) {
+        if debug {
+            println!("machine: {}", pe.machine);
+            println!("subsystem: {}", pe.subsystem);
+            print_build_report(&bytes, &pe);
+            print_signature_report(&bytes, &pe);
+            print_packer_report(&bytes, &pe);
+            print_tls_report(&pe);
+            print_clr_report(&bytes, &pe);
+        }
+        if mode == Mode::Dependencies {
+            let tree = walk_dependencies(&bytes, &pe, app_dir(&target));
+            print_dependency_report(&target_name(&target), &tree);
+            return Ok(0);
+        }
+        if mode != Mode::Inspect {
+            check_supported(&pe)?;
+            warn_if_tampered(&target, &bytes, &pe);
+        }
+    }
+
+    if mode == Mode::Dependencies {
+        return Err("deps: target is not a PE image".to_string().into());
+    }
+    if mode == Mode::Inspect {
+        inspect_pe(&target, &bytes, &inspect)?;
+        return Ok(0);
+    }
+
     if can_run_natively(&bytes) {
-        return handle_native(mode, debug, &target, &metadata);
+        return Ok(handle_native(mode, debug, &target, &metadata)?);
+    }
+
+    Ok(handle_non_native(mode, debug, &target, &bytes, symbol_dir)?)
+}
+
+/// Refuses images waygate cannot host before any import is analyzed:
+/// only i386 and AMD64 code, and only Win32 GUI/console programs.
+fn check_supported(pe: &PeContext) -> Result<(), Failure> {
+    match pe.machine {
+        Machine::I386 | Machine::Amd64 => {}
+        Machine::Arm64ec => {
+            return Err(Failure {
+                code: EXIT_UNSUPPORTED_MACHINE,
+                message: "unsupported architecture: ARM64EC image (AMD64 header with ARM64 hybrid metadata); winrun runs i386 and AMD64 images only".to_string(),
+            })
+        }
+        machine => {
+            return Err(Failure {
+                code: EXIT_UNSUPPORTED_MACHINE,
+                message: format!(
+                    "unsupported architecture: {machine} image; winrun runs i386 and AMD64 images only"
+                ),
+            })
+        }
+    }
+    match pe.subsystem {
+        Subsystem::WindowsGui | Subsystem::WindowsConsole => Ok(()),
+        subsystem => Err(Failure {
+            code: EXIT_UNSUPPORTED_SUBSYSTEM,
+            message: format!(
+                "unsupported subsystem: {subsystem}; winrun runs Windows GUI and console programs only"
+            ),
+        }),
     }
+}
 
-    handle_non_native(mode, debug, &target, &bytes)
+/// Warns when an Authenticode-signed image no longer hashes to the digest
+/// its publisher signed, or when the signature does not cover that digest.
+fn warn_if_tampered(target: &Path, bytes: &[u8], pe: &PeContext) {
+    let Ok(Some(signature)) = parse_signature(bytes, pe) else {
+        return;
+    };
+    if signature.is_invalid() {
+        eprintln!(
+            "winrun warning: {} has an invalid Authenticode signature (signed attributes do not cover the image digest)",
+            target_name(target)
+        );
+    } else if image_hash(bytes, pe, &signature.digest_algorithm)
+        .is_some_and(|hash| hash != signature.signed_digest)
+    {
+        eprintln!(
+            "winrun warning: {} was modified after it was signed (Authenticode digest mismatch)",
+            target_name(target)
+        );
+    }
 }
 
 fn handle_native(
@@ -135,7 +341,13 @@ fn handle_native(
     exec_native(target).map_err(|e| format!("native execution failed: {e}"))
 }
 
-fn handle_non_native(mode: Mode, debug: bool, target: &Path, bytes: &[u8]) -> Result<i32, String> {
+fn handle_non_native(
+    mode: Mode,
+    debug: bool,
+    target: &Path,
+    bytes: &[u8],
+    symbol_dir: Option<PathBuf>,
+) -> Result<i32, String> {
     if debug {
         println!("native: no");
         println!(
@@ -145,9 +357,47 @@ fn handle_non_native(mode: Mode, debug: bool, target: &Path, bytes: &[u8]) -> Re
         debug_log("non-native", "analyzing candidate Win32 symbols");
     }
 
-    let analysis = analyze_non_native(bytes);
+    // Packed images import little beyond what their stub needs, so UPX
+    // images are analyzed decompressed; the packed file is what runs.
+    let unpacked = PeContext::parse(bytes).and_then(|pe| upx::unpack(bytes, &pe).ok());
+    let image = unpacked.as_ref().map_or(bytes, |u| &u.image);
+    let mut analysis = analyze_non_native(image);
+    // Only real PE images have DLLs to look for; fixtures list `.so` names.
+    let dependencies =
+        PeContext::parse(image).map(|pe| walk_dependencies(image, &pe, app_dir(target)));
+    if let Some(tree) = &dependencies {
+        analysis.non_windows_libs.extend(missing_modules(tree));
+    }
     if debug {
+        if unpacked.is_some() {
+            println!("analysis: imports read from the unpacked UPX image");
+        }
         print_non_native_report(&analysis);
+        if let Some(tree) = &dependencies {
+            print_dependency_report(&target_name(target), tree);
+        }
+        if let Some(pe) = PeContext::parse(bytes) {
+            print_fingerprint_report(bytes, &pe);
+        }
+    }
+
+    let manifest = PeContext::parse(bytes).and_then(|pe| load_manifest(bytes, &pe));
+    match &manifest {
+        Some(manifest) => {
+            if manifest.requires_admin() {
+                eprintln!(
+                    "winrun warning: {} requests {} in its manifest; waygate runs it unelevated, so privileged operations will fail",
+                    target_name(target),
+                    manifest.execution_level.as_deref().unwrap_or_default()
+                );
+            }
+            if debug {
+                print_manifest_report(manifest);
+            }
+            waygate::set_app_compat(manifest.app_compat());
+        }
+        None if debug => println!("manifest: none (version APIs report 6.2.9200, DPI unaware)"),
+        None => {}
     }
 
     if analysis.winapi_calls.is_empty() {
@@ -155,13 +405,13 @@ fn handle_non_native(mode: Mode, debug: bool, target: &Path, bytes: &[u8]) -> Re
     }
 
     let plan_path = plan_output_path(target);
-    write_plan_file(&plan_path, &analysis.winapi_calls)
+    let rows = write_plan_file(&plan_path, &analysis)
         .map_err(|e| format!("failed to write plan {}: {e}", plan_path.display()))?;
     if debug {
         debug_log(
             "plan",
             &format!(
-                "wrote {} API calls to {}",
+                "wrote {rows} row(s) for {} API calls to {}",
                 analysis.winapi_calls.len(),
                 plan_path.display()
             ),
@@ -173,22 +423,62 @@ fn handle_non_native(mode: Mode, debug: bool, target: &Path, bytes: &[u8]) -> Re
         return Ok(0);
     }
 
+    // The entry point of a managed image is mscoree!_CorExeMain, which
+    // hands IL to a runtime waygate does not provide.
+    let managed = PeContext::parse(bytes).and_then(|pe| parse_clr(bytes, &pe));
+    if debug && managed.is_some() {
+        println!("image: managed code cannot run through waygate, replaying plan instead");
+    }
+    if let Some(pe) = PeContext::parse(bytes).filter(|_| managed.is_none()) {
+        let mut modules = ModuleSet::new(app_dir(target).to_path_buf(), symbol_dir);
+        if cfg!(target_arch = "x86_64") {
+            waygate::install_thread_tls()?;
+        }
+        let name = target_name(target);
+        let image = load_pe_image(bytes, &pe, &name, &mut modules, debug)?;
+        match executable_entry(&pe, &image) {
+            Some(entry) => {
+                let callbacks = tls_callback_array(&pe, &image);
+                // SAFETY: as for the entry point below; the callbacks are
+                // code of the same image.
+                unsafe { waygate::attach_executable(&name, image.base(), callbacks, debug) }?;
+                // Kept mapped until the set detaches every module.
+                modules.add_image(image);
+                if debug {
+                    println!("executing entry point {entry:#x} through waygate bridges");
+                    debug_log("exec", "transferring control to the PE image");
+                }
+                // SAFETY: the image is mapped, relocated, bound and protected,
+                // and `executable_entry` only accepts code this host can run.
+                let code = unsafe { waygate::call_entry_point(entry) }?;
+                if debug {
+                    debug_log("done", &format!("entry point returned {code}"));
+                }
+                return Ok(code as i32);
+            }
+            None if debug => {
+                println!("image: entry point not executable on this host, replaying plan instead");
+            }
+            None => {}
+        }
+    }
+
     if debug {
         println!("generated plan: {}", plan_path.display());
         println!("executing plan through waygate");
     }
 
     for call in &analysis.winapi_calls {
-        match waygate::dispatch(&call.function, &call.args) {
+        match waygate::dispatch(call.module.as_deref(), &call.function, &call.args) {
             Ok(msg) if debug => println!(
                 "  [ok] {}({}) -> {msg}",
-                call.function,
+                call.qualified_name(),
                 call.args.join(", ")
             ),
             Ok(_) => {}
             Err(err) if debug => println!(
                 "  [err] {}({}) -> {err}",
-                call.function,
+                call.qualified_name(),
                 call.args.join(", ")
             ),
             Err(_) => {}
@@ -202,36 +492,406 @@ fn handle_non_native(mode: Mode, debug: bool, target: &Path, bytes: &[u8]) -> Re
     Ok(0)
 }
 
+fn load_pe_image(
+    bytes: &[u8],
+    pe: &PeContext,
+    name: &str,
+    modules: &mut ModuleSet,
+    debug: bool,
+) -> Result<LoadedImage, String> {
+    let mut image = LoadedImage::map(bytes, pe)?;
+    if debug {
+        print_image_report(&image);
+    }
+
+    if !image.is_at_preferred_base() {
+        if pe.characteristics & IMAGE_FILE_RELOCS_STRIPPED != 0 {
+            return Err(format!(
+                "preferred ImageBase {:#x} is unavailable and the image has its relocations stripped",
+                pe.image_base
+            ));
+        }
+        let relocations = parse_base_relocations(bytes, pe)
+            .ok_or_else(|| "malformed base relocation directory".to_string())?;
+        if relocations.is_empty() {
+            return Err(format!(
+                "preferred ImageBase {:#x} is unavailable and the image has no base relocations",
+                pe.image_base
+            ));
+        }
+        let report = image.relocate(&relocations)?;
+        if debug {
+            print_relocation_report(&report);
+        }
+    }
+    register_symbols(bytes, pe, name, &image, modules, debug);
+
+    let exports = parse_export_directory(bytes, pe)
+        .ok_or_else(|| format!("{name}: malformed export directory"))?;
+    if debug && !exports.entries.is_empty() {
+        print_export_report(&exports);
+    }
+    let forwarded = exports.forwarded_modules();
+    modules.register_exports(name, image.base(), exports);
+
+    if runs_on_host(pe) {
+        let mut imports = parse_import_modules(bytes, pe)
+            .ok_or_else(|| "malformed import directory".to_string())?;
+        imports.extend(
+            parse_delay_import_modules(bytes, pe)
+                .ok_or_else(|| "malformed delay-load import directory".to_string())?,
+        );
+        let dependencies = imports.iter().map(|module| &module.dll).chain(&forwarded);
+        for dll in dependencies {
+            load_app_local_dll(dll, modules, debug)?;
+        }
+        let report =
+            image.bind_imports(&imports, |dll, entry| modules.resolve(dll, entry), debug)?;
+        if debug {
+            print_bind_report(&report);
+        }
+        // Only x86_64 hosts get a TEB for static TLS to live behind.
+        match &pe.tls {
+            Some(tls) if cfg!(target_arch = "x86_64") => allocate_tls(tls, name, &image, debug)?,
+            Some(_) if debug => println!("tls: {name}: static TLS skipped on this host"),
+            _ => {}
+        }
+    } else if debug {
+        println!(
+            "imports: binding skipped ({} {} image on this host)",
+            pe.machine,
+            pe_format(pe)
+        );
+    }
+
+    image.protect()?;
+    Ok(image)
+}
+
+/// Hands the image to waygate so traces and unresolved-import reports name
+/// the calling code and, through its `.pdata`, the frames above it.
+/// Without a matching PDB, callers are still reported as `image+rva`.
+fn register_symbols(
+    bytes: &[u8],
+    pe: &PeContext,
+    name: &str,
+    image: &LoadedImage,
+    modules: &ModuleSet,
+    debug: bool,
+) {
+    let entries = parse_debug_directory(bytes, pe).unwrap_or_default();
+    let pdb = codeview(&entries).and_then(|codeview| {
+        let Some(path) = modules.find_pdb(codeview) else {
+            if debug {
+                println!("symbols: {name}: {} not found", codeview.path());
+            }
+            return None;
+        };
+        match load_pdb(&path, pe, codeview) {
+            Ok(symbols) => {
+                if debug {
+                    println!(
+                        "symbols: {name}: {} ({} procedure(s), {} public(s), {} line(s))",
+                        path.display(),
+                        symbols.function_count(),
+                        symbols.public_count(),
+                        symbols.line_count()
+                    );
+                }
+                Some(symbols)
+            }
+            Err(err) => {
+                if debug {
+                    println!("symbols: {name}: {err}");
+                }
+                None
+            }
+        }
+    });
+    let symbols = ImageSymbols {
+        name: name.to_string(),
+        base: image.base(),
+        size: image.size(),
+        pdb,
+    };
+    let exception = pe.directory(IMAGE_DIRECTORY_ENTRY_EXCEPTION);
+    waygate::register_image(waygate::CodeImage {
+        base: image.base(),
+        size: image.size(),
+        exception_rva: exception.map_or(0, |dir| dir.rva),
+        exception_size: exception.map_or(0, |dir| dir.size),
+        symbolizer: Box::new(move |address| symbols.describe(address)),
+    });
+}
+
+/// Loads `dll` from the application directory if that is where the search
+/// order finds it, together with its own app-local dependencies, and runs
+/// its `DllMain`. Built-in and missing DLLs are left to waygate.
+fn load_app_local_dll(dll: &str, modules: &mut ModuleSet, debug: bool) -> Result<(), String> {
+    if modules.is_registered(dll) {
+        return Ok(());
+    }
+    let DllLocation::AppLocal(path) = modules.search(dll) else {
+        return Ok(());
+    };
+    let bytes = fs::read(&path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let pe =
+        PeContext::parse(&bytes).ok_or_else(|| format!("{}: not a PE image", path.display()))?;
+    if pe.characteristics & IMAGE_FILE_DLL == 0 {
+        return Err(format!("{}: not a DLL", path.display()));
+    }
+    if !runs_on_host(&pe) {
+        return Err(format!(
+            "{}: {} {} DLL, app-local DLLs must be {} like the executable",
+            path.display(),
+            pe.machine,
+            pe_format(&pe),
+            if cfg!(target_arch = "x86") {
+                "i386 PE32"
+            } else {
+                "AMD64 PE32+"
+            }
+        ));
+    }
+    if debug {
+        debug_log("dll", &format!("loading {dll} from {}", path.display()));
+    }
+
+    let image = load_pe_image(&bytes, &pe, dll, modules, debug)?;
+    let entry = dll_entry(&pe, &image);
+    let callbacks = tls_callback_array(&pe, &image);
+    // SAFETY: the DLL is mapped, relocated, bound and protected, and only
+    // DLLs whose code this host can run get this far.
+    unsafe { waygate::attach_dll(dll, image.base(), entry, callbacks, debug) }?;
+    modules.add_image(image);
+    Ok(())
+}
+
+/// Gives the image its TLS index and the loading thread its copy of the
+/// static TLS template. Must run before [`LoadedImage::protect`], since
+/// `_tls_index` may live in a section that ends up read-only.
+fn allocate_tls(
+    tls: &TlsDirectory,
+    name: &str,
+    image: &LoadedImage,
+    debug: bool,
+) -> Result<(), String> {
+    let size = tls.template_size();
+    let start = image
+        .rebase(tls.raw_data_start, size)
+        .ok_or_else(|| format!("{name}: TLS template lies outside the image"))?;
+    let index_address = image
+        .rebase(tls.index_address, 4)
+        .ok_or_else(|| format!("{name}: TLS index lies outside the image"))?;
+    let template = waygate::TlsTemplate {
+        module: name.to_string(),
+        start,
+        size,
+        zero_fill: tls.zero_fill as usize,
+        alignment: tls.alignment(),
+        index_address,
+    };
+    // SAFETY: both ranges were checked to lie inside the mapped image, which
+    // is still writable and stays mapped for the life of the process.
+    let index = unsafe { waygate::allocate_tls_index(template) }?;
+    if debug {
+        println!(
+            "tls: {name}: index {index}, {size}-byte template + {} zero-filled, {} callback(s)",
+            tls.zero_fill,
+            tls.callbacks.len()
+        );
+    }
+    Ok(())
+}
+
+/// Mapped address of the image's TLS callback array, 0 when it has none.
+fn tls_callback_array(pe: &PeContext, image: &LoadedImage) -> u64 {
+    let pointer = if pe.is_pe64 { 8 } else { 4 };
+    pe.tls
+        .as_ref()
+        .and_then(|tls| image.rebase(tls.callbacks_address, pointer))
+        .unwrap_or(0)
+}
+
+/// Whether this host can run the image's code: AMD64 PE32+ images on an
+/// x86_64 host and i386 PE32 images on an x86 one.
+fn runs_on_host(pe: &PeContext) -> bool {
+    if cfg!(target_arch = "x86_64") {
+        pe.is_pe64 && pe.machine == Machine::Amd64
+    } else if cfg!(target_arch = "x86") {
+        !pe.is_pe64 && pe.machine == Machine::I386
+    } else {
+        false
+    }
+}
+
+fn pe_format(pe: &PeContext) -> &'static str {
+    if pe.is_pe64 {
+        "PE32+"
+    } else {
+        "PE32"
+    }
+}
+
+/// Entry point to run directly: only executables this host can run, since
+/// DLL entry points expect `DllMain` arguments.
+fn executable_entry(pe: &PeContext, image: &LoadedImage) -> Option<u64> {
+    if !runs_on_host(pe) || pe.characteristics & IMAGE_FILE_DLL != 0 {
+        return None;
+    }
+    image.entry_address()
+}
+
+/// `DllMain` of a DLL this host can run, if it has one.
+fn dll_entry(pe: &PeContext, image: &LoadedImage) -> Option<u64> {
+    if !runs_on_host(pe) || pe.characteristics & IMAGE_FILE_DLL == 0 {
+        return None;
+    }
+    image.entry_address()
+}
+
+/// Directory app-local DLLs are searched in: the one holding the target.
+fn app_dir(target: &Path) -> &Path {
+    target
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+fn target_name(target: &Path) -> String {
+    target
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("target")
+        .to_string()
+}
+
 fn debug_log(stage: &str, msg: &str) {
     eprintln!("[debug:{stage}] {msg}");
 }
 
 #[derive(Clone, Debug)]
 struct TracedCall {
+    /// DLL the symbol is imported from, when known.
+    module: Option<String>,
     function: String,
     args: Vec<String>,
     backtrace: Vec<String>,
+    delay_loaded: bool,
+    /// Set when the symbol was imported by ordinal rather than by name.
+    ordinal: Option<u16>,
+    /// Declared by a managed `[DllImport]` rather than the import table.
+    pinvoke: bool,
+    /// Code that calls the import, found by decoding the executable
+    /// sections. Empty for synthetic fixtures and traced runs.
+    call_sites: Vec<CallSite>,
+    /// Resolved at run time through `GetProcAddress` rather than imported.
+    dynamic: bool,
+    /// `GetProcAddress` call sites that name a dynamic symbol.
+    resolved_at: Vec<u32>,
+    /// Where a dynamic symbol's name is stored in read-only data.
+    name_rva: Option<u32>,
+}
+
+impl TracedCall {
+    fn new(module: Option<String>, function: String, args: Vec<String>) -> Self {
+        Self {
+            module,
+            function,
+            args,
+            backtrace: Vec::new(),
+            delay_loaded: false,
+            ordinal: None,
+            pinvoke: false,
+            call_sites: Vec::new(),
+            dynamic: false,
+            resolved_at: Vec::new(),
+            name_rva: None,
+        }
+    }
+
+    /// `DLL!function` when the module is known, the bare function otherwise.
+    fn qualified_name(&self) -> String {
+        match &self.module {
+            Some(module) => format!("{module}!{}", self.function),
+            None => self.function.clone(),
+        }
+    }
 }
 
 #[derive(Default)]
 struct Analysis {
     winapi_calls: Vec<TracedCall>,
     non_windows_libs: Vec<String>,
+    /// DLLs the program names for `LoadLibrary`/`GetModuleHandle` without
+    /// importing from them.
+    dynamic_modules: Vec<DynamicModule>,
 }
 
-fn parse_args() -> Result<(Mode, bool, PathBuf), String> {
+/// A name the program looks up at run time, with the call sites that pass
+/// it and where it is stored.
+#[derive(Clone, Debug)]
+struct DynamicModule {
+    name: String,
+    loaded_at: Vec<u32>,
+    name_rva: Option<u32>,
+}
+
+fn parse_args() -> Result<Options, String> {
     let args: Vec<String> = env::args().skip(1).collect();
+    let (symbol_dir, args) = match args.as_slice() {
+        [flag, dir, rest @ ..] if flag == "--symbols" => (Some(PathBuf::from(dir)), rest),
+        rest => (None, rest),
+    };
+    // `--` before the binary makes it a path even when it is named like a
+    // subcommand.
+    let (args, literal) = match args {
+        [flags @ .., separator, path] if separator == "--" => {
+            ([flags, std::slice::from_ref(path)].concat(), true)
+        }
+        args => (args.to_vec(), false),
+    };
+    let options = |mode, debug, path: &str| Options {
+        mode,
+        debug,
+        target: PathBuf::from(path),
+        inspect: InspectOptions {
+            icon_dir: None,
+            overlay_out: None,
+        },
+        symbol_dir: symbol_dir.clone(),
+    };
     match args.as_slice() {
-        [path] => Ok((Mode::Run, false, PathBuf::from(path))),
-        [flag, path] if flag == "-d" => Ok((Mode::Run, true, PathBuf::from(path))),
-        [flag, path] if flag == "-c" => Ok((Mode::CompileOnly, false, PathBuf::from(path))),
+        [path] if literal || !SUBCOMMANDS.contains(&path.as_str()) => {
+            Ok(options(Mode::Run, false, path))
+        }
+        [command, path] if command == "deps" => Ok(options(Mode::Dependencies, false, path)),
+        [flag, path] if flag == "-d" => Ok(options(Mode::Run, true, path)),
+        [flag, path] if flag == "-c" => Ok(options(Mode::CompileOnly, false, path)),
         [flag, path] if flag == "-cd" || flag == "-dc" => {
-            Ok((Mode::CompileOnly, true, PathBuf::from(path)))
+            Ok(options(Mode::CompileOnly, true, path))
         }
-        _ => Err("usage: winrun [-d] [-c|-cd] <binary-file>".to_string()),
+        [command, flags @ .., path] if command == "inspect" && flags.len() % 2 == 0 => {
+            let mut options = options(Mode::Inspect, false, path);
+            for pair in flags.chunks(2) {
+                let value = Some(PathBuf::from(&pair[1]));
+                match pair[0].as_str() {
+                    "--icons" => options.inspect.icon_dir = value,
+                    "--extract-overlay" => options.inspect.overlay_out = value,
+                    _ => return Err(usage()),
+                }
+            }
+            Ok(options)
+        }
+        _ => Err(usage()),
     }
 }
 
+fn usage() -> String {
+    "usage: winrun [--symbols <dir>] [-d] [-c|-cd] [--] <binary-file>\n       winrun inspect [--icons <dir>] [--extract-overlay <file>] [--] <binary-file>\n       winrun deps [--] <binary-file>"
+        .to_string()
+}
+
 fn detect_format(bytes: &[u8]) -> &'static str {
     if bytes.starts_with(&[0x7F, b'E', b'L', b'F']) {
         "ELF"
@@ -265,34 +925,73 @@ fn plan_output_path(target: &Path) -> PathBuf {
     out
 }
 
-fn write_plan_file(path: &Path, calls: &[TracedCall]) -> io::Result<()> {
+/// Writes one row per call site when the code was scanned for them, and
+/// one row per symbol otherwise. Site rows carry the arguments recovered
+/// at that site and end in a `kind@rva` column. Dynamic symbols get a row
+/// per `GetProcAddress` site naming them, or one pointing at the string.
+/// Dynamically loaded DLLs are listed as comments. Returns the number of
+/// rows.
+fn write_plan_file(path: &Path, analysis: &Analysis) -> io::Result<usize> {
     let mut text = String::from("# waygate execution plan\n");
-    for (idx, call) in calls.iter().enumerate() {
-        let typed_args = typed_args_for_call(call);
-        text.push_str(&format!(
-            "{}\t{}\t{}\n",
-            idx + 1,
-            call.function,
-            typed_args.join("||")
-        ));
+    for module in &analysis.dynamic_modules {
+        text.push_str(&format!("# dynamically loaded: {}\n", module.name));
     }
-    fs::write(path, text)
+    let mut idx = 0;
+    for call in &analysis.winapi_calls {
+        let sites: Vec<(String, String)> = match call.call_sites.as_slice() {
+            [] if call.dynamic => match (call.resolved_at.as_slice(), call.name_rva) {
+                ([], Some(rva)) => vec![(String::new(), format!("\tstring@{rva:#x}"))],
+                ([], None) => vec![(typed_args(&call.args), "\tdynamic".to_string())],
+                (sites, _) => sites
+                    .iter()
+                    .map(|rva| (String::new(), format!("\tgetprocaddress@{rva:#x}")))
+                    .collect(),
+            },
+            [] => vec![(typed_args(&call.args), String::new())],
+            sites => sites
+                .iter()
+                .map(|site| {
+                    let location = format!("\t{}@{:#x}", site.kind.label(), site.rva);
+                    (typed_args(&site.args), location)
+                })
+                .collect(),
+        };
+        for (typed_args, site) in sites {
+            idx += 1;
+            text.push_str(&format!(
+                "{idx}\t{}\t{typed_args}{site}\n",
+                call.qualified_name()
+            ));
+        }
+    }
+    fs::write(path, text)?;
+    Ok(idx)
 }
 
-fn typed_args_for_call(call: &TracedCall) -> Vec<String> {
-    call.args.iter().map(|arg| format_typed_arg(arg)).collect()
+fn typed_args(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| format_typed_arg(arg))
+        .collect::<Vec<_>>()
+        .join("||")
 }
 
 fn format_typed_arg(arg: &str) -> String {
     if let Some((name, value)) = arg.split_once('=') {
         let ty = infer_arg_type(value.trim());
-        format!("{}:{}={}", name.trim(), ty, value.trim())
+        format!("{}:{}={}", name.trim(), ty, plan_value(value.trim()))
     } else {
         let ty = infer_arg_type(arg.trim());
-        format!("value:{}={}", ty, arg.trim())
+        format!("value:{}={}", ty, plan_value(arg.trim()))
     }
 }
 
+/// Plan arguments are joined with `||`, so a `|` inside a value, such as
+/// one in a recovered string literal, is written as the `\u{7c}` escape a
+/// Rust string literal would use.
+fn plan_value(value: &str) -> String {
+    value.replace('|', "\\u{7c}")
+}
+
 fn infer_arg_type(raw: &str) -> &'static str {
     let value = raw.trim();
     if value.is_empty() {
@@ -459,9 +1158,8 @@ fn parse_trace_block(lines: &[String]) -> Option<TracedCall> {
     }
 
     Some(TracedCall {
-        function,
-        args,
         backtrace,
+        ..TracedCall::new(None, function, args)
     })
 }
 
@@ -493,6 +1191,13 @@ fn parse_arg_line(line: &str) -> Option<(String, String)> {
     Some((key.to_string(), value.trim().to_string()))
 }
 
+/// Whether an imported or looked-up name is analyzed: `KNOWN_WINAPI`, or
+/// anything waygate's catalog exports. Free-text fixture scans stick to the
+/// list, since catalog names such as `send` are ordinary words.
+fn is_known_winapi(name: &str) -> bool {
+    KNOWN_WINAPI.contains(&name) || waygate::is_implemented(name)
+}
+
 fn analyze_non_native(bytes: &[u8]) -> Analysis {
     if let Some(parsed) = parse_pe_imports(bytes) {
         return parsed;
@@ -502,140 +1207,98 @@ fn analyze_non_native(bytes: &[u8]) -> Analysis {
 
 fn parse_pe_imports(bytes: &[u8]) -> Option<Analysis> {
     let pe = PeContext::parse(bytes)?;
-    let import_rva = pe.import_rva;
-    if import_rva == 0 {
-        return Some(Analysis::default());
-    }
+    let mut modules = parse_import_modules(bytes, &pe)?;
+    modules.extend(parse_delay_import_modules(bytes, &pe)?);
 
-    let mut seen = HashSet::new();
+    // Import index by `dll!function`, and by IAT slot for the call-site scan.
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut slots: HashMap<u32, usize> = HashMap::new();
     let mut calls = Vec::new();
-    let mut idx = 0usize;
-    loop {
-        let desc = pe.rva_to_offset(import_rva as usize + (idx * 20))?;
-        let original_first_thunk = read_u32(bytes, desc)?;
-        let name_rva = read_u32(bytes, desc + 12)?;
-        let first_thunk = read_u32(bytes, desc + 16)?;
-        if original_first_thunk == 0 && name_rva == 0 && first_thunk == 0 {
-            break;
-        }
-
-        let thunk_rva = if original_first_thunk != 0 {
-            original_first_thunk
-        } else {
-            first_thunk
-        } as usize;
-        let thunk_size = if pe.is_pe64 { 8 } else { 4 };
-        let ordinal_flag: u64 = if pe.is_pe64 {
-            0x8000_0000_0000_0000
-        } else {
-            0x8000_0000
-        };
-
-        let mut t = 0usize;
-        loop {
-            let thunk_off = match pe.rva_to_offset(thunk_rva + t * thunk_size) {
-                Some(v) => v,
-                None => break,
-            };
-            let entry = if pe.is_pe64 {
-                read_u64(bytes, thunk_off)?
-            } else {
-                read_u32(bytes, thunk_off)? as u64
-            };
-            if entry == 0 {
-                break;
-            }
-            if entry & ordinal_flag == 0 {
-                let hint_name = pe.rva_to_offset(entry as usize)?;
-                let name = read_c_string(bytes, hint_name + 2)?;
-                if KNOWN_WINAPI.contains(&name.as_str()) && seen.insert(name.clone()) {
-                    calls.push(TracedCall {
-                        function: name,
-                        args: Vec::new(),
-                        backtrace: Vec::new(),
-                    });
+    for module in &modules {
+        for entry in &module.entries {
+            let (function, ordinal) = match (&entry.name, entry.ordinal) {
+                (Some(name), _) if is_known_winapi(name) => (name.clone(), None),
+                (Some(_), _) | (None, None) => continue,
+                // Ordinal imports are kept even when unknown: dropping them
+                // would hide real dependencies from the report and plan.
+                (None, Some(ordinal)) => {
+                    let function = waygate::ordinal_name(&module.dll, ordinal)
+                        .map_or_else(|| format!("#{ordinal}"), str::to_string);
+                    (function, Some(ordinal))
                 }
-            }
-            t += 1;
+            };
+            let key = format!("{}!{function}", module.dll.to_ascii_lowercase());
+            let index = *seen.entry(key).or_insert_with(|| {
+                let mut call = TracedCall::new(Some(module.dll.clone()), function, Vec::new());
+                call.delay_loaded = module.delay_loaded;
+                call.ordinal = ordinal;
+                calls.push(call);
+                calls.len() - 1
+            });
+            slots.insert(entry.iat_rva as u32, index);
         }
-
-        idx += 1;
     }
-
-    Some(Analysis {
-        winapi_calls: calls,
-        non_windows_libs: Vec::new(),
-    })
-}
-
-struct PeSection {
-    virtual_address: usize,
-    mapped_size: usize,
-    raw_ptr: usize,
-}
-
-struct PeContext {
-    is_pe64: bool,
-    import_rva: u32,
-    sections: Vec<PeSection>,
-}
-
-impl PeContext {
-    fn parse(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() < 0x40 || !bytes.starts_with(b"MZ") {
-            return None;
+    let code = sweep(bytes, &pe);
+    let targets = branch_targets(&code);
+    for mut site in find_call_sites(&code, &pe, &slots) {
+        let call = &mut calls[slots[&site.iat_rva]];
+        if let Some(export) = call
+            .module
+            .as_deref()
+            .and_then(|module| waygate::resolve(module, &call.function))
+        {
+            site.args = recover_args(bytes, &pe, &code, &targets, &site, export);
         }
-
-        let pe_offset = read_u32(bytes, 0x3C)? as usize;
-        if pe_offset.checked_add(0x18)? > bytes.len() {
-            return None;
-        }
-        if bytes.get(pe_offset..pe_offset + 4)? != b"PE\0\0" {
-            return None;
+        call.call_sites.push(site);
+    }
+    // The symbol itself shows the first site's arguments, which is also
+    // what a plan replay passes.
+    for call in &mut calls {
+        if let Some(site) = call.call_sites.iter().find(|site| !site.args.is_empty()) {
+            call.args = site.args.clone();
         }
+    }
 
-        let section_count = read_u16(bytes, pe_offset + 6)? as usize;
-        let optional_size = read_u16(bytes, pe_offset + 20)? as usize;
-        let optional_header_offset = pe_offset + 24;
-        let magic = read_u16(bytes, optional_header_offset)?;
-        let (is_pe64, data_directory_offset) = match magic {
-            0x10B => (false, optional_header_offset + 96),
-            0x20B => (true, optional_header_offset + 112),
-            _ => return None,
+    // Managed images import only mscoree!_CorExeMain; their native calls
+    // are declared method by method in the P/Invoke metadata instead.
+    let pinvokes = parse_clr(bytes, &pe).map_or_else(Vec::new, |clr| clr.pinvokes);
+    for pinvoke in pinvokes {
+        let dll = pinvoke.dll();
+        let candidates = pinvoke.candidates();
+        let function = match pinvoke.ordinal() {
+            Some(ordinal) => waygate::ordinal_name(&dll, ordinal)
+                .map_or_else(|| format!("#{ordinal}"), str::to_string),
+            None => candidates
+                .iter()
+                .find(|name| is_known_winapi(name))
+                .unwrap_or(&candidates[0])
+                .clone(),
         };
-
-        let import_rva = read_u32(bytes, data_directory_offset + 8)?;
-        let section_table = pe_offset + 24 + optional_size;
-        let mut sections = Vec::with_capacity(section_count);
-        for i in 0..section_count {
-            let sec = section_table + i * 40;
-            let virtual_size = read_u32(bytes, sec + 8)? as usize;
-            let virtual_address = read_u32(bytes, sec + 12)? as usize;
-            let raw_size = read_u32(bytes, sec + 16)? as usize;
-            let raw_ptr = read_u32(bytes, sec + 20)? as usize;
-            sections.push(PeSection {
-                virtual_address,
-                mapped_size: virtual_size.max(raw_size),
-                raw_ptr,
-            });
+        let key = format!("{}!{function}", dll.to_ascii_lowercase());
+        if let Entry::Vacant(slot) = seen.entry(key) {
+            slot.insert(calls.len());
+            let mut call = TracedCall::new(Some(dll), function, Vec::new());
+            call.ordinal = pinvoke.ordinal();
+            call.pinvoke = true;
+            calls.push(call);
         }
-
-        Some(Self {
-            is_pe64,
-            import_rva,
-            sections,
-        })
     }
 
-    fn rva_to_offset(&self, rva: usize) -> Option<usize> {
-        for section in &self.sections {
-            if rva >= section.virtual_address && rva < section.virtual_address + section.mapped_size
-            {
-                return section.raw_ptr.checked_add(rva - section.virtual_address);
-            }
-        }
-        None
-    }
+    let mut skip: HashSet<usize> = modules.iter().map(|module| module.name_rva).collect();
+    skip.extend(
+        modules
+            .iter()
+            .flat_map(|module| &module.entries)
+            .filter_map(|entry| entry.name_rva),
+    );
+    let strings = read_only_strings(bytes, &pe, &skip);
+    let imported: Vec<&str> = modules.iter().map(|module| module.dll.as_str()).collect();
+    let mut analysis = Analysis {
+        winapi_calls: calls,
+        ..Analysis::default()
+    };
+    add_dynamic_resolution(&mut analysis, &imported, &strings);
+    Some(analysis)
 }
 
 fn parse_synthetic_fixture(bytes: &[u8]) -> Analysis {
@@ -644,6 +1307,11 @@ fn parse_synthetic_fixture(bytes: &[u8]) -> Analysis {
     let mut seen_non_empty_args = BTreeSet::new();
     let mut winapi_calls = Vec::new();
     let mut libs = BTreeSet::new();
+    let dlls: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.to_ascii_lowercase().ends_with(".dll"))
+        .collect();
 
     for line in text.lines() {
         let trimmed = line.trim();
@@ -661,87 +1329,228 @@ fn parse_synthetic_fixture(bytes: &[u8]) -> Analysis {
         }
 
         for sym in KNOWN_WINAPI {
-            if !lower.contains(&sym.to_ascii_lowercase()) {
+            if find_symbol(&lower, &sym.to_ascii_lowercase()).is_none() {
                 continue;
             }
 
-            let call = parse_symbol_call_case_insensitive(trimmed, sym);
-            let signature = format!("{}({})", call.function, call.args.join(","));
+            let mut call = parse_symbol_call_case_insensitive(trimmed, sym);
+            if call.module.is_none() {
+                call.module = dlls
+                    .iter()
+                    .find(|dll| waygate::resolve(dll, sym).is_some())
+                    .map(|dll| dll.to_string());
+            }
+            let signature = format!("{}({})", call.qualified_name(), call.args.join(","));
             if !seen_signatures.insert(signature) {
                 continue;
             }
 
             if call.args.is_empty() {
-                if seen_non_empty_args.contains(&call.function) {
+                if seen_non_empty_args.contains(&call.qualified_name()) {
                     continue;
                 }
             } else {
-                seen_non_empty_args.insert(call.function.clone());
+                seen_non_empty_args.insert(call.qualified_name());
             }
 
             winapi_calls.push(call);
         }
     }
 
-    Analysis {
+    let mut analysis = Analysis {
         winapi_calls,
         non_windows_libs: libs.into_iter().collect(),
+        dynamic_modules: Vec::new(),
+    };
+    add_dynamic_resolution(&mut analysis, &dlls, &[]);
+    analysis
+}
+
+/// Adds what the program looks up at run time. Catalog symbols become
+/// dynamic entries when a `GetProcAddress` call passes them or when they
+/// sit in read-only data (`strings`); DLL names passed to `LoadLibrary` or
+/// `GetModuleHandle`, or stored as strings, become dynamic modules. Names
+/// the import table already covers are left out. A symbol is attributed to
+/// the first dynamic or imported DLL that exports it, else to the catalog's.
+fn add_dynamic_resolution(analysis: &mut Analysis, imported: &[&str], strings: &[DataString]) {
+    let mut symbols: Vec<DynamicModule> = Vec::new();
+    let mut dlls: Vec<DynamicModule> = Vec::new();
+    let note = |list: &mut Vec<DynamicModule>, name: &str| -> usize {
+        match list
+            .iter()
+            .position(|known| known.name.eq_ignore_ascii_case(name))
+        {
+            Some(index) => index,
+            None => {
+                list.push(DynamicModule {
+                    name: name.to_string(),
+                    loaded_at: Vec::new(),
+                    name_rva: None,
+                });
+                list.len() - 1
+            }
+        }
+    };
+
+    for call in &analysis.winapi_calls {
+        let Some(export) = waygate::lookup(&call.function) else {
+            continue;
+        };
+        let resolves_symbol = match export.family {
+            "GetProcAddress" => true,
+            "LoadLibrary" | "LoadLibraryEx" | "GetModuleHandle" | "GetModuleHandleEx" => false,
+            _ => continue,
+        };
+        let Some(param) = export
+            .params
+            .iter()
+            .find(|param| matches!(param.kind, ArgKind::Str | ArgKind::WStr))
+        else {
+            continue;
+        };
+        let uses: Vec<(Option<u32>, &[String])> = match call.call_sites.as_slice() {
+            [] => vec![(None, call.args.as_slice())],
+            sites => sites
+                .iter()
+                .map(|site| (Some(site.rva), site.args.as_slice()))
+                .collect(),
+        };
+        for (rva, args) in uses {
+            let Some(name) = string_arg(args, param.name) else {
+                continue;
+            };
+            let list = if !resolves_symbol {
+                &mut dlls
+            } else if is_known_winapi(&name) {
+                &mut symbols
+            } else {
+                continue;
+            };
+            let index = note(list, dll_file_name(&name));
+            list[index].loaded_at.extend(rva);
+        }
+    }
+    for string in strings {
+        let list = if is_known_winapi(&string.text) {
+            &mut symbols
+        } else if is_dll_name(&string.text) {
+            &mut dlls
+        } else {
+            continue;
+        };
+        let index = note(list, dll_file_name(&string.text));
+        list[index].name_rva.get_or_insert(string.rva);
+    }
+
+    for symbol in symbols {
+        let function = symbol.name;
+        if analysis
+            .winapi_calls
+            .iter()
+            .any(|call| call.function == function)
+        {
+            continue;
+        }
+        let module = dlls
+            .iter()
+            .map(|dll| dll.name.as_str())
+            .chain(imported.iter().copied())
+            .find(|dll| waygate::resolve(dll, &function).is_some())
+            .map(str::to_string)
+            .or_else(|| waygate::lookup(&function).map(|export| format!("{}.dll", export.dll)));
+        let mut call = TracedCall::new(module, function, Vec::new());
+        call.dynamic = true;
+        call.resolved_at = symbol.loaded_at;
+        call.name_rva = symbol.name_rva;
+        analysis.winapi_calls.push(call);
     }
+    analysis.dynamic_modules = dlls
+        .into_iter()
+        .filter(|dll| {
+            !imported
+                .iter()
+                .any(|imported| same_module(imported, &dll.name))
+        })
+        .collect();
 }
 
-fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
-    Some(u16::from_le_bytes(
-        bytes.get(offset..offset + 2)?.try_into().ok()?,
-    ))
+/// The string value of `name="..."` among recovered or fixture arguments.
+fn string_arg(args: &[String], name: &str) -> Option<String> {
+    args.iter().find_map(|arg| {
+        let (key, value) = arg.split_once('=')?;
+        let value = value.trim();
+        (key.trim() == name && value.len() >= 2 && value.starts_with('"') && value.ends_with('"'))
+            .then(|| value[1..value.len() - 1].to_string())
+    })
 }
 
-fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
-    Some(u32::from_le_bytes(
-        bytes.get(offset..offset + 4)?.try_into().ok()?,
-    ))
+/// The file name of a DLL path as passed to `LoadLibrary`.
+fn dll_file_name(path: &str) -> &str {
+    path.rsplit(['\\', '/']).next().unwrap_or(path)
 }
 
-fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
-    Some(u64::from_le_bytes(
-        bytes.get(offset..offset + 8)?.try_into().ok()?,
-    ))
+/// A string that names a DLL file (`user32.dll`, `C:\\lib\\foo.dll`), not a
+/// message that merely ends in one.
+fn is_dll_name(text: &str) -> bool {
+    let name = dll_file_name(text);
+    name.len() > 4
+        && name.to_ascii_lowercase().ends_with(".dll")
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
 }
 
-fn read_c_string(bytes: &[u8], offset: usize) -> Option<String> {
-    let slice = bytes.get(offset..)?;
-    let end = slice.iter().position(|b| *b == 0)?;
-    std::str::from_utf8(&slice[..end])
-        .ok()
-        .map(|s| s.to_string())
+/// Compares DLL names the way the loader does: case-insensitively, with
+/// or without `.dll`.
+fn same_module(a: &str, b: &str) -> bool {
+    let stem = |name: &str| {
+        let name = name.to_ascii_lowercase();
+        name.strip_suffix(".dll").unwrap_or(&name).to_string()
+    };
+    stem(a) == stem(b)
 }
 
+/// Parses `symbol(args)` out of a fixture line. An explicit `module.dll!`
+/// prefix on the symbol sets the call's module.
 fn parse_symbol_call_case_insensitive(line: &str, symbol: &str) -> TracedCall {
     let lower_line = line.to_ascii_lowercase();
     let lower_symbol = symbol.to_ascii_lowercase();
-    if let Some(start) = lower_line.find(&lower_symbol) {
+    let mut call = TracedCall::new(None, symbol.to_string(), Vec::new());
+    if let Some(start) = find_symbol(&lower_line, &lower_symbol) {
+        call.module = line[..start]
+            .strip_suffix('!')
+            .and_then(|head| head.rsplit(char::is_whitespace).next())
+            .filter(|module| !module.is_empty())
+            .map(str::to_string);
         let rest = &line[start + symbol.len()..];
         if rest.starts_with('(') {
             if let Some(end) = rest.find(')') {
                 let inner = &rest[1..end];
-                let args = inner
+                call.args = inner
                     .split(',')
                     .map(|s| s.trim().to_string())
                     .filter(|s| !s.is_empty())
                     .collect();
-                return TracedCall {
-                    function: symbol.to_string(),
-                    args,
-                    backtrace: Vec::new(),
-                };
             }
         }
     }
+    call
+}
 
-    TracedCall {
-        function: symbol.to_string(),
-        args: Vec::new(),
-        backtrace: Vec::new(),
-    }
+/// Finds `symbol` in `line` as a whole identifier, so `LoadLibrary` does not
+/// match inside `LoadLibraryA` nor `SetEvent` inside `ResetEvent`. Names
+/// inside string literals are data, such as a `GetProcAddress` argument, and
+/// do not count.
+fn find_symbol(line: &str, symbol: &str) -> Option<usize> {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    line.match_indices(symbol)
+        .map(|(start, _)| start)
+        .find(|&start| {
+            let before = line[..start].chars().next_back();
+            let after = line[start + symbol.len()..].chars().next();
+            let quoted = line[..start].matches('"').count() % 2 == 1;
+            !before.is_some_and(is_ident) && !after.is_some_and(is_ident) && !quoted
+        })
 }
 
 fn print_trace_report(trace: &[TracedCall]) {
@@ -763,17 +1572,90 @@ fn print_trace_report(trace: &[TracedCall]) {
 }
 
 fn print_non_native_report(analysis: &Analysis) {
-    println!("win32api: found {} symbol(s)", analysis.winapi_calls.len());
-    for (i, call) in analysis.winapi_calls.iter().enumerate() {
-        if call.args.is_empty() {
-            println!("  {:>2}. {}", i + 1, call.function);
-        } else {
-            println!(
-                "  {:>2}. {}({})",
-                i + 1,
-                call.function,
-                call.args.join(", ")
-            );
+    let mut modules: Vec<Option<&str>> = Vec::new();
+    for call in &analysis.winapi_calls {
+        if !modules.contains(&call.module.as_deref()) {
+            modules.push(call.module.as_deref());
+        }
+    }
+    println!(
+        "win32api: found {} symbol(s) in {} module(s)",
+        analysis.winapi_calls.len(),
+        modules.len()
+    );
+    for module in modules {
+        match module {
+            Some(module) => match waygate::api_set_host(module) {
+                Some(host) => println!("  {module} -> {host}.dll:"),
+                None => println!("  {module}:"),
+            },
+            None => println!("  (module unknown):"),
+        }
+        for (i, call) in analysis.winapi_calls.iter().enumerate() {
+            if call.module.as_deref() != module {
+                continue;
+            }
+            let delay = if call.delay_loaded {
+                " [delay-load]"
+            } else if call.pinvoke {
+                " [P/Invoke]"
+            } else if call.dynamic {
+                " [dynamic]"
+            } else {
+                ""
+            };
+            let symbol = match call.ordinal {
+                Some(ordinal) if call.function.starts_with('#') => {
+                    format!("#{ordinal} (unknown ordinal)")
+                }
+                Some(ordinal) => format!("#{ordinal} ({})", call.function),
+                None => call.function.clone(),
+            };
+            if call.args.is_empty() {
+                println!("    {:>2}. {symbol}{delay}", i + 1);
+            } else {
+                println!(
+                    "    {:>2}. {symbol}({}){delay}",
+                    i + 1,
+                    call.args.join(", ")
+                );
+            }
+            if !call.call_sites.is_empty() {
+                let sites: Vec<String> = call
+                    .call_sites
+                    .iter()
+                    .map(|site| match site.args.as_slice() {
+                        [] => format!("{}@{:#x}", site.kind.label(), site.rva),
+                        args => {
+                            format!("{}@{:#x}({})", site.kind.label(), site.rva, args.join(", "))
+                        }
+                    })
+                    .collect();
+                println!("        call sites: {}", sites.join(", "));
+            }
+            if !call.resolved_at.is_empty() {
+                println!(
+                    "        resolved by GetProcAddress at {}",
+                    format_rvas(&call.resolved_at)
+                );
+            } else if let Some(rva) = call.name_rva {
+                println!("        named in read-only data at {rva:#x}");
+            }
+        }
+    }
+
+    if analysis.dynamic_modules.is_empty() {
+        println!("dynamically loaded modules: none");
+    } else {
+        println!("dynamically loaded modules:");
+        for module in &analysis.dynamic_modules {
+            match (module.loaded_at.as_slice(), module.name_rva) {
+                ([], Some(rva)) => {
+                    println!("  - {} (named in read-only data at {rva:#x})", module.name)
+                }
+                ([], None) => println!("  - {}", module.name),
+                (sites, _) => println!("  - {} (loaded at {})", module.name, format_rvas(sites)),
+            }
         }
     }
 
@@ -786,3 +1668,125 @@ fn print_non_native_report(analysis: &Analysis) {
         }
     }
 }
+
+fn format_rvas(rvas: &[u32]) -> String {
+    rvas.iter()
+        .map(|rva| format!("{rva:#x}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn print_image_report(image: &LoadedImage) {
+    println!(
+        "image: mapped {:#x} bytes at {:#x} (preferred {:#x}{})",
+        image.size(),
+        image.base(),
+        image.preferred_base(),
+        if image.is_at_preferred_base() {
+            ""
+        } else {
+            ", relocation required"
+        }
+    );
+    match image.entry_address() {
+        Some(entry) => println!("image: entry point {entry:#x}"),
+        None => println!("image: no entry point"),
+    }
+    for section in image.sections() {
+        println!(
+            "  {:<8} rva {:#010x} size {:#08x} {} copied {:#x} zero-filled {:#x}",
+            section.name,
+            section.rva,
+            section.size,
+            section.protection,
+            section.copied,
+            section.zero_filled
+        );
+    }
+}
+
+fn print_relocation_report(report: &RelocationReport) {
+    println!(
+        "relocations: applied {} fixup(s), delta {}{:#x}, {} padding entr{}",
+        report.applied,
+        if report.delta < 0 { '-' } else { '+' },
+        report.delta.unsigned_abs(),
+        report.padding,
+        if report.padding == 1 { "y" } else { "ies" }
+    );
+    for (section, count) in &report.per_section {
+        if *count > 0 {
+            println!("  {section:<8} {count} fixup(s)");
+        }
+    }
+}
+
+fn print_export_report(exports: &ExportDirectory) {
+    let forwarded = exports
+        .entries
+        .iter()
+        .filter(|entry| matches!(entry.target, ExportTarget::Forwarder(_)))
+        .count();
+    println!(
+        "exports: {} entr{} in {}, {} forwarded",
+        exports.entries.len(),
+        if exports.entries.len() == 1 {
+            "y"
+        } else {
+            "ies"
+        },
+        exports.dll_name,
+        forwarded
+    );
+}
+
+fn print_bind_report(report: &BindReport) {
+    println!(
+        "imports: bound {} IAT slot(s) ({} to app-local DLLs, {} delay-loaded), {} routed to the missing-import trap",
+        report.bound,
+        report.local,
+        report.lazy,
+        report.missing.len()
+    );
+    for symbol in &report.missing {
+        println!("  - {symbol}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testpe::{TestPe, IMAGE_SCN_CODE};
+
+    #[test]
+    fn plan_arguments_escape_the_separator() {
+        let args = ["text=\"a||b|\"".to_string(), "kind=0".to_string()];
+        let plan = typed_args(&args);
+        assert_eq!(plan.split("||").count(), 2);
+        assert!(plan.starts_with("text:string=\"a\\u{7c}\\u{7c}b\\u{7c}\"||"));
+    }
+
+    fn refusal(pe: TestPe) -> Option<i32> {
+        let bytes = pe.section(".text", 0x1000, &[0xC3], IMAGE_SCN_CODE).build();
+        let pe = PeContext::parse(&bytes).expect("image parses");
+        check_supported(&pe).err().map(|failure| failure.code)
+    }
+
+    #[test]
+    fn console_and_gui_programs_are_supported() {
+        assert_eq!(refusal(TestPe::new(true)), None);
+        assert_eq!(refusal(TestPe::new(false).subsystem(2)), None);
+    }
+
+    #[test]
+    fn foreign_machines_exit_3() {
+        let arm64 = TestPe::new(true).machine(0xAA64);
+        assert_eq!(refusal(arm64), Some(EXIT_UNSUPPORTED_MACHINE));
+    }
+
+    #[test]
+    fn native_subsystems_exit_4() {
+        let driver = TestPe::new(true).subsystem(1);
+        assert_eq!(refusal(driver), Some(EXIT_UNSUPPORTED_SUBSYSTEM));
+    }
+}
@@ -0,0 +1,222 @@
+use waygate::{AppCompat, DpiAwareness, OsVersion};
+
+use crate::pe::PeContext;
+use crate::resources::{manifest_text, parse_resources, ResourceId, RT_MANIFEST};
+
+/// `supportedOS` IDs from the compatibility section, oldest first.
+const SUPPORTED_OS: &[(&str, &str)] = &[
+    ("{e2011457-1546-43c5-a5fe-008deee3d3f0}", "Windows Vista"),
+    ("{35138b9a-5d96-4fbd-8e2d-a2440225f93a}", "Windows 7"),
+    ("{4a2f28e3-53b9-4441-ba9c-d69d4a4a6e38}", "Windows 8"),
+    ("{1f676c76-80e1-4239-95bb-83d0f6d0da78}", "Windows 8.1"),
+    ("{8e0f7a12-bfb3-4fe8-b9a5-48fd50a15a9a}", "Windows 10/11"),
+];
+
+const COMMON_CONTROLS: &str = "Microsoft.Windows.Common-Controls";
+/// Microsoft's key token for Windows side-by-side assemblies.
+const WINDOWS_KEY_TOKEN: &str = "6595b64144ccf1df";
+
+/// One `<dependentAssembly>` identity.
+#[derive(Clone, Debug, Default)]
+pub struct AssemblyIdentity {
+    pub name: String,
+    pub version: String,
+    pub architecture: String,
+    pub public_key_token: String,
+}
+
+impl AssemblyIdentity {
+    /// True for the side-by-side Common Controls 6 assembly that gives an
+    /// app themed controls and the comctl32 v6 API.
+    pub fn is_common_controls_v6(&self) -> bool {
+        self.name.eq_ignore_ascii_case(COMMON_CONTROLS)
+            && self.version.starts_with("6.")
+            && self
+                .public_key_token
+                .eq_ignore_ascii_case(WINDOWS_KEY_TOKEN)
+    }
+}
+
+/// The parts of an application manifest that change how the program
+/// behaves on Windows.
+#[derive(Clone, Debug, Default)]
+pub struct Manifest {
+    /// `asInvoker`, `highestAvailable` or `requireAdministrator`.
+    pub execution_level: Option<String>,
+    pub ui_access: bool,
+    /// `supportedOS` GUIDs, lowercased, in manifest order.
+    pub supported_os: Vec<String>,
+    /// Raw `<dpiAware>` text (`true`, `true/pm`, `per monitor`, ...).
+    pub dpi_aware: Option<String>,
+    /// Raw `<dpiAwareness>` text, a comma-separated preference list.
+    pub dpi_awareness: Option<String>,
+    pub dependencies: Vec<AssemblyIdentity>,
+}
+
+impl Manifest {
+    /// Only `requireAdministrator` refuses to run unelevated;
+    /// `highestAvailable` runs as the invoking user when it cannot elevate.
+    pub fn requires_admin(&self) -> bool {
+        self.execution_level.as_deref() == Some("requireAdministrator")
+    }
+
+    /// Names of the declared `supportedOS` entries; unknown GUIDs are
+    /// listed as-is.
+    pub fn supported_os_names(&self) -> Vec<&str> {
+        self.supported_os
+            .iter()
+            .map(|guid| {
+                SUPPORTED_OS
+                    .iter()
+                    .find(|(id, _)| id == guid)
+                    .map_or(guid.as_str(), |(_, name)| name)
+            })
+            .collect()
+    }
+
+    /// Version the version-reporting APIs return: Windows 8 unless the
+    /// app declares support for 8.1 or 10, as on Windows 8.1 and later.
+    pub fn reported_version(&self) -> OsVersion {
+        let declares = |name: &str| self.supported_os_names().contains(&name);
+        if declares("Windows 10/11") {
+            OsVersion::WIN10
+        } else if declares("Windows 8.1") {
+            OsVersion::WIN81
+        } else {
+            OsVersion::WIN8
+        }
+    }
+
+    /// `<dpiAwareness>` wins over `<dpiAware>`; its first recognized
+    /// entry applies.
+    pub fn dpi_mode(&self) -> DpiAwareness {
+        if let Some(list) = &self.dpi_awareness {
+            for entry in list.split(',').map(|e| e.trim().to_ascii_lowercase()) {
+                match entry.as_str() {
+                    "permonitorv2" => return DpiAwareness::PerMonitorV2,
+                    "permonitor" => return DpiAwareness::PerMonitor,
+                    "system" => return DpiAwareness::System,
+                    "unaware" => return DpiAwareness::Unaware,
+                    _ => {}
+                }
+            }
+        }
+        match self.dpi_aware.as_deref().map(str::to_ascii_lowercase) {
+            Some(value) if value.ends_with("/pm") || value == "per monitor" => {
+                DpiAwareness::PerMonitor
+            }
+            Some(value) if value == "true" => DpiAwareness::System,
+            _ => DpiAwareness::Unaware,
+        }
+    }
+
+    pub fn app_compat(&self) -> AppCompat {
+        AppCompat {
+            version: self.reported_version(),
+            dpi_awareness: self.dpi_mode(),
+            common_controls_v6: self
+                .dependencies
+                .iter()
+                .any(AssemblyIdentity::is_common_controls_v6),
+        }
+    }
+}
+
+/// The embedded application manifest: RT_MANIFEST 1 for executables
+/// (2 is the isolation-aware DLL manifest), falling back to the first
+/// manifest present.
+pub fn load_manifest(bytes: &[u8], pe: &PeContext) -> Option<Manifest> {
+    let resources = parse_resources(bytes, pe)?;
+    let mut manifests = resources.iter().filter(|res| res.kind.is(RT_MANIFEST));
+    let manifest = manifests
+        .clone()
+        .find(|res| res.name == ResourceId::Id(1))
+        .or_else(|| manifests.next())?;
+    Some(parse_manifest(&manifest_text(&manifest.data)))
+}
+
+/// Reads the manifest XML. This is a tag scanner, not a validating
+/// parser: namespace prefixes are dropped and unknown elements ignored,
+/// which is also how the Windows side-by-side loader treats extensions.
+pub fn parse_manifest(xml: &str) -> Manifest {
+    let mut manifest = Manifest::default();
+    let mut open: Vec<String> = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.split_once("-->").map_or("", |(_, after)| after);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = local_name(name.trim());
+            if let Some(pos) = open.iter().rposition(|open| open == name) {
+                open.truncate(pos);
+            }
+            continue;
+        }
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let (name, attrs) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        let name = local_name(name);
+        let attr = |key: &str| attribute(attrs, key);
+        let text = rest.split('<').next().unwrap_or_default().trim();
+
+        match name {
+            "requestedExecutionLevel" => {
+                manifest.execution_level = attr("level");
+                manifest.ui_access = attr("uiAccess").is_some_and(|v| v == "true");
+            }
+            "supportedOS" => {
+                if let Some(id) = attr("Id") {
+                    manifest.supported_os.push(id.to_ascii_lowercase());
+                }
+            }
+            "dpiAware" if !self_closing => manifest.dpi_aware = Some(text.to_string()),
+            "dpiAwareness" if !self_closing => manifest.dpi_awareness = Some(text.to_string()),
+            "assemblyIdentity" if open.iter().any(|open| open == "dependentAssembly") => {
+                manifest.dependencies.push(AssemblyIdentity {
+                    name: attr("name").unwrap_or_default(),
+                    version: attr("version").unwrap_or_default(),
+                    architecture: attr("processorArchitecture").unwrap_or_default(),
+                    public_key_token: attr("publicKeyToken").unwrap_or_default(),
+                });
+            }
+            _ => {}
+        }
+        if !self_closing {
+            open.push(name.to_string());
+        }
+    }
+    manifest
+}
+
+/// `asmv3:application` -> `application`.
+fn local_name(name: &str) -> &str {
+    name.rsplit_once(':').map_or(name, |(_, local)| local)
+}
+
+/// Value of attribute `key` (namespace prefix ignored) in a start tag's
+/// attribute text.
+fn attribute(attrs: &str, key: &str) -> Option<String> {
+    let mut rest = attrs;
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq].trim();
+        let value = rest[eq + 1..].trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let (value, after) = value[1..].split_once(quote)?;
+        if local_name(name) == key {
+            return Some(value.to_string());
+        }
+        rest = after;
+    }
+    None
+}
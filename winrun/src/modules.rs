@@ -0,0 +1,186 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::debugdir::CodeView;
+use crate::exports::{split_forwarder, ExportDirectory, ExportTarget, ForwardedSymbol};
+use crate::imports::ImportEntry;
+use crate::loader::LoadedImage;
+use crate::pdb::find_pdb;
+
+/// Longest forwarder chain followed before giving up.
+const MAX_FORWARDER_HOPS: usize = 16;
+
+/// Where a DLL named by an import table is served from.
+#[derive(Clone)]
+pub enum DllLocation {
+    /// A system module implemented by waygate.
+    Builtin,
+    /// A file shipped beside the target executable.
+    AppLocal(PathBuf),
+    Missing,
+}
+
+/// DLL search order: waygate's built-in system modules first (like
+/// KnownDLLs, they are never taken from disk), then the directory holding
+/// the target. Names are matched case-insensitively, as on Windows.
+pub fn search_dll(app_dir: &Path, dll: &str) -> DllLocation {
+    if waygate::is_builtin_module(dll) {
+        return DllLocation::Builtin;
+    }
+    find_file(app_dir, dll).map_or(DllLocation::Missing, DllLocation::AppLocal)
+}
+
+/// The file in `dir` called `name`, compared case-insensitively.
+pub fn find_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.is_file()
+                && path
+                    .file_name()
+                    .and_then(|file| file.to_str())
+                    .is_some_and(|file| file.eq_ignore_ascii_case(name))
+        })
+}
+
+/// How the loader fills one IAT slot.
+pub enum Binding {
+    /// An address inside a loaded app-local DLL.
+    Address(u64),
+    /// A waygate thunk for `module!symbol`. Differs from the import itself
+    /// when the export was forwarded; unresolvable targets end up on the
+    /// missing-import trap under this name.
+    Thunk { module: String, symbol: String },
+}
+
+struct ModuleExports {
+    name: String,
+    base: u64,
+    directory: ExportDirectory,
+}
+
+/// App-local DLLs loaded for one target, with their export tables.
+///
+/// Exports are registered as soon as a DLL is mapped, before its own
+/// imports are bound, so import cycles between app-local DLLs resolve.
+/// The executable joins the set once it is attached. Dropping the set sends
+/// DLL_PROCESS_DETACH before the images unmap.
+pub struct ModuleSet {
+    app_dir: PathBuf,
+    /// Extra directory searched for PDBs (`--symbols`).
+    symbol_dir: Option<PathBuf>,
+    exports: Vec<ModuleExports>,
+    images: Vec<LoadedImage>,
+}
+
+impl ModuleSet {
+    pub fn new(app_dir: PathBuf, symbol_dir: Option<PathBuf>) -> Self {
+        Self {
+            app_dir,
+            symbol_dir,
+            exports: Vec::new(),
+            images: Vec::new(),
+        }
+    }
+
+    pub fn search(&self, dll: &str) -> DllLocation {
+        search_dll(&self.app_dir, dll)
+    }
+
+    /// PDB for an image loaded from the application directory.
+    pub fn find_pdb(&self, codeview: &CodeView) -> Option<PathBuf> {
+        find_pdb(&self.app_dir, self.symbol_dir.as_deref(), codeview)
+    }
+
+    /// True once `dll` has been mapped (it may still be mid-load).
+    pub fn is_registered(&self, dll: &str) -> bool {
+        self.find(dll).is_some()
+    }
+
+    pub fn register_exports(&mut self, dll: &str, base: u64, directory: ExportDirectory) {
+        self.exports.push(ModuleExports {
+            name: dll.to_string(),
+            base,
+            directory,
+        });
+    }
+
+    /// Keeps a fully loaded image mapped for the life of the set.
+    pub fn add_image(&mut self, image: LoadedImage) {
+        self.images.push(image);
+    }
+
+    /// Binding for `entry` imported from `dll`, or `None` when `dll` is not
+    /// an app-local module and the import goes to waygate as written.
+    pub fn resolve(&self, dll: &str, entry: &ImportEntry) -> Option<Binding> {
+        let module = self.find(dll)?;
+        let export = match (&entry.name, entry.ordinal) {
+            (Some(name), _) => module.directory.by_name(name),
+            (None, Some(ordinal)) => module.directory.by_ordinal(ordinal),
+            (None, None) => None,
+        };
+        let unresolved = || Binding::Thunk {
+            module: dll.to_string(),
+            symbol: match (&entry.name, entry.ordinal) {
+                (Some(name), _) => name.clone(),
+                (None, ordinal) => format!("#{}", ordinal.unwrap_or(0)),
+            },
+        };
+        Some(match export.map(|export| &export.target) {
+            Some(ExportTarget::Rva(rva)) => Binding::Address(module.base + *rva as u64),
+            Some(ExportTarget::Forwarder(forwarder)) => {
+                self.resolve_forwarder(forwarder).unwrap_or_else(unresolved)
+            }
+            None => unresolved(),
+        })
+    }
+
+    /// Follows a forwarder chain through app-local DLLs until it reaches
+    /// code, or a module that is not app-local (bound to waygate under the
+    /// final forwarded name, API set contracts included). `None` for
+    /// malformed forwarders, missing targets, cycles and overlong chains,
+    /// which the caller sends to the missing-import trap.
+    fn resolve_forwarder(&self, forwarder: &str) -> Option<Binding> {
+        let mut forwarder = forwarder.to_string();
+        for _ in 0..MAX_FORWARDER_HOPS {
+            let (target, symbol) = split_forwarder(&forwarder)?;
+            let Some(module) = self.find(&target) else {
+                let symbol = match symbol {
+                    ForwardedSymbol::Name(name) => name,
+                    ForwardedSymbol::Ordinal(ordinal) => waygate::ordinal_name(&target, ordinal)
+                        .map_or_else(|| format!("#{ordinal}"), str::to_string),
+                };
+                return Some(Binding::Thunk {
+                    module: target,
+                    symbol,
+                });
+            };
+            let export = match &symbol {
+                ForwardedSymbol::Name(name) => module.directory.by_name(name),
+                ForwardedSymbol::Ordinal(ordinal) => module.directory.by_ordinal(*ordinal),
+            };
+            match export.map(|export| &export.target) {
+                Some(ExportTarget::Rva(rva)) => {
+                    return Some(Binding::Address(module.base + *rva as u64))
+                }
+                Some(ExportTarget::Forwarder(next)) => forwarder = next.clone(),
+                None => return None,
+            }
+        }
+        None
+    }
+
+    fn find(&self, dll: &str) -> Option<&ModuleExports> {
+        self.exports
+            .iter()
+            .find(|module| module.name.eq_ignore_ascii_case(dll))
+    }
+}
+
+impl Drop for ModuleSet {
+    fn drop(&mut self) {
+        waygate::detach_modules();
+    }
+}
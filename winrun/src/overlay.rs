@@ -0,0 +1,140 @@
+use std::fmt;
+
+use crate::pe::{PeContext, IMAGE_DIRECTORY_ENTRY_SECURITY};
+
+/// NSIS `firstheader`: flags, then `0xDEADBEEF` and `NullsoftInst`.
+const NSIS_SIGNATURE: &[u8] = b"\xEF\xBE\xAD\xDENullsoftInst";
+/// NSIS looks for its header at 512-byte file offsets.
+const NSIS_ALIGNMENT: usize = 512;
+/// Inno Setup's SetupLdr offset table, in the loader's resources.
+const INNO_OFFSET_TABLE: &[u8] = b"rDlPtS";
+/// Inno Setup before 5.1.5 stored the offset table at this file offset.
+const INNO_LEGACY_OFFSET: usize = 0x30;
+const INNO_LEGACY_MAGIC: &[u8] = b"Inno";
+const INNO_SETUP_DATA: &[u8] = b"Inno Setup Setup Data (";
+const SEVEN_ZIP_SIGNATURE: &[u8] = b"7z\xBC\xAF\x27\x1C";
+const ZIP_LOCAL_HEADER: &[u8] = b"PK\x03\x04";
+const ZIP_END_OF_DIRECTORY: &[u8] = b"PK\x05\x06";
+/// End-of-central-directory record plus the longest archive comment.
+const ZIP_TAIL_SEARCH: usize = 22 + 0xFFFF;
+
+/// Installer or self-extractor whose payload sits in the overlay.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Installer {
+    Nsis,
+    InnoSetup { version: Option<String> },
+    SevenZipSfx,
+    Zip,
+}
+
+impl fmt::Display for Installer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Installer::Nsis => f.write_str("NSIS installer"),
+            Installer::InnoSetup {
+                version: Some(version),
+            } => write!(f, "Inno Setup {version} installer"),
+            Installer::InnoSetup { version: None } => f.write_str("Inno Setup installer"),
+            Installer::SevenZipSfx => f.write_str("7-Zip self-extracting archive"),
+            Installer::Zip => f.write_str("ZIP self-extracting archive"),
+        }
+    }
+}
+
+/// Data appended after the last section, which the loader never maps.
+pub struct Overlay {
+    /// File offset of the first byte past the image.
+    pub start: usize,
+    /// End of the overlay; a trailing certificate table is not included.
+    pub end: usize,
+    /// Set when an Authenticode certificate table follows the overlay.
+    pub before_certificates: bool,
+    /// Recognized installer, with the file offset its payload starts at.
+    pub installer: Option<(Installer, usize)>,
+}
+
+impl Overlay {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn data<'a>(&self, bytes: &'a [u8]) -> &'a [u8] {
+        &bytes[self.start..self.end]
+    }
+}
+
+/// Finds the overlay, if the file extends past its image. A certificate
+/// table at the end of the file is signature data, not overlay, so an
+/// image followed only by its signature has none.
+pub fn find_overlay(bytes: &[u8], pe: &PeContext) -> Option<Overlay> {
+    let start = pe.image_end();
+    let mut end = bytes.len();
+    let mut before_certificates = false;
+    if let Some(dir) = pe.directory(IMAGE_DIRECTORY_ENTRY_SECURITY) {
+        let table = dir.rva as usize;
+        if table >= start && table < end && table + dir.size as usize >= end {
+            end = table;
+            before_certificates = true;
+        }
+    }
+    // Signing pads the file to 8 bytes before appending the table.
+    let padding = before_certificates
+        && end.saturating_sub(start) < 8
+        && bytes[start.min(end)..end].iter().all(|b| *b == 0);
+    if start >= end || padding {
+        return None;
+    }
+    let mut overlay = Overlay {
+        start,
+        end,
+        before_certificates,
+        installer: None,
+    };
+    overlay.installer = identify_installer(bytes, &overlay);
+    Some(overlay)
+}
+
+fn identify_installer(bytes: &[u8], overlay: &Overlay) -> Option<(Installer, usize)> {
+    let data = overlay.data(bytes);
+    let at = |offset: usize| overlay.start + offset;
+
+    let first_aligned = overlay.start.next_multiple_of(NSIS_ALIGNMENT) - overlay.start;
+    let nsis = (first_aligned..data.len())
+        .step_by(NSIS_ALIGNMENT)
+        .find(|offset| data[*offset..].get(4..4 + NSIS_SIGNATURE.len()) == Some(NSIS_SIGNATURE));
+    if let Some(offset) = nsis {
+        return Some((Installer::Nsis, at(offset)));
+    }
+
+    let legacy_inno =
+        bytes.get(INNO_LEGACY_OFFSET..INNO_LEGACY_OFFSET + 4) == Some(INNO_LEGACY_MAGIC);
+    if legacy_inno || find(&bytes[..overlay.start], INNO_OFFSET_TABLE).is_some() {
+        let setup_data = find(data, INNO_SETUP_DATA);
+        let version = setup_data.and_then(|offset| {
+            let rest = &data[offset + INNO_SETUP_DATA.len()..];
+            let close = rest.iter().take(32).position(|b| *b == b')')?;
+            std::str::from_utf8(&rest[..close]).ok().map(str::to_string)
+        });
+        return Some((
+            Installer::InnoSetup { version },
+            at(setup_data.unwrap_or(0)),
+        ));
+    }
+
+    if let Some(offset) = find(data, SEVEN_ZIP_SIGNATURE) {
+        return Some((Installer::SevenZipSfx, at(offset)));
+    }
+
+    let tail = data.len().saturating_sub(ZIP_TAIL_SEARCH);
+    if find(&data[tail..], ZIP_END_OF_DIRECTORY).is_some() {
+        let first_entry = find(data, ZIP_LOCAL_HEADER).unwrap_or(0);
+        return Some((Installer::Zip, at(first_entry)));
+    }
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
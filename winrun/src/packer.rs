@@ -0,0 +1,158 @@
+use crate::imports::parse_import_modules;
+use crate::pe::PeContext;
+use crate::upx::find_pack_header;
+
+/// Section names packers leave behind, matched case-sensitively.
+const PACKER_SECTIONS: &[(&str, &str)] = &[
+    ("UPX0", "UPX"),
+    ("UPX1", "UPX"),
+    ("UPX2", "UPX"),
+    (".aspack", "ASPack"),
+    (".adata", "ASPack"),
+    (".MPRESS1", "MPRESS"),
+    (".MPRESS2", "MPRESS"),
+    (".petite", "Petite"),
+    ("PEC2", "PECompact"),
+    ("pec1", "PECompact"),
+    ("PEC2TO", "PECompact"),
+    (".nsp0", "NsPack"),
+    (".nsp1", "NsPack"),
+    (".themida", "Themida"),
+    (".winlice", "WinLicense"),
+    (".vmp0", "VMProtect"),
+    (".vmp1", "VMProtect"),
+    (".enigma1", "Enigma Protector"),
+    (".enigma2", "Enigma Protector"),
+    ("FSG!", "FSG"),
+    ("MEW", "MEW"),
+];
+
+/// Compressed or encrypted data sits near the 8 bits/byte maximum; native
+/// code and tables rarely pass 6.5.
+pub const HIGH_ENTROPY: f64 = 7.2;
+
+/// Sections smaller than this say too little for entropy to mean much.
+const ENTROPY_MIN_SIZE: usize = 512;
+
+/// Functions an unpacking stub needs to rebuild the real import table.
+const LOADER_IMPORTS: &[&str] = &[
+    "LoadLibraryA",
+    "LoadLibraryW",
+    "LoadLibraryExA",
+    "LoadLibraryExW",
+    "GetProcAddress",
+    "GetModuleHandleA",
+    "GetModuleHandleW",
+    "VirtualAlloc",
+    "VirtualFree",
+    "VirtualProtect",
+    "ExitProcess",
+];
+
+pub struct SectionEntropy {
+    pub name: String,
+    pub entropy: f64,
+}
+
+/// Evidence that an image is packed.
+pub struct PackerReport {
+    /// Packer named by its section names, with the sections that matched.
+    pub signature: Option<(&'static str, Vec<String>)>,
+    /// A valid `UPX!` pack header, which survives section renaming.
+    pub upx_header: bool,
+    /// Entropy of every section with raw data.
+    pub sections: Vec<SectionEntropy>,
+    /// The import table holds only what an unpacking stub needs.
+    pub loader_only_imports: bool,
+}
+
+impl PackerReport {
+    pub fn high_entropy(&self) -> impl Iterator<Item = &SectionEntropy> {
+        self.sections
+            .iter()
+            .filter(|section| section.entropy >= HIGH_ENTROPY)
+    }
+
+    /// Names the packer from its section names or pack header; without
+    /// either, compressed-looking sections behind a stub-sized import
+    /// table still mark the image as packed by something unknown.
+    pub fn packer_name(&self) -> Option<&'static str> {
+        match &self.signature {
+            Some((name, _)) => Some(name),
+            None if self.upx_header => Some("UPX"),
+            None if self.loader_only_imports && self.high_entropy().count() > 0 => {
+                Some("unknown packer")
+            }
+            None => None,
+        }
+    }
+}
+
+pub fn detect_packer(bytes: &[u8], pe: &PeContext) -> PackerReport {
+    let mut signature: Option<(&'static str, Vec<String>)> = None;
+    for section in &pe.sections {
+        let Some((_, packer)) = PACKER_SECTIONS
+            .iter()
+            .find(|(name, _)| *name == section.name)
+        else {
+            continue;
+        };
+        match &mut signature {
+            Some((found, names)) if found == packer => names.push(section.name.clone()),
+            Some(_) => {}
+            None => signature = Some((packer, vec![section.name.clone()])),
+        }
+    }
+
+    let sections = pe
+        .sections
+        .iter()
+        .filter(|section| section.raw_size >= ENTROPY_MIN_SIZE)
+        .filter_map(|section| {
+            let end = (section.raw_ptr + section.raw_size).min(bytes.len());
+            let data = bytes.get(section.raw_ptr..end)?;
+            Some(SectionEntropy {
+                name: section.name.clone(),
+                entropy: shannon_entropy(data),
+            })
+        })
+        .collect();
+
+    let imported: Vec<String> = parse_import_modules(bytes, pe)
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|module| module.entries)
+        .filter_map(|entry| entry.name)
+        .collect();
+    let loader_only_imports = imported.iter().any(|name| name == "GetProcAddress")
+        && imported
+            .iter()
+            .all(|name| LOADER_IMPORTS.contains(&name.as_str()));
+
+    PackerReport {
+        signature,
+        upx_header: find_pack_header(bytes).is_some(),
+        sections,
+        loader_only_imports,
+    }
+}
+
+/// Shannon entropy in bits per byte, from 0 (constant) to 8 (uniform).
+pub fn shannon_entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 256];
+    for byte in data {
+        counts[*byte as usize] += 1;
+    }
+    let total = data.len() as f64;
+    counts
+        .iter()
+        .filter(|count| **count != 0)
+        .map(|count| {
+            let p = *count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
@@ -0,0 +1,474 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::debugdir::{CodeView, Guid};
+use crate::modules::find_file;
+use crate::pe::{read_c_string, read_u16, read_u32, PeContext};
+
+const MSF_MAGIC: &[u8] = b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0";
+
+/// Fixed stream indices.
+const PDB_INFO_STREAM: usize = 1;
+const DBI_STREAM: usize = 3;
+
+/// Stream index meaning "no stream".
+const NIL_STREAM: u16 = 0xFFFF;
+
+const DBI_HEADER_SIZE: usize = 64;
+/// ModInfo record size up to the module name.
+const MODULE_INFO_SIZE: usize = 64;
+
+const S_PUB32: u16 = 0x110E;
+const S_LPROC32: u16 = 0x110F;
+const S_GPROC32: u16 = 0x1110;
+const S_LPROC32_ID: u16 = 0x1146;
+const S_GPROC32_ID: u16 = 0x1147;
+
+const DEBUG_S_LINES: u32 = 0xF2;
+const DEBUG_S_FILECHKSMS: u32 = 0xF4;
+/// `CV_LINES_HAVE_COLUMNS` in a lines subsection header.
+const CV_LINES_HAVE_COLUMNS: u16 = 0x0001;
+
+/// The multi-stream file container a PDB is stored in.
+struct Msf<'a> {
+    bytes: &'a [u8],
+    block_size: usize,
+    /// Per stream, its byte size and blocks; `None` for nil streams.
+    streams: Vec<Option<(usize, Vec<u32>)>>,
+}
+
+impl<'a> Msf<'a> {
+    fn parse(bytes: &'a [u8]) -> Option<Self> {
+        if !bytes.starts_with(MSF_MAGIC) {
+            return None;
+        }
+        let block_size = read_u32(bytes, 32)? as usize;
+        let directory_size = read_u32(bytes, 44)? as usize;
+        let block_map = read_u32(bytes, 52)? as usize;
+        if !matches!(block_size, 512 | 1024 | 2048 | 4096) {
+            return None;
+        }
+
+        let mut directory = Vec::with_capacity(directory_size);
+        let map = block_map.checked_mul(block_size)?;
+        for idx in 0..directory_size.div_ceil(block_size) {
+            let block = read_u32(bytes, map + idx * 4)? as usize;
+            let start = block.checked_mul(block_size)?;
+            directory.extend_from_slice(bytes.get(start..start + block_size)?);
+        }
+        directory.truncate(directory_size);
+
+        let count = read_u32(&directory, 0)? as usize;
+        let mut sizes = Vec::with_capacity(count.min(0x1_0000));
+        for idx in 0..count {
+            sizes.push(read_u32(&directory, 4 + idx * 4)?);
+        }
+        let mut offset = 4 + count * 4;
+        let mut streams = Vec::with_capacity(sizes.len());
+        for size in sizes {
+            if size == u32::MAX {
+                streams.push(None);
+                continue;
+            }
+            let size = size as usize;
+            let mut blocks = Vec::new();
+            for _ in 0..size.div_ceil(block_size) {
+                blocks.push(read_u32(&directory, offset)?);
+                offset += 4;
+            }
+            streams.push(Some((size, blocks)));
+        }
+        Some(Self {
+            bytes,
+            block_size,
+            streams,
+        })
+    }
+
+    fn stream(&self, index: usize) -> Option<Vec<u8>> {
+        let (size, blocks) = self.streams.get(index)?.as_ref()?;
+        let mut data = Vec::with_capacity(*size);
+        for block in blocks {
+            let start = (*block as usize).checked_mul(self.block_size)?;
+            data.extend_from_slice(self.bytes.get(start..start + self.block_size)?);
+        }
+        data.truncate(*size);
+        Some(data)
+    }
+}
+
+struct Function {
+    rva: u32,
+    size: u32,
+    name: String,
+}
+
+struct Line {
+    rva: u32,
+    file: usize,
+    line: u32,
+}
+
+/// What an address resolved to.
+pub struct SymbolHit<'a> {
+    pub name: &'a str,
+    pub offset: u32,
+    /// Source file and line, when the PDB has line tables.
+    pub line: Option<(&'a str, u32)>,
+}
+
+/// Functions, publics and line tables from one PDB, keyed by RVA.
+pub struct PdbSymbols {
+    functions: Vec<Function>,
+    publics: Vec<(u32, String)>,
+    lines: Vec<Line>,
+    files: Vec<String>,
+}
+
+impl PdbSymbols {
+    pub fn function_count(&self) -> usize {
+        self.functions.len()
+    }
+
+    pub fn public_count(&self) -> usize {
+        self.publics.len()
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Innermost procedure containing `rva`, falling back to the closest
+    /// preceding public symbol; line info comes from the line tables.
+    pub fn lookup(&self, rva: u32) -> Option<SymbolHit<'_>> {
+        let function = self
+            .functions
+            .partition_point(|function| function.rva <= rva)
+            .checked_sub(1)
+            .map(|idx| &self.functions[idx])
+            .filter(|function| rva < function.rva.saturating_add(function.size.max(1)));
+        let (start, name, end) = match function {
+            Some(function) => (
+                function.rva,
+                function.name.as_str(),
+                function.rva.saturating_add(function.size),
+            ),
+            None => {
+                let idx = self.publics.partition_point(|(start, _)| *start <= rva);
+                let (start, name) = self.publics.get(idx.checked_sub(1)?)?;
+                (*start, name.as_str(), u32::MAX)
+            }
+        };
+        let line = self
+            .lines
+            .partition_point(|line| line.rva <= rva)
+            .checked_sub(1)
+            .map(|idx| &self.lines[idx])
+            .filter(|line| line.rva >= start && line.rva < end)
+            .map(|line| (self.files[line.file].as_str(), line.line));
+        Some(SymbolHit {
+            name,
+            offset: rva - start,
+            line,
+        })
+    }
+}
+
+/// A loaded image's address range with its PDB symbols, if any were found.
+pub struct ImageSymbols {
+    pub name: String,
+    pub base: u64,
+    pub size: usize,
+    pub pdb: Option<PdbSymbols>,
+}
+
+impl ImageSymbols {
+    /// Describes a return address inside the image as
+    /// `app.exe!WinMain+0x42 (main.c:17)`, or `app.exe+0x1042` without
+    /// symbols. The lookup uses the byte before the return address so a
+    /// call that ends its function still names its caller and line.
+    pub fn describe(&self, address: u64) -> Option<String> {
+        let rva = address
+            .checked_sub(self.base)
+            .filter(|rva| *rva < self.size as u64)? as u32;
+        let Some(hit) = self
+            .pdb
+            .as_ref()
+            .and_then(|pdb| pdb.lookup(rva.saturating_sub(1)))
+        else {
+            return Some(format!("{}+{rva:#x}", self.name));
+        };
+        let mut text = format!("{}!{}+{:#x}", self.name, hit.name, hit.offset + 1);
+        if let Some((file, line)) = hit.line {
+            let file = file.rsplit(['\\', '/']).next().unwrap_or(file);
+            text.push_str(&format!(" ({file}:{line})"));
+        }
+        Some(text)
+    }
+}
+
+/// Finds the PDB for an image: beside the binary under the file name the
+/// CodeView record gives, then in `symbol_dir` either flat or in the
+/// symbol server layout (`name.pdb/GUIDAGE/name.pdb`).
+pub fn find_pdb(
+    image_dir: &Path,
+    symbol_dir: Option<&Path>,
+    codeview: &CodeView,
+) -> Option<PathBuf> {
+    let path = codeview.path();
+    let name = path.rsplit(['\\', '/']).next().unwrap_or(path);
+    if let Some(found) = find_file(image_dir, name) {
+        return Some(found);
+    }
+    let dir = symbol_dir?;
+    find_file(dir, name).or_else(|| find_file(&dir.join(name).join(codeview.symbol_key()), name))
+}
+
+/// Reads `path` and checks it belongs to the image described by
+/// `codeview` before loading its symbols.
+pub fn load_pdb(path: &Path, pe: &PeContext, codeview: &CodeView) -> Result<PdbSymbols, String> {
+    let bytes = fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let msf =
+        Msf::parse(&bytes).ok_or_else(|| format!("{}: not an MSF 7.0 PDB", path.display()))?;
+    let info = msf
+        .stream(PDB_INFO_STREAM)
+        .ok_or_else(|| format!("{}: missing PDB info stream", path.display()))?;
+    let dbi = msf
+        .stream(DBI_STREAM)
+        .filter(|dbi| dbi.len() >= DBI_HEADER_SIZE)
+        .ok_or_else(|| format!("{}: missing DBI stream", path.display()))?;
+
+    // The DBI age is the one the linker bumps; the info stream's may lag.
+    let CodeView::Rsds { guid, age, .. } = codeview else {
+        return Err(format!(
+            "{}: NB10 (PDB 2.0) symbols are not supported",
+            path.display()
+        ));
+    };
+    let pdb_guid = info
+        .get(12..28)
+        .and_then(|raw| raw.try_into().ok())
+        .map(Guid);
+    if pdb_guid != Some(*guid) || read_u32(&dbi, 8) != Some(*age) {
+        return Err(format!(
+            "{}: PDB does not match the image (wanted {})",
+            path.display(),
+            codeview.symbol_key()
+        ));
+    }
+
+    let names = names_stream_index(&info)
+        .and_then(|index| msf.stream(index))
+        .unwrap_or_default();
+    parse_symbols(&msf, &dbi, &names, pe)
+        .ok_or_else(|| format!("{}: malformed DBI stream", path.display()))
+}
+
+/// Looks up `/names` in the info stream's named stream map: a string
+/// buffer followed by a serialized hash table of (name offset, stream).
+fn names_stream_index(info: &[u8]) -> Option<usize> {
+    let buffer_size = read_u32(info, 28)? as usize;
+    let buffer = info.get(32..32 + buffer_size)?;
+    let mut offset = 32 + buffer_size;
+    let _size = read_u32(info, offset)?;
+    let capacity = read_u32(info, offset + 4)? as usize;
+    offset += 8;
+    let present_words = read_u32(info, offset)? as usize;
+    let present: Vec<u32> = (0..present_words)
+        .map(|idx| read_u32(info, offset + 4 + idx * 4))
+        .collect::<Option<_>>()?;
+    offset += 4 + present_words * 4;
+    let deleted_words = read_u32(info, offset)? as usize;
+    offset += 4 + deleted_words * 4;
+    for bucket in 0..capacity {
+        let is_present = present
+            .get(bucket / 32)
+            .is_some_and(|word| word & (1 << (bucket % 32)) != 0);
+        if !is_present {
+            continue;
+        }
+        let name_offset = read_u32(info, offset)? as usize;
+        let stream = read_u32(info, offset + 4)? as usize;
+        offset += 8;
+        if read_c_string(buffer, name_offset).as_deref() == Some("/names") {
+            return Some(stream);
+        }
+    }
+    None
+}
+
+/// Entry of the `/names` string table at `offset`.
+fn names_entry(names: &[u8], offset: u32) -> Option<String> {
+    read_c_string(names, 12 + offset as usize)
+}
+
+fn parse_symbols(msf: &Msf, dbi: &[u8], names: &[u8], pe: &PeContext) -> Option<PdbSymbols> {
+    let section_rva = |segment: u16, offset: u32| -> Option<u32> {
+        let section = pe.sections.get((segment as usize).checked_sub(1)?)?;
+        (section.virtual_address as u32).checked_add(offset)
+    };
+
+    let mut symbols = PdbSymbols {
+        functions: Vec::new(),
+        publics: Vec::new(),
+        lines: Vec::new(),
+        files: Vec::new(),
+    };
+
+    let records_stream = read_u16(dbi, 20)?;
+    if records_stream != NIL_STREAM {
+        let records = msf.stream(records_stream as usize).unwrap_or_default();
+        for_each_record(&records, 0, |kind, record| {
+            if kind == S_PUB32 {
+                let offset = read_u32(record, 8)?;
+                let segment = read_u16(record, 12)?;
+                let name = read_c_string(record, 14)?;
+                symbols.publics.push((section_rva(segment, offset)?, name));
+            }
+            Some(())
+        });
+    }
+
+    let modules_size = read_u32(dbi, 24)? as usize;
+    let modules = dbi.get(DBI_HEADER_SIZE..DBI_HEADER_SIZE + modules_size)?;
+    let mut offset = 0;
+    while offset + MODULE_INFO_SIZE <= modules.len() {
+        let stream = read_u16(modules, offset + 34)?;
+        let symbols_size = read_u32(modules, offset + 36)? as usize;
+        let c11_size = read_u32(modules, offset + 40)? as usize;
+        let c13_size = read_u32(modules, offset + 44)? as usize;
+        let module_name = read_c_string(modules, offset + MODULE_INFO_SIZE)?;
+        let object_name =
+            read_c_string(modules, offset + MODULE_INFO_SIZE + module_name.len() + 1)?;
+        offset = (offset + MODULE_INFO_SIZE + module_name.len() + object_name.len() + 2 + 3) & !3;
+        if stream == NIL_STREAM {
+            continue;
+        }
+        let Some(data) = msf.stream(stream as usize) else {
+            continue;
+        };
+
+        // Procedure records follow a 4-byte signature.
+        let records = data.get(..symbols_size.min(data.len())).unwrap_or_default();
+        for_each_record(records, 4, |kind, record| {
+            if matches!(kind, S_GPROC32 | S_LPROC32 | S_GPROC32_ID | S_LPROC32_ID) {
+                let size = read_u32(record, 16)?;
+                let offset = read_u32(record, 32)?;
+                let segment = read_u16(record, 36)?;
+                let name = read_c_string(record, 39)?;
+                symbols.functions.push(Function {
+                    rva: section_rva(segment, offset)?,
+                    size,
+                    name,
+                });
+            }
+            Some(())
+        });
+
+        let c13_start = symbols_size + c11_size;
+        if let Some(c13) = data.get(c13_start..c13_start + c13_size) {
+            parse_c13_lines(c13, names, &section_rva, &mut symbols);
+        }
+    }
+
+    symbols.functions.sort_by_key(|function| function.rva);
+    symbols.publics.sort_by_key(|(rva, _)| *rva);
+    symbols.lines.sort_by_key(|line| line.rva);
+    Some(symbols)
+}
+
+/// Walks CodeView records (`u16 length, u16 kind, ...`) starting at
+/// `start`. `visit` gets the kind and the record including its header.
+fn for_each_record(data: &[u8], start: usize, mut visit: impl FnMut(u16, &[u8]) -> Option<()>) {
+    let mut offset = start;
+    while let (Some(len), Some(kind)) = (read_u16(data, offset), read_u16(data, offset + 2)) {
+        let end = offset + 2 + len as usize;
+        let Some(record) = data.get(offset..end) else {
+            break;
+        };
+        let _ = visit(kind, record);
+        if len < 2 {
+            break;
+        }
+        offset = end;
+    }
+}
+
+/// Reads a module's C13 debug subsections: file checksums name the
+/// source files, line subsections map code offsets to line numbers.
+fn parse_c13_lines(
+    c13: &[u8],
+    names: &[u8],
+    section_rva: &impl Fn(u16, u32) -> Option<u32>,
+    symbols: &mut PdbSymbols,
+) {
+    let mut subsections = Vec::new();
+    let mut offset = 0;
+    while let (Some(kind), Some(len)) = (read_u32(c13, offset), read_u32(c13, offset + 4)) {
+        let start = offset + 8;
+        let Some(data) = c13.get(start..start + len as usize) else {
+            break;
+        };
+        subsections.push((kind, data));
+        offset = (start + len as usize + 3) & !3;
+    }
+    let checksums = subsections
+        .iter()
+        .find(|(kind, _)| *kind == DEBUG_S_FILECHKSMS)
+        .map(|(_, data)| *data)
+        .unwrap_or_default();
+
+    let mut file_index = |checksum_offset: u32| -> Option<usize> {
+        let name_offset = read_u32(checksums, checksum_offset as usize)?;
+        let name = names_entry(names, name_offset)?;
+        Some(match symbols.files.iter().position(|file| *file == name) {
+            Some(idx) => idx,
+            None => {
+                symbols.files.push(name);
+                symbols.files.len() - 1
+            }
+        })
+    };
+
+    let mut lines = Vec::new();
+    for (_, data) in subsections
+        .iter()
+        .filter(|(kind, _)| *kind == DEBUG_S_LINES)
+    {
+        let Some(base) = read_u32(data, 0).zip(read_u16(data, 4)) else {
+            continue;
+        };
+        let Some(base) = section_rva(base.1, base.0) else {
+            continue;
+        };
+        let has_columns = read_u16(data, 6).is_some_and(|flags| flags & CV_LINES_HAVE_COLUMNS != 0);
+        let mut offset = 12;
+        while let (Some(checksum), Some(count), Some(block_size)) = (
+            read_u32(data, offset),
+            read_u32(data, offset + 4),
+            read_u32(data, offset + 8),
+        ) {
+            let Some(file) = file_index(checksum) else {
+                break;
+            };
+            for idx in 0..count as usize {
+                let entry = offset + 12 + idx * 8;
+                let (Some(code), Some(flags)) = (read_u32(data, entry), read_u32(data, entry + 4))
+                else {
+                    break;
+                };
+                let Some(rva) = base.checked_add(code) else {
+                    continue;
+                };
+                lines.push(Line {
+                    rva,
+                    file,
+                    line: flags & 0x00FF_FFFF,
+                });
+            }
+            let minimum = 12 + count as usize * if has_columns { 12 } else { 8 };
+            offset += (block_size as usize).max(minimum);
+        }
+    }
+    symbols.lines.extend(lines);
+}
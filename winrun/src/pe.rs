@@ -0,0 +1,442 @@
+use std::fmt;
+
+pub const IMAGE_DIRECTORY_ENTRY_EXPORT: usize = 0;
+pub const IMAGE_DIRECTORY_ENTRY_RESOURCE: usize = 2;
+pub const IMAGE_DIRECTORY_ENTRY_EXCEPTION: usize = 3;
+pub const IMAGE_DIRECTORY_ENTRY_SECURITY: usize = 4;
+pub const IMAGE_DIRECTORY_ENTRY_BASERELOC: usize = 5;
+pub const IMAGE_DIRECTORY_ENTRY_DEBUG: usize = 6;
+pub const IMAGE_DIRECTORY_ENTRY_TLS: usize = 9;
+pub const IMAGE_DIRECTORY_ENTRY_LOAD_CONFIG: usize = 10;
+pub const IMAGE_DIRECTORY_ENTRY_DELAY_IMPORT: usize = 13;
+pub const IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR: usize = 14;
+
+pub const IMAGE_FILE_RELOCS_STRIPPED: u16 = 0x0001;
+pub const IMAGE_FILE_DLL: u16 = 0x2000;
+
+pub const IMAGE_SCN_CNT_CODE: u32 = 0x0000_0020;
+pub const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x0000_0040;
+pub const IMAGE_SCN_CNT_UNINITIALIZED_DATA: u32 = 0x0000_0080;
+pub const IMAGE_SCN_MEM_DISCARDABLE: u32 = 0x0200_0000;
+pub const IMAGE_SCN_MEM_SHARED: u32 = 0x1000_0000;
+pub const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+pub const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+pub const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+/// Offset of `CHPEMetadataPointer` in IMAGE_LOAD_CONFIG_DIRECTORY64.
+const LOAD_CONFIG64_CHPE_METADATA: usize = 0xC8;
+
+/// Longest TLS callback array read before giving up on a missing NULL.
+const MAX_TLS_CALLBACKS: usize = 256;
+
+/// COFF `Machine`, with hybrid ARM64 images told apart.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Machine {
+    I386,
+    Amd64,
+    Arm64,
+    /// AMD64 header plus ARM64 hybrid (CHPE) metadata: x64-compatible
+    /// code emitted by an ARM64 compiler.
+    Arm64ec,
+    /// ARM64 header plus hybrid metadata: one image serving ARM64 and
+    /// ARM64EC processes.
+    Arm64x,
+    Arm,
+    Ia64,
+    Other(u16),
+}
+
+impl Machine {
+    fn from_header(machine: u16, hybrid: bool) -> Self {
+        match (machine, hybrid) {
+            (0x014C, _) => Machine::I386,
+            (0x8664, false) => Machine::Amd64,
+            (0x8664, true) | (0xA641, _) => Machine::Arm64ec,
+            (0xAA64, false) => Machine::Arm64,
+            (0xAA64, true) => Machine::Arm64x,
+            (0x01C0 | 0x01C2 | 0x01C4, _) => Machine::Arm,
+            (0x0200, _) => Machine::Ia64,
+            (other, _) => Machine::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for Machine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Machine::I386 => f.write_str("i386"),
+            Machine::Amd64 => f.write_str("AMD64"),
+            Machine::Arm64 => f.write_str("ARM64"),
+            Machine::Arm64ec => f.write_str("ARM64EC"),
+            Machine::Arm64x => f.write_str("ARM64X"),
+            Machine::Arm => f.write_str("ARM"),
+            Machine::Ia64 => f.write_str("IA-64"),
+            Machine::Other(value) => write!(f, "unknown machine {value:#06x}"),
+        }
+    }
+}
+
+/// Optional header `Subsystem`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Subsystem {
+    Native,
+    WindowsGui,
+    WindowsConsole,
+    Posix,
+    NativeWindows,
+    WindowsCeGui,
+    EfiApplication,
+    EfiBootServiceDriver,
+    EfiRuntimeDriver,
+    EfiRom,
+    Xbox,
+    BootApplication,
+    Other(u16),
+}
+
+impl Subsystem {
+    fn from_header(value: u16) -> Self {
+        match value {
+            1 => Subsystem::Native,
+            2 => Subsystem::WindowsGui,
+            3 => Subsystem::WindowsConsole,
+            7 => Subsystem::Posix,
+            8 => Subsystem::NativeWindows,
+            9 => Subsystem::WindowsCeGui,
+            10 => Subsystem::EfiApplication,
+            11 => Subsystem::EfiBootServiceDriver,
+            12 => Subsystem::EfiRuntimeDriver,
+            13 => Subsystem::EfiRom,
+            14 => Subsystem::Xbox,
+            16 => Subsystem::BootApplication,
+            other => Subsystem::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Subsystem::Native => f.write_str("native (kernel driver / NT native)"),
+            Subsystem::WindowsGui => f.write_str("Windows GUI"),
+            Subsystem::WindowsConsole => f.write_str("Windows console"),
+            Subsystem::Posix => f.write_str("POSIX"),
+            Subsystem::NativeWindows => f.write_str("native Win9x driver"),
+            Subsystem::WindowsCeGui => f.write_str("Windows CE GUI"),
+            Subsystem::EfiApplication => f.write_str("EFI application"),
+            Subsystem::EfiBootServiceDriver => f.write_str("EFI boot service driver"),
+            Subsystem::EfiRuntimeDriver => f.write_str("EFI runtime driver"),
+            Subsystem::EfiRom => f.write_str("EFI ROM"),
+            Subsystem::Xbox => f.write_str("Xbox"),
+            Subsystem::BootApplication => f.write_str("Windows boot application"),
+            Subsystem::Other(value) => write!(f, "unknown subsystem {value}"),
+        }
+    }
+}
+
+pub struct PeSection {
+    pub name: String,
+    pub virtual_address: usize,
+    pub virtual_size: usize,
+    pub mapped_size: usize,
+    pub raw_ptr: usize,
+    pub raw_size: usize,
+    pub characteristics: u32,
+}
+
+/// IMAGE_TLS_DIRECTORY (data directory 9). Addresses are VAs at the
+/// preferred `ImageBase`, as the image stores them.
+pub struct TlsDirectory {
+    /// Static TLS template: the initialized bytes copied into every
+    /// thread's block, followed by `zero_fill` zero bytes.
+    pub raw_data_start: u64,
+    pub raw_data_end: u64,
+    /// Where the loader writes the module's TLS index (`_tls_index`).
+    pub index_address: u64,
+    pub callbacks_address: u64,
+    pub zero_fill: u32,
+    pub characteristics: u32,
+    /// The NULL-terminated callback array as it is in the file. Code can
+    /// still add entries at run time if the array is writable.
+    pub callbacks: Vec<u64>,
+}
+
+impl TlsDirectory {
+    pub fn template_size(&self) -> usize {
+        self.raw_data_end.saturating_sub(self.raw_data_start) as usize
+    }
+
+    /// Alignment of each thread's block, from the IMAGE_SCN_ALIGN_* bits.
+    pub fn alignment(&self) -> usize {
+        match (self.characteristics >> 20) & 0xF {
+            0 => 1,
+            bits => 1 << (bits - 1),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DataDirectory {
+    pub rva: u32,
+    pub size: u32,
+}
+
+pub struct PeContext {
+    pub is_pe64: bool,
+    pub machine: Machine,
+    pub subsystem: Subsystem,
+    /// COFF header TimeDateStamp: seconds since 1970, or hash bits for
+    /// reproducible builds.
+    pub time_date_stamp: u32,
+    pub characteristics: u16,
+    pub import_rva: u32,
+    pub image_base: u64,
+    pub entry_point: u32,
+    pub size_of_image: u32,
+    pub size_of_headers: u32,
+    /// File offset of the optional header's CheckSum field.
+    pub checksum_offset: usize,
+    /// File offset of the data directory table.
+    pub data_directory_offset: usize,
+    pub data_directories: Vec<DataDirectory>,
+    pub sections: Vec<PeSection>,
+    pub tls: Option<TlsDirectory>,
+    /// End of the COFF symbol and string tables, 0 when there are none.
+    symbols_end: usize,
+}
+
+impl PeContext {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 0x40 || !bytes.starts_with(b"MZ") {
+            return None;
+        }
+
+        let pe_offset = read_u32(bytes, 0x3C)? as usize;
+        if pe_offset.checked_add(0x18)? > bytes.len() {
+            return None;
+        }
+        if bytes.get(pe_offset..pe_offset + 4)? != b"PE\0\0" {
+            return None;
+        }
+
+        let section_count = read_u16(bytes, pe_offset + 6)? as usize;
+        let optional_size = read_u16(bytes, pe_offset + 20)? as usize;
+        let optional_header_offset = pe_offset + 24;
+        let magic = read_u16(bytes, optional_header_offset)?;
+        let (is_pe64, data_directory_offset) = match magic {
+            0x10B => (false, optional_header_offset + 96),
+            0x20B => (true, optional_header_offset + 112),
+            _ => return None,
+        };
+        let characteristics = read_u16(bytes, pe_offset + 22)?;
+        let raw_machine = read_u16(bytes, pe_offset + 4)?;
+        let time_date_stamp = read_u32(bytes, pe_offset + 8)?;
+        let symbol_table = read_u32(bytes, pe_offset + 12)? as usize;
+        let symbol_count = read_u32(bytes, pe_offset + 16)? as usize;
+        // Image files only carry these when a MinGW linker keeps them; the
+        // string table's u32 size (itself included) follows the symbols.
+        let symbols_end = match symbol_table {
+            0 => 0,
+            start => {
+                let strings = start + symbol_count * 18;
+                strings + read_u32(bytes, strings).map_or(0, |size| size as usize)
+            }
+        };
+        let subsystem = Subsystem::from_header(read_u16(bytes, optional_header_offset + 68)?);
+
+        let image_base = if is_pe64 {
+            read_u64(bytes, optional_header_offset + 24)?
+        } else {
+            read_u32(bytes, optional_header_offset + 28)? as u64
+        };
+        let entry_point = read_u32(bytes, optional_header_offset + 16)?;
+        let size_of_image = read_u32(bytes, optional_header_offset + 56)?;
+        let size_of_headers = read_u32(bytes, optional_header_offset + 60)?;
+
+        let import_rva = read_u32(bytes, data_directory_offset + 8)?;
+        let directory_count = read_u32(bytes, data_directory_offset - 4)?.min(16) as usize;
+        let mut data_directories = Vec::with_capacity(directory_count);
+        for i in 0..directory_count {
+            data_directories.push(DataDirectory {
+                rva: read_u32(bytes, data_directory_offset + i * 8)?,
+                size: read_u32(bytes, data_directory_offset + i * 8 + 4)?,
+            });
+        }
+        let section_table = pe_offset + 24 + optional_size;
+        let mut sections = Vec::with_capacity(section_count);
+        for i in 0..section_count {
+            let sec = section_table + i * 40;
+            let raw_name = bytes.get(sec..sec + 8)?;
+            let name_len = raw_name.iter().position(|b| *b == 0).unwrap_or(8);
+            let name = String::from_utf8_lossy(&raw_name[..name_len]).into_owned();
+            let virtual_size = read_u32(bytes, sec + 8)? as usize;
+            let virtual_address = read_u32(bytes, sec + 12)? as usize;
+            let raw_size = read_u32(bytes, sec + 16)? as usize;
+            let raw_ptr = read_u32(bytes, sec + 20)? as usize;
+            let characteristics = read_u32(bytes, sec + 36)?;
+            sections.push(PeSection {
+                name,
+                virtual_address,
+                virtual_size,
+                mapped_size: virtual_size.max(raw_size),
+                raw_ptr,
+                raw_size,
+                characteristics,
+            });
+        }
+
+        let mut pe = Self {
+            is_pe64,
+            machine: Machine::Other(raw_machine),
+            subsystem,
+            time_date_stamp,
+            characteristics,
+            import_rva,
+            image_base,
+            entry_point,
+            size_of_image,
+            size_of_headers,
+            checksum_offset: optional_header_offset + 64,
+            data_directory_offset,
+            data_directories,
+            sections,
+            tls: None,
+            symbols_end,
+        };
+        pe.machine = Machine::from_header(raw_machine, pe.has_hybrid_metadata(bytes));
+        pe.tls = pe.parse_tls(bytes);
+        Some(pe)
+    }
+
+    fn parse_tls(&self, bytes: &[u8]) -> Option<TlsDirectory> {
+        let dir = self.directory(IMAGE_DIRECTORY_ENTRY_TLS)?;
+        let base = self.rva_to_offset(dir.rva as usize)?;
+        // The four address fields are pointer-sized.
+        let width = if self.is_pe64 { 8 } else { 4 };
+        let read_va = |offset| {
+            if self.is_pe64 {
+                read_u64(bytes, offset)
+            } else {
+                read_u32(bytes, offset).map(u64::from)
+            }
+        };
+        let mut tls = TlsDirectory {
+            raw_data_start: read_va(base)?,
+            raw_data_end: read_va(base + width)?,
+            index_address: read_va(base + 2 * width)?,
+            callbacks_address: read_va(base + 3 * width)?,
+            zero_fill: read_u32(bytes, base + 4 * width)?,
+            characteristics: read_u32(bytes, base + 4 * width + 4)?,
+            callbacks: Vec::new(),
+        };
+        let array = tls
+            .callbacks_address
+            .checked_sub(self.image_base)
+            .and_then(|rva| self.rva_to_offset(rva as usize));
+        if let Some(array) = array {
+            for idx in 0..MAX_TLS_CALLBACKS {
+                match read_va(array + idx * width) {
+                    Some(0) | None => break,
+                    Some(callback) => tls.callbacks.push(callback),
+                }
+            }
+        }
+        Some(tls)
+    }
+
+    /// True when the load config points at ARM64 hybrid (CHPE) metadata,
+    /// which marks ARM64EC and ARM64X images.
+    fn has_hybrid_metadata(&self, bytes: &[u8]) -> bool {
+        let Some(dir) = self.directory(IMAGE_DIRECTORY_ENTRY_LOAD_CONFIG) else {
+            return false;
+        };
+        if !self.is_pe64 {
+            return false;
+        }
+        let Some(base) = self.rva_to_offset(dir.rva as usize) else {
+            return false;
+        };
+        let size = read_u32(bytes, base).unwrap_or(0) as usize;
+        size >= LOAD_CONFIG64_CHPE_METADATA + 8
+            && read_u64(bytes, base + LOAD_CONFIG64_CHPE_METADATA).is_some_and(|ptr| ptr != 0)
+    }
+
+    /// Returns the data directory at `index`, or `None` when the image does
+    /// not declare it or leaves it empty.
+    pub fn directory(&self, index: usize) -> Option<DataDirectory> {
+        self.data_directories
+            .get(index)
+            .copied()
+            .filter(|dir| dir.rva != 0 && dir.size != 0)
+    }
+
+    /// File offset where the image ends: the end of the last section's
+    /// raw data or COFF symbol table, or of the headers for an image
+    /// without either. Anything after it is overlay data.
+    pub fn image_end(&self) -> usize {
+        self.sections
+            .iter()
+            .filter(|section| section.raw_size != 0)
+            .map(|section| section.raw_ptr + section.raw_size)
+            .fold(self.size_of_headers as usize, usize::max)
+            .max(self.symbols_end)
+    }
+
+    pub fn rva_to_offset(&self, rva: usize) -> Option<usize> {
+        for section in &self.sections {
+            if rva >= section.virtual_address && rva < section.virtual_address + section.mapped_size
+            {
+                return section.raw_ptr.checked_add(rva - section.virtual_address);
+            }
+        }
+        None
+    }
+}
+
+pub fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+pub fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+pub fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+pub fn read_c_string(bytes: &[u8], offset: usize) -> Option<String> {
+    let slice = bytes.get(offset..)?;
+    let end = slice.iter().position(|b| *b == 0)?;
+    std::str::from_utf8(&slice[..end])
+        .ok()
+        .map(|s| s.to_string())
+}
+
+/// `YYYY-MM-DD HH:MM:SS UTC` for a PE timestamp (seconds since 1970).
+pub fn format_timestamp(seconds: u32) -> String {
+    let days = (seconds / 86_400) as i64;
+    let time = seconds % 86_400;
+    // Days-to-civil conversion, proleptic Gregorian calendar.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
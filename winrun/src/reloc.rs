@@ -0,0 +1,117 @@
+use crate::pe::{read_u16, read_u32, PeContext, IMAGE_DIRECTORY_ENTRY_BASERELOC};
+
+pub const IMAGE_REL_BASED_ABSOLUTE: u8 = 0;
+pub const IMAGE_REL_BASED_HIGH: u8 = 1;
+pub const IMAGE_REL_BASED_LOW: u8 = 2;
+pub const IMAGE_REL_BASED_HIGHLOW: u8 = 3;
+pub const IMAGE_REL_BASED_HIGHADJ: u8 = 4;
+pub const IMAGE_REL_BASED_DIR64: u8 = 10;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Relocation {
+    pub kind: u8,
+    pub rva: usize,
+    /// Second slot consumed by `HIGHADJ`, holding the low 16 bits of the
+    /// full 32-bit value being adjusted.
+    pub extra: u16,
+}
+
+/// Parses `.reloc` (IMAGE_DIRECTORY_ENTRY_BASERELOC) into a flat list of
+/// fixups. Returns an empty list when the image has no relocation directory.
+pub fn parse_base_relocations(bytes: &[u8], pe: &PeContext) -> Option<Vec<Relocation>> {
+    let Some(dir) = pe.directory(IMAGE_DIRECTORY_ENTRY_BASERELOC) else {
+        return Some(Vec::new());
+    };
+
+    let mut relocations = Vec::new();
+    let end = dir.rva as usize + dir.size as usize;
+    let mut block_rva = dir.rva as usize;
+    while block_rva + 8 <= end {
+        let block = pe.rva_to_offset(block_rva)?;
+        let page_rva = read_u32(bytes, block)? as usize;
+        let block_size = read_u32(bytes, block + 4)? as usize;
+        if block_size < 8 {
+            break;
+        }
+
+        let count = (block_size - 8) / 2;
+        let mut i = 0usize;
+        while i < count {
+            let entry = read_u16(bytes, block + 8 + i * 2)?;
+            let kind = (entry >> 12) as u8;
+            let mut extra = 0;
+            if kind == IMAGE_REL_BASED_HIGHADJ {
+                i += 1;
+                extra = read_u16(bytes, block + 8 + i * 2)?;
+            }
+            relocations.push(Relocation {
+                kind,
+                rva: page_rva + (entry & 0x0FFF) as usize,
+                extra,
+            });
+            i += 1;
+        }
+
+        block_rva += block_size;
+    }
+
+    Some(relocations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testpe::{TestPe, IMAGE_SCN_DATA, IMAGE_SCN_RDATA};
+
+    /// One `.reloc` block: its page RVA followed by its entries.
+    fn block(page: u32, entries: &[u16]) -> Vec<u8> {
+        let mut out = page.to_le_bytes().to_vec();
+        out.extend_from_slice(&(8 + 2 * entries.len() as u32).to_le_bytes());
+        for entry in entries {
+            out.extend_from_slice(&entry.to_le_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn blocks_flatten_into_fixups() {
+        let mut table = block(0x2000, &[0xA000, 0x3008, 0x200C, 0x400E, 0x2010, 0x0000]);
+        table.extend(block(0x5000, &[0x1FFE, 0x3ABC]));
+        let bytes = TestPe::new(true)
+            .section(".data", 0x2000, &[0; 0x10], IMAGE_SCN_DATA)
+            .section(".reloc", 0x3000, &table, IMAGE_SCN_RDATA)
+            .directory(IMAGE_DIRECTORY_ENTRY_BASERELOC, 0x3000, table.len() as u32)
+            .build();
+        let pe = PeContext::parse(&bytes).expect("image parses");
+
+        let fixups: Vec<_> = parse_base_relocations(&bytes, &pe)
+            .expect("relocations parse")
+            .iter()
+            .map(|r| (r.kind, r.rva, r.extra))
+            .collect();
+        assert_eq!(
+            fixups,
+            [
+                (IMAGE_REL_BASED_DIR64, 0x2000, 0),
+                (IMAGE_REL_BASED_HIGHLOW, 0x2008, 0),
+                (IMAGE_REL_BASED_LOW, 0x200C, 0),
+                // HIGHADJ takes the next slot as the low half.
+                (IMAGE_REL_BASED_HIGHADJ, 0x200E, 0x2010),
+                (IMAGE_REL_BASED_ABSOLUTE, 0x2000, 0),
+                (IMAGE_REL_BASED_HIGH, 0x5FFE, 0),
+                (IMAGE_REL_BASED_HIGHLOW, 0x5ABC, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn images_without_a_directory_have_no_fixups() {
+        let bytes = TestPe::new(false)
+            .section(".data", 0x1000, &[0; 0x10], IMAGE_SCN_DATA)
+            .build();
+        let pe = PeContext::parse(&bytes).expect("image parses");
+        assert!(parse_base_relocations(&bytes, &pe)
+            .expect("no directory")
+            .is_empty());
+    }
+}
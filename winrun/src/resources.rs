@@ -0,0 +1,369 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Range;
+
+use crate::pe::{read_u16, read_u32, PeContext, IMAGE_DIRECTORY_ENTRY_RESOURCE};
+
+pub const RT_CURSOR: u16 = 1;
+pub const RT_BITMAP: u16 = 2;
+pub const RT_ICON: u16 = 3;
+pub const RT_MENU: u16 = 4;
+pub const RT_DIALOG: u16 = 5;
+pub const RT_STRING: u16 = 6;
+pub const RT_ACCELERATOR: u16 = 9;
+pub const RT_RCDATA: u16 = 10;
+pub const RT_MESSAGETABLE: u16 = 11;
+pub const RT_GROUP_CURSOR: u16 = 12;
+pub const RT_GROUP_ICON: u16 = 14;
+pub const RT_VERSION: u16 = 16;
+pub const RT_MANIFEST: u16 = 24;
+
+/// Signature of VS_FIXEDFILEINFO.
+const VS_FFI_SIGNATURE: u32 = 0xFEEF_04BD;
+
+/// Resource types and names are either integer IDs or UTF-16 strings.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ResourceId {
+    Id(u16),
+    Name(String),
+}
+
+impl ResourceId {
+    pub fn is(&self, id: u16) -> bool {
+        *self == ResourceId::Id(id)
+    }
+}
+
+impl fmt::Display for ResourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceId::Id(id) => write!(f, "{id}"),
+            ResourceId::Name(name) => write!(f, "\"{name}\""),
+        }
+    }
+}
+
+/// One leaf of the resource tree (type / name / language).
+#[derive(Clone, Debug)]
+pub struct Resource {
+    pub kind: ResourceId,
+    pub name: ResourceId,
+    pub language: u16,
+    pub code_page: u32,
+    pub data: Vec<u8>,
+}
+
+/// `RT_ICON`, `RT_VERSION`, ... for the predefined types, the raw ID or
+/// name otherwise.
+pub fn type_name(kind: &ResourceId) -> String {
+    let ResourceId::Id(id) = kind else {
+        return kind.to_string();
+    };
+    let name = match *id {
+        RT_CURSOR => "RT_CURSOR",
+        RT_BITMAP => "RT_BITMAP",
+        RT_ICON => "RT_ICON",
+        RT_MENU => "RT_MENU",
+        RT_DIALOG => "RT_DIALOG",
+        RT_STRING => "RT_STRING",
+        RT_ACCELERATOR => "RT_ACCELERATOR",
+        RT_RCDATA => "RT_RCDATA",
+        RT_MESSAGETABLE => "RT_MESSAGETABLE",
+        RT_GROUP_CURSOR => "RT_GROUP_CURSOR",
+        RT_GROUP_ICON => "RT_GROUP_ICON",
+        RT_VERSION => "RT_VERSION",
+        RT_MANIFEST => "RT_MANIFEST",
+        _ => return id.to_string(),
+    };
+    name.to_string()
+}
+
+enum NodeTarget {
+    /// Offset of a subdirectory, relative to the resource root.
+    Directory(usize),
+    /// Offset of an IMAGE_RESOURCE_DATA_ENTRY, relative to the root.
+    Data(usize),
+}
+
+/// Walks IMAGE_DIRECTORY_ENTRY_RESOURCE. The tree is always three levels
+/// deep (type, name, language); anything else is treated as malformed, as
+/// is a directory reached twice, which only cycles and shared subtrees do.
+/// Images without resources yield an empty list.
+pub fn parse_resources(bytes: &[u8], pe: &PeContext) -> Option<Vec<Resource>> {
+    let Some(dir) = pe.directory(IMAGE_DIRECTORY_ENTRY_RESOURCE) else {
+        return Some(Vec::new());
+    };
+    let root = pe.rva_to_offset(dir.rva as usize)?;
+    let mut resources = Vec::new();
+    let mut visited = HashSet::new();
+    for (kind, target) in read_directory(bytes, root, 0, &mut visited)? {
+        let NodeTarget::Directory(names) = target else {
+            return None;
+        };
+        for (name, target) in read_directory(bytes, root, names, &mut visited)? {
+            let NodeTarget::Directory(languages) = target else {
+                return None;
+            };
+            for (language, target) in read_directory(bytes, root, languages, &mut visited)? {
+                let (NodeTarget::Data(entry), ResourceId::Id(language)) = (target, language) else {
+                    return None;
+                };
+                let entry = root.checked_add(entry)?;
+                let data_rva = read_u32(bytes, entry)? as usize;
+                let size = read_u32(bytes, entry + 4)? as usize;
+                let code_page = read_u32(bytes, entry + 8)?;
+                let start = pe.rva_to_offset(data_rva)?;
+                let data = bytes.get(start..start.checked_add(size)?)?.to_vec();
+                resources.push(Resource {
+                    kind: kind.clone(),
+                    name: name.clone(),
+                    language,
+                    code_page,
+                    data,
+                });
+            }
+        }
+    }
+    Some(resources)
+}
+
+fn read_directory(
+    bytes: &[u8],
+    root: usize,
+    offset: usize,
+    visited: &mut HashSet<usize>,
+) -> Option<Vec<(ResourceId, NodeTarget)>> {
+    if !visited.insert(offset) {
+        return None;
+    }
+    let dir = root.checked_add(offset)?;
+    let count = read_u16(bytes, dir + 12)? as usize + read_u16(bytes, dir + 14)? as usize;
+    let mut nodes = Vec::with_capacity(count);
+    for idx in 0..count {
+        let entry = dir + 16 + idx * 8;
+        let name = read_u32(bytes, entry)?;
+        let target = read_u32(bytes, entry + 4)?;
+        let id = if name & 0x8000_0000 != 0 {
+            let name_offset = root.checked_add((name & 0x7FFF_FFFF) as usize)?;
+            let len = read_u16(bytes, name_offset)? as usize;
+            ResourceId::Name(read_wide(
+                bytes,
+                name_offset + 2..name_offset + 2 + len * 2,
+            )?)
+        } else {
+            ResourceId::Id(u16::try_from(name).ok()?)
+        };
+        let target_offset = (target & 0x7FFF_FFFF) as usize;
+        let target = if target & 0x8000_0000 != 0 {
+            NodeTarget::Directory(target_offset)
+        } else {
+            NodeTarget::Data(target_offset)
+        };
+        nodes.push((id, target));
+    }
+    Some(nodes)
+}
+
+fn read_wide(bytes: &[u8], range: Range<usize>) -> Option<String> {
+    let units: Vec<u16> = bytes
+        .get(range)?
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    Some(String::from_utf16_lossy(&units))
+}
+
+/// Decoded VS_VERSIONINFO.
+#[derive(Clone, Debug, Default)]
+pub struct VersionInfo {
+    /// `a.b.c.d` from VS_FIXEDFILEINFO.
+    pub file_version: Option<String>,
+    pub product_version: Option<String>,
+    /// StringFileInfo entries as `(table, key, value)`; the table key is the
+    /// language/code page pair, e.g. `040904b0`.
+    pub strings: Vec<(String, String, String)>,
+    /// `(language, code page)` pairs from VarFileInfo\Translation.
+    pub translations: Vec<(u16, u16)>,
+}
+
+impl VersionInfo {
+    /// First StringFileInfo value for `key`, across all tables.
+    pub fn string(&self, key: &str) -> Option<&str> {
+        self.strings
+            .iter()
+            .find(|(_, k, _)| k == key)
+            .map(|(_, _, value)| value.as_str())
+    }
+}
+
+/// One node of the VS_VERSIONINFO block tree. Ranges index the resource.
+struct VersionBlock {
+    key: String,
+    is_text: bool,
+    value: Range<usize>,
+    children: Range<usize>,
+    end: usize,
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn version_block(data: &[u8], start: usize, limit: usize) -> Option<VersionBlock> {
+    let length = read_u16(data, start)? as usize;
+    let value_length = read_u16(data, start + 2)? as usize;
+    let is_text = read_u16(data, start + 4)? == 1;
+    let end = start.checked_add(length)?;
+    if length < 6 || end > limit {
+        return None;
+    }
+    let mut key_end = start + 6;
+    while read_u16(data, key_end)? != 0 {
+        key_end += 2;
+        if key_end >= end {
+            return None;
+        }
+    }
+    let key = read_wide(data, start + 6..key_end)?;
+    let value_start = align4(key_end + 2).min(end);
+    let value_size = if is_text {
+        value_length * 2
+    } else {
+        value_length
+    };
+    let value_end = value_start.saturating_add(value_size).min(end);
+    Some(VersionBlock {
+        key,
+        is_text,
+        value: value_start..value_end,
+        children: align4(value_end).min(end)..end,
+        end,
+    })
+}
+
+fn child_blocks(data: &[u8], range: Range<usize>) -> Vec<VersionBlock> {
+    let mut blocks = Vec::new();
+    let mut offset = range.start;
+    while offset + 6 <= range.end {
+        let Some(block) = version_block(data, offset, range.end) else {
+            break;
+        };
+        offset = align4(block.end);
+        blocks.push(block);
+    }
+    blocks
+}
+
+fn fixed_version(ms: u32, ls: u32) -> String {
+    format!("{}.{}.{}.{}", ms >> 16, ms & 0xFFFF, ls >> 16, ls & 0xFFFF)
+}
+
+/// Decodes an RT_VERSION resource: the fixed file info plus the
+/// StringFileInfo and VarFileInfo children.
+pub fn parse_version_info(data: &[u8]) -> Option<VersionInfo> {
+    let root = version_block(data, 0, data.len())?;
+    if root.key != "VS_VERSION_INFO" {
+        return None;
+    }
+    let mut info = VersionInfo::default();
+    let fixed = data.get(root.value.clone()).unwrap_or_default();
+    if fixed.len() >= 52 && read_u32(fixed, 0) == Some(VS_FFI_SIGNATURE) {
+        info.file_version = Some(fixed_version(read_u32(fixed, 8)?, read_u32(fixed, 12)?));
+        info.product_version = Some(fixed_version(read_u32(fixed, 16)?, read_u32(fixed, 20)?));
+    }
+    for child in child_blocks(data, root.children) {
+        match child.key.as_str() {
+            "StringFileInfo" => {
+                for table in child_blocks(data, child.children) {
+                    for entry in child_blocks(data, table.children.clone()) {
+                        let value = if entry.is_text {
+                            read_wide(data, entry.value)?
+                        } else {
+                            String::from_utf8_lossy(data.get(entry.value)?).into_owned()
+                        };
+                        info.strings.push((
+                            table.key.clone(),
+                            entry.key,
+                            value.trim_end_matches('\0').to_string(),
+                        ));
+                    }
+                }
+            }
+            "VarFileInfo" => {
+                for var in child_blocks(data, child.children) {
+                    if var.key == "Translation" {
+                        for pair in data.get(var.value)?.chunks_exact(4) {
+                            info.translations.push((
+                                u16::from_le_bytes([pair[0], pair[1]]),
+                                u16::from_le_bytes([pair[2], pair[3]]),
+                            ));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(info)
+}
+
+/// RT_MANIFEST text. Manifests are normally UTF-8, occasionally UTF-16
+/// with a byte order mark.
+pub fn manifest_text(data: &[u8]) -> String {
+    let text = if let Some(wide) = data.strip_prefix(&[0xFF, 0xFE]) {
+        read_wide(wide, 0..wide.len() & !1).unwrap_or_default()
+    } else {
+        let data = data.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(data);
+        String::from_utf8_lossy(data).into_owned()
+    };
+    text.trim_end_matches(['\0', '\r', '\n', ' ']).to_string()
+}
+
+/// Strings of one RT_STRING block. Block `n` holds string IDs
+/// `(n - 1) * 16 ..= (n - 1) * 16 + 15`, each stored as a length-prefixed
+/// UTF-16 string; empty slots are skipped.
+pub fn parse_string_block(block: u16, data: &[u8]) -> Option<Vec<(u32, String)>> {
+    let first = (block.checked_sub(1)? as u32) * 16;
+    let mut strings = Vec::new();
+    let mut offset = 0;
+    for idx in 0..16 {
+        let len = read_u16(data, offset)? as usize;
+        let text = read_wide(data, offset + 2..offset + 2 + len * 2)?;
+        offset += 2 + len * 2;
+        if len > 0 {
+            strings.push((first + idx, text));
+        }
+    }
+    Some(strings)
+}
+
+/// One image of an RT_GROUP_ICON (GRPICONDIRENTRY).
+#[derive(Clone, Debug)]
+pub struct IconEntry {
+    pub width: u32,
+    pub height: u32,
+    pub bit_count: u16,
+    /// Name of the RT_ICON resource holding the image.
+    pub icon_id: u16,
+}
+
+/// Decodes an RT_GROUP_ICON directory. A stored width or height of 0
+/// means 256 pixels.
+pub fn parse_icon_group(data: &[u8]) -> Option<Vec<IconEntry>> {
+    if read_u16(data, 0)? != 0 || read_u16(data, 2)? != 1 {
+        return None;
+    }
+    let count = read_u16(data, 4)? as usize;
+    let mut entries = Vec::with_capacity(count);
+    for idx in 0..count {
+        let entry = 6 + idx * 14;
+        let dimension = |byte: u8| if byte == 0 { 256 } else { byte as u32 };
+        entries.push(IconEntry {
+            width: dimension(*data.get(entry)?),
+            height: dimension(*data.get(entry + 1)?),
+            bit_count: read_u16(data, entry + 6)?,
+            icon_id: read_u16(data, entry + 12)?,
+        });
+    }
+    Some(entries)
+}
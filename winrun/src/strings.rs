@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+
+use crate::pe::{
+    PeContext, IMAGE_DIRECTORY_ENTRY_EXPORT, IMAGE_DIRECTORY_ENTRY_RESOURCE,
+    IMAGE_SCN_CNT_UNINITIALIZED_DATA, IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_WRITE,
+};
+
+/// Shortest string worth reporting.
+const MIN_LENGTH: usize = 4;
+/// Longer runs are data, not names.
+const MAX_LENGTH: usize = 260;
+
+/// A NUL-terminated string found in the image's read-only data.
+pub struct DataString {
+    pub rva: u32,
+    pub text: String,
+}
+
+/// Finds printable ASCII and UTF-16LE strings in sections that are neither
+/// writable nor executable. The export and resource directories are left
+/// out, as are strings starting at an RVA in `skip` (the import table's own
+/// names, which say nothing about run-time lookups).
+pub fn read_only_strings(bytes: &[u8], pe: &PeContext, skip: &HashSet<usize>) -> Vec<DataString> {
+    let excluded: Vec<(usize, usize)> =
+        [IMAGE_DIRECTORY_ENTRY_EXPORT, IMAGE_DIRECTORY_ENTRY_RESOURCE]
+            .into_iter()
+            .filter_map(|index| pe.directory(index))
+            .map(|dir| (dir.rva as usize, dir.rva as usize + dir.size as usize))
+            .collect();
+    let mut strings = Vec::new();
+    for section in &pe.sections {
+        let flags = IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_WRITE | IMAGE_SCN_CNT_UNINITIALIZED_DATA;
+        if section.characteristics & flags != 0 {
+            continue;
+        }
+        let size = match section.virtual_size {
+            0 => section.raw_size,
+            size => size.min(section.raw_size),
+        };
+        let Some(data) = bytes.get(section.raw_ptr..(section.raw_ptr + size).min(bytes.len()))
+        else {
+            continue;
+        };
+        let found = ascii_strings(data).chain(wide_strings(data));
+        for (offset, text) in found {
+            let rva = section.virtual_address + offset;
+            let excluded = excluded
+                .iter()
+                .any(|(start, end)| (*start..*end).contains(&rva));
+            if !excluded && !skip.contains(&rva) {
+                strings.push(DataString {
+                    rva: rva as u32,
+                    text,
+                });
+            }
+        }
+    }
+    strings
+}
+
+fn printable(unit: u16) -> bool {
+    (0x20..0x7F).contains(&unit)
+}
+
+/// NUL-terminated runs of printable bytes, by offset.
+fn ascii_strings(data: &[u8]) -> impl Iterator<Item = (usize, String)> + '_ {
+    let mut start = 0;
+    data.iter().enumerate().filter_map(move |(i, byte)| {
+        if printable(*byte as u16) {
+            return None;
+        }
+        let run = start..i;
+        start = i + 1;
+        let length = run.len();
+        (*byte == 0 && (MIN_LENGTH..=MAX_LENGTH).contains(&length))
+            .then(|| (run.start, String::from_utf8_lossy(&data[run]).into_owned()))
+    })
+}
+
+/// NUL-terminated runs of printable UTF-16LE units at even offsets.
+fn wide_strings(data: &[u8]) -> impl Iterator<Item = (usize, String)> + '_ {
+    let mut start = 0;
+    data.chunks_exact(2)
+        .enumerate()
+        .filter_map(move |(i, pair)| {
+            let unit = u16::from_le_bytes([pair[0], pair[1]]);
+            if printable(unit) {
+                return None;
+            }
+            let mut run = start..i;
+            start = i + 1;
+            // An ANSI string's last character and NUL also read as a
+            // UTF-16 unit; it belongs to that string, not this one.
+            if run.start > 0 && printable(data[run.start * 2 - 1] as u16) {
+                run.start += 1;
+            }
+            let length = run.len();
+            (unit == 0 && (MIN_LENGTH..=MAX_LENGTH).contains(&length)).then(|| {
+                let text: String = data[run.start * 2..run.end * 2]
+                    .iter()
+                    .step_by(2)
+                    .map(|byte| *byte as char)
+                    .collect();
+                (run.start * 2, text)
+            })
+        })
+}
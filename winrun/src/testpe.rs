@@ -0,0 +1,145 @@
+// Minimal PE images for unit tests: headers, a section table and data
+// directories, with each section's raw data at a 0x200-aligned offset.
+
+pub const IMAGE_SCN_CODE: u32 = 0x6000_0020;
+pub const IMAGE_SCN_RDATA: u32 = 0x4000_0040;
+pub const IMAGE_SCN_DATA: u32 = 0xC000_0040;
+
+const PE_OFFSET: usize = 0x40;
+const FILE_ALIGNMENT: usize = 0x200;
+const SECTION_ALIGNMENT: usize = 0x1000;
+
+struct Section {
+    name: &'static str,
+    rva: u32,
+    data: Vec<u8>,
+    characteristics: u32,
+}
+
+pub struct TestPe {
+    is64: bool,
+    machine: u16,
+    subsystem: u16,
+    image_base: u64,
+    characteristics: u16,
+    sections: Vec<Section>,
+    directories: [(u32, u32); 16],
+}
+
+impl TestPe {
+    /// An AMD64 PE32+ or i386 PE32 console executable at the linkers'
+    /// default image base, with no sections yet.
+    pub fn new(is64: bool) -> Self {
+        Self {
+            is64,
+            machine: if is64 { 0x8664 } else { 0x14C },
+            subsystem: 3,
+            image_base: if is64 { 0x1_4000_0000 } else { 0x40_0000 },
+            characteristics: 0x0022,
+            sections: Vec::new(),
+            directories: [(0, 0); 16],
+        }
+    }
+
+    pub fn machine(mut self, machine: u16) -> Self {
+        self.machine = machine;
+        self
+    }
+
+    pub fn subsystem(mut self, subsystem: u16) -> Self {
+        self.subsystem = subsystem;
+        self
+    }
+
+    pub fn image_base(mut self, image_base: u64) -> Self {
+        self.image_base = image_base;
+        self
+    }
+
+    pub fn section(mut self, name: &'static str, rva: u32, data: &[u8], flags: u32) -> Self {
+        self.sections.push(Section {
+            name,
+            rva,
+            data: data.to_vec(),
+            characteristics: flags,
+        });
+        self
+    }
+
+    pub fn directory(mut self, index: usize, rva: u32, size: u32) -> Self {
+        self.directories[index] = (rva, size);
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let optional_size: u16 = if self.is64 { 240 } else { 224 };
+        let optional = PE_OFFSET + 24;
+        let table = optional + optional_size as usize;
+        let headers = (table + self.sections.len() * 40).next_multiple_of(FILE_ALIGNMENT);
+        let raw_sizes: Vec<usize> = self
+            .sections
+            .iter()
+            .map(|section| section.data.len().max(1).next_multiple_of(FILE_ALIGNMENT))
+            .collect();
+        let mut bytes = vec![0u8; headers + raw_sizes.iter().sum::<usize>()];
+        let mut put = |offset: usize, value: &[u8]| {
+            bytes[offset..offset + value.len()].copy_from_slice(value);
+        };
+
+        put(0, b"MZ");
+        put(0x3C, &(PE_OFFSET as u32).to_le_bytes());
+        put(PE_OFFSET, b"PE\0\0");
+        put(PE_OFFSET + 4, &self.machine.to_le_bytes());
+        put(PE_OFFSET + 6, &(self.sections.len() as u16).to_le_bytes());
+        put(PE_OFFSET + 20, &optional_size.to_le_bytes());
+        put(PE_OFFSET + 22, &self.characteristics.to_le_bytes());
+        put(
+            optional,
+            &(if self.is64 { 0x20Bu16 } else { 0x10B }).to_le_bytes(),
+        );
+        if self.is64 {
+            put(optional + 24, &self.image_base.to_le_bytes());
+        } else {
+            put(optional + 28, &(self.image_base as u32).to_le_bytes());
+        }
+        put(optional + 32, &(SECTION_ALIGNMENT as u32).to_le_bytes());
+        put(optional + 36, &(FILE_ALIGNMENT as u32).to_le_bytes());
+        let image_end = self
+            .sections
+            .iter()
+            .zip(&raw_sizes)
+            .map(|(section, raw)| section.rva as usize + raw)
+            .max()
+            .unwrap_or(headers);
+        let size_of_image = image_end.next_multiple_of(SECTION_ALIGNMENT) as u32;
+        put(optional + 56, &size_of_image.to_le_bytes());
+        put(optional + 60, &(headers as u32).to_le_bytes());
+        put(optional + 68, &self.subsystem.to_le_bytes());
+        let directories = optional + if self.is64 { 112 } else { 96 };
+        put(directories - 4, &16u32.to_le_bytes());
+        for (i, (rva, size)) in self.directories.iter().enumerate() {
+            put(directories + i * 8, &rva.to_le_bytes());
+            put(directories + i * 8 + 4, &size.to_le_bytes());
+        }
+
+        let mut raw_ptr = headers;
+        for (i, (section, raw)) in self.sections.iter().zip(&raw_sizes).enumerate() {
+            let header = table + i * 40;
+            let mut name = [0u8; 8];
+            name[..section.name.len()].copy_from_slice(section.name.as_bytes());
+            put(header, &name);
+            for (offset, value) in [
+                (8, *raw as u32),
+                (12, section.rva),
+                (16, *raw as u32),
+                (20, raw_ptr as u32),
+                (36, section.characteristics),
+            ] {
+                put(header + offset, &value.to_le_bytes());
+            }
+            put(raw_ptr, &section.data);
+            raw_ptr += raw;
+        }
+        bytes
+    }
+}
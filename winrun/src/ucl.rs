@@ -0,0 +1,123 @@
+/// UCL's NRV2B and NRV2E decompressors, in the `_le32` flavour UPX uses
+/// for PE images: control bits come MSB-first from little-endian 32-bit
+/// words interleaved with the literal bytes.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Nrv {
+    B,
+    E,
+}
+
+struct Bits<'a> {
+    input: &'a [u8],
+    pos: usize,
+    word: u32,
+    left: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn bit(&mut self) -> Option<usize> {
+        if self.left == 0 {
+            self.word =
+                u32::from_le_bytes(self.input.get(self.pos..self.pos + 4)?.try_into().ok()?);
+            self.pos += 4;
+            self.left = 32;
+        }
+        self.left -= 1;
+        Some(((self.word >> self.left) & 1) as usize)
+    }
+
+    fn byte(&mut self) -> Option<usize> {
+        let byte = *self.input.get(self.pos)?;
+        self.pos += 1;
+        Some(byte as usize)
+    }
+
+    /// Elias-gamma style number: a leading 1, then data bits each followed
+    /// by a stop bit.
+    fn gamma(&mut self) -> Option<usize> {
+        let mut value = 1usize;
+        loop {
+            value = value.checked_mul(2)? + self.bit()?;
+            if self.bit()? == 1 {
+                return Some(value);
+            }
+        }
+    }
+}
+
+/// Decompresses `input` into exactly `out_len` bytes, or returns `None`
+/// when the stream is corrupt or ends early.
+pub fn decompress(variant: Nrv, input: &[u8], out_len: usize) -> Option<Vec<u8>> {
+    let mut bits = Bits {
+        input,
+        pos: 0,
+        word: 0,
+        left: 0,
+    };
+    let mut out = Vec::with_capacity(out_len);
+    let mut last_offset = 1usize;
+    loop {
+        while bits.bit()? == 1 {
+            out.push(bits.byte()? as u8);
+        }
+
+        let (offset, mut len) = match variant {
+            Nrv::B => {
+                let high = bits.gamma()?;
+                let offset = if high == 2 {
+                    last_offset
+                } else {
+                    let offset = ((high - 3) << 8) | bits.byte()?;
+                    if offset == 0xFFFF_FFFF {
+                        break;
+                    }
+                    last_offset = offset + 1;
+                    last_offset
+                };
+                let mut len = bits.bit()? * 2 + bits.bit()?;
+                if len == 0 {
+                    len = bits.gamma()? + 2;
+                }
+                (offset, len + usize::from(offset > 0xD00))
+            }
+            Nrv::E => {
+                let mut high = 1usize;
+                loop {
+                    high = high.checked_mul(2)? + bits.bit()?;
+                    if bits.bit()? == 1 {
+                        break;
+                    }
+                    high = (high - 1).checked_mul(2)? + bits.bit()?;
+                }
+                let (offset, short) = if high == 2 {
+                    (last_offset, bits.bit()?)
+                } else {
+                    let raw = ((high - 3) << 8) | bits.byte()?;
+                    if raw == 0xFFFF_FFFF {
+                        break;
+                    }
+                    last_offset = (raw >> 1) + 1;
+                    (last_offset, (raw & 1) ^ 1)
+                };
+                let len = if short == 1 {
+                    1 + bits.bit()?
+                } else if bits.bit()? == 1 {
+                    3 + bits.bit()?
+                } else {
+                    bits.gamma()? + 3
+                };
+                (offset, len + usize::from(offset > 0x500))
+            }
+        };
+
+        let start = out.len().checked_sub(offset)?;
+        len += 1;
+        if out.len() + len > out_len {
+            return None;
+        }
+        for idx in 0..len {
+            out.push(out[start + idx]);
+        }
+    }
+    (out.len() == out_len).then_some(out)
+}
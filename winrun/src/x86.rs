@@ -0,0 +1,663 @@
+/// Length decoder for x86 and x86-64 machine code, with enough operand
+/// detail to recognize calls, moves and pushes. It does not name
+/// mnemonics; callers match on the opcode bytes.
+///
+/// Opcodes are numbered by map: one-byte opcodes as themselves,
+/// `0F xx` as `0x0Fxx`, `0F 38 xx` as `0x0F38xx` and `0F 3A xx` as
+/// `0x0F3Axx`. VEX and EVEX instructions use the map they select.
+#[derive(Copy, Clone, Debug)]
+pub struct Instruction {
+    pub len: usize,
+    pub opcode: u32,
+    /// REX prefix byte, 0 when absent (always 0 in 32-bit code).
+    pub rex: u8,
+    pub modrm: Option<ModRm>,
+    /// Immediate operand, sign-extended from its encoded size. Near
+    /// branches keep their displacement here.
+    pub imm: Option<i64>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ModRm {
+    pub mode: u8,
+    /// The `reg` field with REX.R applied: a register or an opcode
+    /// extension (`/2` for `FF /2`).
+    pub reg: u8,
+    /// The `rm` field with REX.B applied; names a register when `mode == 3`.
+    pub rm: u8,
+    /// The memory operand; `None` for a register (`mode == 3`) and for
+    /// 16-bit addressing, which is decoded for length only.
+    pub mem: Option<Memory>,
+}
+
+/// A memory operand `[base + index * scale + disp]`.
+#[derive(Copy, Clone, Debug)]
+pub struct Memory {
+    pub base: Option<u8>,
+    pub index: Option<(u8, u8)>,
+    pub disp: i64,
+    /// `[rip + disp]`, relative to the end of the instruction.
+    pub rip_relative: bool,
+}
+
+impl Instruction {
+    /// True for REX.W, a 64-bit operand.
+    pub fn wide(&self) -> bool {
+        self.rex & 0x08 != 0
+    }
+
+    /// Register operand of a ModRM instruction when `rm` is a register.
+    pub fn rm_register(&self) -> Option<u8> {
+        self.modrm
+            .filter(|modrm| modrm.mode == 3)
+            .map(|modrm| modrm.rm)
+    }
+
+    /// True when the instruction may write general register `reg`. Anything
+    /// not modeled counts as a write; calls count as none, since what they
+    /// clobber depends on the calling convention.
+    pub fn writes(&self, reg: u8) -> bool {
+        let op = self.opcode;
+        // Without REX, byte registers 4-7 are AH, CH, DH and BH.
+        let byte_op = matches!(op, 0x00..=0x3F if op & 1 == 0)
+            || matches!(
+                op,
+                0x80 | 0x82 | 0x86 | 0x88 | 0x8A | 0xB0..=0xB7 | 0xC0 | 0xC6 | 0xD0 | 0xD2 | 0xF6
+                    | 0xFE | 0x0F90..=0x0F9F
+            );
+        let names = |field: u8| {
+            field == reg
+                || (byte_op && self.rex == 0 && (4..8).contains(&field) && field - 4 == reg)
+        };
+        let in_opcode = (op as u8 & 7) | (self.rex & 0x01) << 3;
+        let modrm = self.modrm;
+        let extension = modrm.map(|modrm| modrm.reg & 7);
+        let reg_field = modrm.is_some_and(|modrm| names(modrm.reg));
+        let rm_field = self.rm_register().is_some_and(names);
+        match op {
+            0x58..=0x5F | 0xB0..=0xBF => names(in_opcode),
+            // xchg reg, eax swaps both.
+            0x91..=0x97 => names(in_opcode) || reg == 0,
+            // Branches, nops, breakpoints, compares, tests and pushes.
+            0x70..=0x7F | 0x0F80..=0x0F8F | 0x90 | 0xCC | 0x0F1F | 0xE8 => false,
+            0x38..=0x3D | 0x84 | 0x85 | 0xA8 | 0xA9 | 0x50..=0x57 | 0x68 | 0x6A => false,
+            0x80..=0x83 if extension == Some(7) => false,
+            0xF6 | 0xF7 if matches!(extension, Some(0 | 1)) => false,
+            0xFF if matches!(extension, Some(2 | 6)) => false,
+            // mul, imul, div and idiv write the accumulator and RDX.
+            0xF6 | 0xF7 if matches!(extension, Some(4..=7)) => matches!(reg, 0 | 2),
+            // ALU ops whose direction bit picks the destination.
+            0x00..=0x3F if op & 4 == 0 => {
+                if op & 2 != 0 {
+                    reg_field
+                } else {
+                    rm_field
+                }
+            }
+            0x80..=0x83
+            | 0x88
+            | 0x89
+            | 0xC0
+            | 0xC1
+            | 0xC6
+            | 0xC7
+            | 0xD0..=0xD3
+            | 0xF6
+            | 0xF7
+            | 0xFE
+            | 0xFF
+            | 0x0F90..=0x0F9F => rm_field,
+            0x63 | 0x69 | 0x6B | 0x8A | 0x8B | 0x8D => reg_field,
+            _ if modrm.is_some() => reg_field || rm_field,
+            // No ModRM: string ops, cdq, leave, syscall and friends write
+            // the legacy registers and R11.
+            _ => reg < 8 || reg == 11,
+        }
+    }
+
+    /// Absolute address of a memory operand that names one: `[rip + disp]`,
+    /// a bare `[disp32]` or the `moffs` of `mov eax, [addr]` and its
+    /// siblings. `address` is where the instruction starts.
+    pub fn memory_target(&self, address: u64) -> Option<u64> {
+        if matches!(self.opcode, 0xA0..=0xA3) {
+            return self.imm.map(|offset| offset as u64);
+        }
+        let mem = self.modrm?.mem?;
+        if mem.rip_relative {
+            return Some(
+                address
+                    .wrapping_add(self.len as u64)
+                    .wrapping_add(mem.disp as u64),
+            );
+        }
+        (mem.base.is_none() && mem.index.is_none()).then_some(mem.disp as u32 as u64)
+    }
+}
+
+/// Immediate size classes of the one-byte and two-byte maps.
+#[derive(Copy, Clone)]
+enum Imm {
+    None,
+    Byte,
+    Word,
+    /// 16 or 32 bits, by operand size.
+    Z,
+    /// `iw` followed by `ib` (ENTER).
+    WordByte,
+    /// `iz` followed by `iw` (far pointers).
+    ZWord,
+    /// 16, 32 or (with REX.W) 64 bits (`mov r, imm`).
+    V,
+    /// An address-sized offset (`mov al, [moffs]`).
+    Offset,
+}
+
+/// ModRM presence and immediate size for one-byte opcodes.
+fn one_byte(op: u8, is64: bool) -> Option<(bool, Imm)> {
+    Some(match op {
+        0x0F => return None,
+        0x00..=0x3F => match op & 7 {
+            0..=3 => (true, Imm::None),
+            4 => (false, Imm::Byte),
+            5 => (false, Imm::Z),
+            _ if is64 && matches!(op, 0x06 | 0x07 | 0x0E | 0x16 | 0x17 | 0x1E | 0x1F) => {
+                return None
+            }
+            _ if is64 && matches!(op, 0x27 | 0x2F | 0x37 | 0x3F) => return None,
+            _ => (false, Imm::None),
+        },
+        0x40..=0x61 => (false, Imm::None),
+        0x62 | 0x63 => (true, Imm::None),
+        0x68 => (false, Imm::Z),
+        0x69 => (true, Imm::Z),
+        0x6A => (false, Imm::Byte),
+        0x6B => (true, Imm::Byte),
+        0x6C..=0x6F => (false, Imm::None),
+        0x70..=0x7F => (false, Imm::Byte),
+        0x80 | 0x82 | 0x83 => (true, Imm::Byte),
+        0x81 => (true, Imm::Z),
+        0x84..=0x8F => (true, Imm::None),
+        0x9A if is64 => return None,
+        0x9A => (false, Imm::ZWord),
+        0x90..=0x9F => (false, Imm::None),
+        0xA0..=0xA3 => (false, Imm::Offset),
+        0xA8 => (false, Imm::Byte),
+        0xA9 => (false, Imm::Z),
+        0xA4..=0xAF => (false, Imm::None),
+        0xB0..=0xB7 => (false, Imm::Byte),
+        0xB8..=0xBF => (false, Imm::V),
+        0xC0 | 0xC1 | 0xC6 => (true, Imm::Byte),
+        0xC7 => (true, Imm::Z),
+        0xC2 | 0xCA => (false, Imm::Word),
+        0xC4 | 0xC5 => (true, Imm::None),
+        0xC8 => (false, Imm::WordByte),
+        0xCD | 0xD4 | 0xD5 => (false, Imm::Byte),
+        0xC3 | 0xC9 | 0xCB | 0xCC | 0xCE | 0xCF | 0xD6 | 0xD7 => (false, Imm::None),
+        0xD0..=0xD3 | 0xD8..=0xDF => (true, Imm::None),
+        0xE0..=0xE7 | 0xEB => (false, Imm::Byte),
+        0xE8 | 0xE9 => (false, Imm::Z),
+        0xEA if is64 => return None,
+        0xEA => (false, Imm::ZWord),
+        0xEC..=0xEF | 0xF1 | 0xF4 | 0xF5 | 0xF8..=0xFD => (false, Imm::None),
+        // F6/F7 take an immediate only for TEST (/0, /1); see `decode`.
+        0xF6 | 0xF7 | 0xFE | 0xFF => (true, Imm::None),
+        _ => return None,
+    })
+}
+
+/// ModRM presence and immediate size for `0F xx`.
+fn two_byte(op: u8) -> (bool, Imm) {
+    match op {
+        0x05..=0x09 | 0x0B | 0x0E | 0x30..=0x37 | 0x77 | 0xA0..=0xA2 | 0xA8..=0xAA => {
+            (false, Imm::None)
+        }
+        0xC8..=0xCF => (false, Imm::None),
+        0x80..=0x8F => (false, Imm::Z),
+        0x70..=0x73 | 0xA4 | 0xAC | 0xBA | 0xC2 | 0xC4..=0xC6 | 0x0F => (true, Imm::Byte),
+        _ => (true, Imm::None),
+    }
+}
+
+struct Cursor<'a> {
+    code: &'a [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn byte(&mut self) -> Option<u8> {
+        let byte = *self.code.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.code.get(self.pos).copied()
+    }
+
+    fn signed(&mut self, size: usize) -> Option<i64> {
+        let bytes = self.code.get(self.pos..self.pos + size)?;
+        self.pos += size;
+        let mut raw = [0u8; 8];
+        raw[..size].copy_from_slice(bytes);
+        let shift = 64 - 8 * size as u32;
+        Some((i64::from_le_bytes(raw) << shift) >> shift)
+    }
+}
+
+/// Decodes the instruction at the start of `code`, or `None` for an
+/// invalid or truncated encoding.
+pub fn decode(code: &[u8], is64: bool) -> Option<Instruction> {
+    let mut cur = Cursor { code, pos: 0 };
+    let mut operand_16 = false;
+    let mut address_16 = false;
+    let mut rex = 0u8;
+    let mut op = loop {
+        match cur.byte()? {
+            0x66 => operand_16 = true,
+            0x67 => address_16 = true,
+            0xF0 | 0xF2 | 0xF3 | 0x26 | 0x2E | 0x36 | 0x3E | 0x64 | 0x65 => {}
+            // A REX prefix only counts when it comes last.
+            byte if is64 && byte & 0xF0 == 0x40 => {
+                rex = byte;
+                continue;
+            }
+            byte => break byte,
+        }
+        rex = 0;
+    };
+    // In 32-bit code, C4/C5/62 are LES/LDS/BOUND unless ModRM says register.
+    let vex_like = matches!(op, 0xC4 | 0xC5 | 0x62) && (is64 || cur.peek()? >= 0xC0);
+
+    let (opcode, has_modrm, imm) = if vex_like {
+        let map = match op {
+            0xC5 => {
+                let payload = cur.byte()?;
+                rex = 0x40 | if payload & 0x80 == 0 { 0x04 } else { 0 };
+                1
+            }
+            0xC4 => {
+                let p0 = cur.byte()?;
+                let p1 = cur.byte()?;
+                rex = 0x40
+                    | ((!p0 >> 7 & 1) << 2)
+                    | ((!p0 >> 6 & 1) << 1)
+                    | (!p0 >> 5 & 1)
+                    | (p1 >> 4 & 0x08);
+                p0 & 0x1F
+            }
+            _ => {
+                let p0 = cur.byte()?;
+                let p1 = cur.byte()?;
+                cur.byte()?;
+                rex = 0x40
+                    | ((!p0 >> 7 & 1) << 2)
+                    | ((!p0 >> 6 & 1) << 1)
+                    | (!p0 >> 5 & 1)
+                    | (p1 >> 4 & 0x08);
+                p0 & 0x07
+            }
+        };
+        let byte = cur.byte()?;
+        match map {
+            1 => {
+                let (_, imm) = two_byte(byte);
+                (0x0F00 | byte as u32, byte != 0x77, imm)
+            }
+            2 => (0x0F_3800 | byte as u32, true, Imm::None),
+            3 => (0x0F_3A00 | byte as u32, true, Imm::Byte),
+            _ => return None,
+        }
+    } else if op == 0x0F {
+        op = cur.byte()?;
+        match op {
+            0x38 => (0x0F_3800 | cur.byte()? as u32, true, Imm::None),
+            0x3A => (0x0F_3A00 | cur.byte()? as u32, true, Imm::Byte),
+            _ => {
+                let (has_modrm, imm) = two_byte(op);
+                (0x0F00 | op as u32, has_modrm, imm)
+            }
+        }
+    } else {
+        let (has_modrm, imm) = one_byte(op, is64)?;
+        (op as u32, has_modrm, imm)
+    };
+
+    let modrm = if has_modrm {
+        Some(decode_modrm(&mut cur, rex, is64, address_16)?)
+    } else {
+        None
+    };
+    let imm = match (opcode, modrm) {
+        (0xF6, Some(modrm)) if modrm.reg & 7 < 2 => Imm::Byte,
+        (0xF7, Some(modrm)) if modrm.reg & 7 < 2 => Imm::Z,
+        _ => imm,
+    };
+    let z = if operand_16 && !(is64 && matches!(opcode, 0xE8 | 0xE9 | 0x0F80..=0x0F8F)) {
+        2
+    } else {
+        4
+    };
+    let imm = match imm {
+        Imm::None => None,
+        Imm::Byte => Some(cur.signed(1)?),
+        Imm::Word => Some(cur.signed(2)?),
+        Imm::Z => Some(cur.signed(z)?),
+        Imm::WordByte => {
+            let word = cur.signed(2)?;
+            cur.signed(1)?;
+            Some(word)
+        }
+        Imm::ZWord => {
+            let offset = cur.signed(z)?;
+            cur.signed(2)?;
+            Some(offset)
+        }
+        Imm::V if rex & 0x08 != 0 => Some(cur.signed(8)?),
+        Imm::V => Some(cur.signed(z)?),
+        Imm::Offset => {
+            let size = match (is64, address_16) {
+                (true, false) => 8,
+                (true, true) | (false, false) => 4,
+                (false, true) => 2,
+            };
+            Some(cur.signed(size)?)
+        }
+    };
+    Some(Instruction {
+        len: cur.pos,
+        opcode,
+        rex: if is64 { rex } else { 0 },
+        modrm,
+        imm,
+    })
+}
+
+fn decode_modrm(cur: &mut Cursor, rex: u8, is64: bool, address_16: bool) -> Option<ModRm> {
+    let byte = cur.byte()?;
+    let mode = byte >> 6;
+    let reg = (byte >> 3 & 7) | (rex & 0x04) << 1;
+    let rm = byte & 7;
+    let rm_ext = rm | (rex & 0x01) << 3;
+    if mode == 3 {
+        return Some(ModRm {
+            mode,
+            reg,
+            rm: rm_ext,
+            mem: None,
+        });
+    }
+    if address_16 && !is64 {
+        // 16-bit addressing ([bx + si], [bp + disp], ...) is skipped over
+        // but not modeled: its base and index registers have no place in
+        // `Memory`, and leaving them out would read as an absolute address.
+        match (mode, rm) {
+            (0, 6) | (2, _) => cur.signed(2)?,
+            (1, _) => cur.signed(1)?,
+            _ => 0,
+        };
+        return Some(ModRm {
+            mode,
+            reg,
+            rm: rm_ext,
+            mem: None,
+        });
+    }
+    let mut mem = Memory {
+        base: Some(rm_ext),
+        index: None,
+        disp: 0,
+        rip_relative: false,
+    };
+    if rm == 4 {
+        let sib = cur.byte()?;
+        let index = (sib >> 3 & 7) | (rex & 0x02) << 2;
+        if index != 4 {
+            mem.index = Some((index, 1 << (sib >> 6)));
+        }
+        let base = sib & 7;
+        mem.base = Some(base | (rex & 0x01) << 3);
+        if base == 5 && mode == 0 {
+            mem.base = None;
+            mem.disp = cur.signed(4)?;
+        }
+    } else if rm == 5 && mode == 0 {
+        mem.base = None;
+        mem.rip_relative = is64;
+        mem.disp = cur.signed(4)?;
+    }
+    match mode {
+        1 => mem.disp = cur.signed(1)?,
+        2 => mem.disp = cur.signed(4)?,
+        _ => {}
+    }
+    Some(ModRm {
+        mode,
+        reg,
+        rm: rm_ext,
+        mem: Some(mem),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode64(code: &[u8]) -> Instruction {
+        decode(code, true).expect("valid x86-64 encoding")
+    }
+
+    fn decode32(code: &[u8]) -> Instruction {
+        decode(code, false).expect("valid x86 encoding")
+    }
+
+    #[test]
+    fn operand_size_prefix_shortens_immediates() {
+        // mov ax, 0x1234
+        let insn = decode32(&[0x66, 0xB8, 0x34, 0x12]);
+        assert_eq!(insn.len, 4);
+        assert_eq!(insn.imm, Some(0x1234));
+        // call rel32 keeps its 32-bit displacement in 64-bit code.
+        let insn = decode64(&[0x66, 0xE8, 0x10, 0x00, 0x00, 0x00]);
+        assert_eq!(insn.len, 6);
+        assert_eq!(insn.imm, Some(0x10));
+    }
+
+    #[test]
+    fn lock_and_segment_prefixes_are_skipped() {
+        // lock cmpxchg [edx], ecx
+        let insn = decode32(&[0xF0, 0x0F, 0xB1, 0x0A]);
+        assert_eq!((insn.len, insn.opcode), (4, 0x0FB1));
+        // mov eax, fs:[0x30]
+        let insn = decode32(&[0x64, 0xA1, 0x30, 0x00, 0x00, 0x00]);
+        assert_eq!(insn.len, 6);
+        assert_eq!(insn.memory_target(0), Some(0x30));
+    }
+
+    #[test]
+    fn rex_extends_registers_and_operand_size() {
+        // mov r8, [rsp + 0x28]
+        let insn = decode64(&[0x4C, 0x8B, 0x44, 0x24, 0x28]);
+        assert_eq!(insn.len, 5);
+        assert!(insn.wide());
+        let modrm = insn.modrm.unwrap();
+        assert_eq!(modrm.reg, 8);
+        // mov rax, imm64
+        let insn = decode64(&[0x48, 0xB8, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(insn.len, 10);
+        assert_eq!(insn.imm, Some(0x0807_0605_0403_0201));
+        // pop r8
+        let insn = decode64(&[0x41, 0x58]);
+        assert!(insn.writes(8));
+        assert!(!insn.writes(0));
+    }
+
+    #[test]
+    fn rex_only_counts_when_last() {
+        // REX.W followed by 66: the REX is ignored, so mov ax, cx.
+        let insn = decode64(&[0x48, 0x66, 0x8B, 0xC1]);
+        assert_eq!(insn.len, 4);
+        assert_eq!(insn.rex, 0);
+        assert!(!insn.wide());
+    }
+
+    #[test]
+    fn rex_is_inc_dec_in_32_bit_code() {
+        // inc eax, then mov eax, imm32
+        let insn = decode32(&[0x40, 0xB8, 1, 0, 0, 0]);
+        assert_eq!((insn.len, insn.opcode), (1, 0x40));
+    }
+
+    #[test]
+    fn sib_with_base_index_and_scale() {
+        // mov rax, [rbx + rsi * 8 + 0x10]
+        let insn = decode64(&[0x48, 0x8B, 0x44, 0xF3, 0x10]);
+        let mem = insn.modrm.unwrap().mem.unwrap();
+        assert_eq!(mem.base, Some(3));
+        assert_eq!(mem.index, Some((6, 8)));
+        assert_eq!(mem.disp, 0x10);
+        assert_eq!(insn.memory_target(0x1000), None);
+    }
+
+    #[test]
+    fn sib_without_base_takes_disp32() {
+        // mov eax, [ecx * 4 + 0x401000]
+        let insn = decode32(&[0x8B, 0x04, 0x8D, 0x00, 0x10, 0x40, 0x00]);
+        assert_eq!(insn.len, 7);
+        let mem = insn.modrm.unwrap().mem.unwrap();
+        assert_eq!(mem.base, None);
+        assert_eq!(mem.index, Some((1, 4)));
+        assert_eq!(mem.disp, 0x40_1000);
+        // The index makes it a table, not one absolute address.
+        assert_eq!(insn.memory_target(0), None);
+        // REX.X extends the index; index 4 alone means none.
+        let insn = decode64(&[0x42, 0x8B, 0x04, 0x25, 0x00, 0x10, 0x00, 0x00]);
+        let mem = insn.modrm.unwrap().mem.unwrap();
+        assert_eq!(mem.index, Some((12, 1)));
+        let insn = decode64(&[0x8B, 0x04, 0x25, 0x00, 0x10, 0x00, 0x00]);
+        assert_eq!(insn.modrm.unwrap().mem.unwrap().index, None);
+        assert_eq!(insn.memory_target(0), Some(0x1000));
+    }
+
+    #[test]
+    fn rip_relative_targets_follow_the_instruction() {
+        // mov rax, [rip + 0x10]
+        let insn = decode64(&[0x48, 0x8B, 0x05, 0x10, 0x00, 0x00, 0x00]);
+        assert_eq!(insn.len, 7);
+        assert!(insn.modrm.unwrap().mem.unwrap().rip_relative);
+        assert_eq!(insn.memory_target(0x1000), Some(0x1017));
+        // call [rip - 6]
+        let insn = decode64(&[0xFF, 0x15, 0xFA, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(insn.memory_target(0x2000), Some(0x2000));
+        // The same bytes in 32-bit code name an absolute address.
+        let insn = decode32(&[0xFF, 0x15, 0x00, 0x20, 0x40, 0x00]);
+        assert_eq!(insn.memory_target(0), Some(0x40_2000));
+    }
+
+    #[test]
+    fn moffs_size_follows_address_size() {
+        let insn = decode32(&[0xA1, 0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(insn.len, 5);
+        assert_eq!(insn.memory_target(0), Some(0x1234_5678));
+        let insn = decode64(&[0x48, 0xA1, 8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(insn.len, 10);
+        assert_eq!(insn.memory_target(0), Some(0x0102_0304_0506_0708));
+        let insn = decode64(&[0x67, 0xA3, 0x00, 0x10, 0x00, 0x00]);
+        assert_eq!(insn.len, 6);
+        let insn = decode32(&[0x67, 0xA1, 0x34, 0x12]);
+        assert_eq!(insn.len, 4);
+        assert_eq!(insn.memory_target(0), Some(0x1234));
+    }
+
+    #[test]
+    fn sixteen_bit_addressing_has_no_memory_target() {
+        // mov eax, [bp + 2]
+        let insn = decode32(&[0x67, 0x8B, 0x46, 0x02]);
+        assert_eq!(insn.len, 4);
+        assert!(insn.modrm.unwrap().mem.is_none());
+        assert_eq!(insn.memory_target(0), None);
+        // call [bx + si + 0x2000]
+        let insn = decode32(&[0x67, 0xFF, 0x90, 0x00, 0x20]);
+        assert_eq!(insn.len, 5);
+        assert_eq!(insn.memory_target(0), None);
+        // call [0x2000]: a bare disp16 is not modeled either.
+        let insn = decode32(&[0x67, 0xFF, 0x16, 0x00, 0x20]);
+        assert_eq!(insn.len, 5);
+        assert_eq!(insn.memory_target(0), None);
+    }
+
+    #[test]
+    fn vex_selects_its_map() {
+        // vzeroupper
+        let insn = decode64(&[0xC5, 0xF8, 0x77]);
+        assert_eq!((insn.len, insn.opcode), (3, 0x0F77));
+        assert!(insn.modrm.is_none());
+        // vbroadcastss xmm0, [rip + 0]
+        let insn = decode64(&[0xC4, 0xE2, 0x79, 0x18, 0x05, 0, 0, 0, 0]);
+        assert_eq!((insn.len, insn.opcode), (9, 0x0F_3818));
+        assert_eq!(insn.memory_target(0x1000), Some(0x1009));
+        // les eax, [ecx] in 32-bit code
+        let insn = decode32(&[0xC4, 0x01]);
+        assert_eq!((insn.len, insn.opcode), (2, 0xC4));
+    }
+
+    #[test]
+    fn test_immediates_depend_on_the_extension() {
+        assert_eq!(decode32(&[0xF6, 0xC1, 0x01]).len, 3);
+        assert_eq!(decode32(&[0xF7, 0xC1, 0x01, 0x00, 0x00, 0x00]).len, 6);
+        // not ecx
+        assert_eq!(decode32(&[0xF7, 0xD1]).len, 2);
+    }
+
+    #[test]
+    fn invalid_and_truncated_encodings() {
+        assert!(decode(&[0xE8, 0x00, 0x00], false).is_none());
+        assert!(decode(&[0x8B, 0x04], false).is_none());
+        // push es does not exist in 64-bit code.
+        assert!(decode(&[0x06], true).is_none());
+        assert!(decode(&[0x06], false).is_some());
+    }
+
+    #[test]
+    fn writes_follows_the_destination_operand() {
+        // xor eax, eax
+        assert!(decode64(&[0x31, 0xC0]).writes(0));
+        // mov eax, ecx writes rm, mov ecx, eax writes reg.
+        let insn = decode64(&[0x89, 0xC8]);
+        assert!(insn.writes(0) && !insn.writes(1));
+        let insn = decode64(&[0x8B, 0xC8]);
+        assert!(insn.writes(1) && !insn.writes(0));
+        // lea rdx, [rip + 0x10]
+        let insn = decode64(&[0x48, 0x8D, 0x15, 0x10, 0, 0, 0]);
+        assert!(insn.writes(2) && !insn.writes(0));
+        // Stores to memory write no register.
+        let insn = decode64(&[0x89, 0x44, 0x24, 0x20]);
+        assert!(!insn.writes(0) && !insn.writes(4));
+    }
+
+    #[test]
+    fn writes_maps_high_byte_registers() {
+        // mov ah, al writes RAX; with a REX prefix the same field is SPL.
+        let insn = decode64(&[0x88, 0xC4]);
+        assert!(insn.writes(0));
+        let insn = decode64(&[0x40, 0x88, 0xC4]);
+        assert!(!insn.writes(0) && insn.writes(4));
+    }
+
+    #[test]
+    fn writes_for_compares_calls_and_implicit_operands() {
+        // cmp eax, ecx; test eax, eax; push rax
+        for code in [&[0x39, 0xC8][..], &[0x85, 0xC0], &[0x50]] {
+            assert!(!decode64(code).writes(0));
+        }
+        // call rel32 and call rax leave registers to the convention.
+        assert!(!decode64(&[0xE8, 0, 0, 0, 0]).writes(0));
+        assert!(!decode64(&[0xFF, 0xD0]).writes(0));
+        // mul ecx writes RAX and RDX, not RCX.
+        let insn = decode64(&[0xF7, 0xE1]);
+        assert!(insn.writes(0) && insn.writes(2) && !insn.writes(1));
+        // cdq writes RDX; syscall clobbers R11.
+        assert!(decode64(&[0x99]).writes(2));
+        assert!(decode64(&[0x0F, 0x05]).writes(11));
+        assert!(!decode64(&[0x0F, 0x05]).writes(12));
+    }
+}